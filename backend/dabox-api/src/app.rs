@@ -1,8 +1,17 @@
+use crate::middlewares::auth::Authenticator;
 use crate::prelude::*;
+use crate::routes::archive::*;
+use crate::routes::audit::*;
+use crate::routes::batch::*;
 use crate::routes::directory::*;
+use crate::routes::events::*;
+use crate::routes::file::*;
+use crate::routes::search::*;
+use crate::routes::tag::*;
+use crate::routes::trash::*;
 
 use axum::routing::{delete, get, post, put};
-use axum::Router;
+use axum::{Extension, Router};
 use tokio::net::ToSocketAddrs;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
@@ -12,13 +21,46 @@ pub trait AppExt {
     fn enable_cors(self) -> Router;
 }
 
-pub fn create_app<R: DaRepository + 'static>(repository: Arc<R>) -> Router {
+pub fn create_app<R: DaRepository + 'static>(
+    repository: Arc<R>,
+    authenticator: Authenticator,
+    max_upload_size: MaxUploadSize,
+) -> Router {
     Router::new()
         .route("/directory/:id", get(get_directory::<R>))
+        .route("/directory", get(list_root_directories::<R>))
         .route("/directory", post(post_directory::<R>))
         .route("/directory/:id", put(put_directory::<R>))
         .route("/directory/:id", delete(delete_directory::<R>))
+        .route("/directory/:id/move", post(move_directory::<R>))
+        .route("/directory/:id/copy", post(copy_directory::<R>))
+        .route("/directory/:id/children", get(list_children::<R>))
+        .route("/directory/:id/ancestors", get(list_ancestors::<R>))
+        .route("/directory/:id/grants", get(list_grants::<R>))
+        .route("/directory/:id/grants/:grantee", put(put_grant::<R>))
+        .route("/directory/:id/grants/:grantee", delete(delete_grant::<R>))
+        .route("/directory/:id/tags/:tag", put(put_tag::<R>))
+        .route("/directory/:id/tags/:tag", delete(delete_tag::<R>))
+        .route("/batch", post(post_batch::<R>))
+        .route("/export", get(export_directories::<R>))
+        .route("/import", post(import_directories::<R>))
+        .route("/path/*path", get(get_directory_by_path::<R>))
+        .route("/search", get(search_directories::<R>))
+        .route("/tags", get(list_tags::<R>))
+        .route("/tags/directories", get(list_tagged_directories::<R>))
+        .route("/directory/:id/files", get(list_files::<R>))
+        .route("/directory/:id/files", post(post_file::<R>))
+        .route("/file/:id", get(get_file::<R>))
+        .route("/file/:id", delete(delete_file::<R>))
+        .route("/file/:id/content", get(get_file_content::<R>))
+        .route("/trash", get(list_trash::<R>))
+        .route("/trash/:id/restore", post(restore_directory::<R>))
+        .route("/trash/:id", delete(purge_directory::<R>))
+        .route("/audit", get(list_audit_events::<R>))
+        .route("/events", get(stream_events::<R>))
         .with_state(repository)
+        .layer(Extension(authenticator))
+        .layer(Extension(max_upload_size))
         .layer(
             TraceLayer::new_for_http().make_span_with(|request: &extract::Request<_>| {
                 let matched_path = request
@@ -39,7 +81,9 @@ impl AppExt for Router {
         let ret = CorsLayer::new()
             .allow_origin(Any)
             .allow_headers(Any)
-            .allow_methods(Any);
+            .allow_methods(Any)
+            // The frontend reads the versions of the directories to send them back in `If-Match`
+            .expose_headers([axum::http::header::ETAG]);
         self.layer(ret)
     }
 
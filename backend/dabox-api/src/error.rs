@@ -1,44 +1,252 @@
 use crate::prelude::*;
 
-use axum::http::StatusCode;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use dabox_core::error::DaError;
+use serde::Serialize;
 use thiserror::Error;
 
+/// Media type of the RFC 7807 problem details.
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("Internal server error")]
     InternalServerError,
-    #[error("Forbidden: {0}")]
-    Forbidden(String),
-    #[error("Not found")]
-    NotFound,
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    /// The uploaded content is larger than the maximum upload size (in bytes)
+    #[error("Payload too large (max: {0} bytes)")]
+    PayloadTooLarge(u64),
+    #[error(transparent)]
+    Repository(DaError),
+}
+
+/// An RFC 7807 problem details body.
+/// `code` is an extension member holding the stable machine-readable identifier of the error.
+#[derive(Debug, Serialize)]
+struct Problem {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    code: &'static str,
+    /// The index of the operation that failed, for the errors of a batch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operation: Option<usize>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Repository(err) => repository_status(err),
+        }
+    }
+
+    /// A stable machine-readable identifier of the error, see `DaError::code` for the repository errors.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InternalServerError => "internal_server_error",
+            Self::BadRequest(_) => "bad_request",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::PayloadTooLarge(_) => "payload_too_large",
+            Self::Repository(err) => err.code(),
+        }
+    }
+}
+
+fn repository_status(err: &DaError) -> StatusCode {
+    match err {
+        DaError::AccessDenied { .. } => StatusCode::FORBIDDEN,
+        DaError::DirectoryNotFound(_) => StatusCode::NOT_FOUND,
+        DaError::ParentNotFound(_) => StatusCode::NOT_FOUND,
+        DaError::DirectoryCycle(..) => StatusCode::CONFLICT,
+        DaError::DirectoryDepthLimitExceeded(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        DaError::InvalidCursor(_) => StatusCode::BAD_REQUEST,
+        DaError::InvalidName(_) => StatusCode::BAD_REQUEST,
+        DaError::PathNotFound(_) => StatusCode::NOT_FOUND,
+        DaError::AmbiguousPath(_) => StatusCode::CONFLICT,
+        DaError::NameConflict(_) => StatusCode::CONFLICT,
+        DaError::FileNotFound(_) => StatusCode::NOT_FOUND,
+        DaError::TrashEntryNotFound(_) => StatusCode::NOT_FOUND,
+        DaError::VersionMismatch { .. } => StatusCode::PRECONDITION_FAILED,
+        DaError::InvalidBatch(_) => StatusCode::BAD_REQUEST,
+        DaError::InvalidArchive(_) => StatusCode::BAD_REQUEST,
+        DaError::InvalidTag(_) => StatusCode::BAD_REQUEST,
+        // A batch fails the way its failed operation does
+        DaError::BatchOperationFailed { source, .. } => repository_status(source),
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn repository_detail(err: &DaError) -> String {
+    match err {
+        // The owner of the resource is not disclosed to the requester
+        DaError::AccessDenied { requested_by, .. } => {
+            format!("the user {requested_by} is not allowed to access the resource")
+        }
+        DaError::BatchOperationFailed { index, source } => {
+            format!(
+                "Operation {index} of the batch failed: {}",
+                repository_detail(source)
+            )
+        }
+        err => err.to_string(),
+    }
 }
 
 impl From<DaError> for ApiError {
     fn from(err: DaError) -> Self {
-        match err {
-            DaError::DirectoryNotFound(_) => Self::NotFound,
-            DaError::AccessDenied { requested_by, .. } => Self::Forbidden(format!(
-                "the user {requested_by} is not allowed to access the resource"
-            )),
-            _ => {
-                error!("Unexpected error: {err}");
-                Self::InternalServerError
-            }
+        let err = Self::Repository(err);
+        if err.status() == StatusCode::INTERNAL_SERVER_ERROR {
+            // Don't leak the details of unexpected errors to the client
+            error!("Unexpected error: {err}");
+            return Self::InternalServerError;
         }
+        err
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
     }
 }
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        match self {
-            Self::Forbidden(_) => (StatusCode::FORBIDDEN, "Forbidden").into_response(),
-            Self::NotFound => (StatusCode::NOT_FOUND, "Not found").into_response(),
-            Self::InternalServerError => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
-            }
+        let status = self.status();
+        let problem = Problem {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail: match &self {
+                Self::InternalServerError => "Internal server error".to_string(),
+                Self::BadRequest(detail) | Self::Unauthorized(detail) => detail.clone(),
+                Self::PayloadTooLarge(max) => format!("An upload holds at most {max} bytes"),
+                Self::Repository(err) => repository_detail(err),
+            },
+            code: self.code(),
+            operation: match &self {
+                Self::Repository(DaError::BatchOperationFailed { index, .. }) => Some(*index),
+                _ => None,
+            },
+        };
+        let mut response = (status, axum::Json(problem)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+        );
+        if let Self::Unauthorized(_) = self {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
         }
+        response
     }
 }
 
 pub type ApiResult<T> = Result<T, ApiError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn problem(err: ApiError) -> (Response, serde_json::Value) {
+        let (parts, body) = err.into_response().into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (
+            Response::from_parts(parts, ().into()),
+            serde_json::from_slice(&body).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_problem_details() {
+        let sid: DaDirectorySid = serde_json::from_str("3").unwrap();
+        let (response, body) = problem(DaError::DirectoryNotFound(sid).into()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            PROBLEM_CONTENT_TYPE
+        );
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "No directory with id 3 found",
+                "code": "directory_not_found",
+            })
+        );
+
+        // A missing parent is told apart from a missing directory
+        let (response, body) = problem(DaError::ParentNotFound(sid).into()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "parent_not_found");
+        assert_eq!(body["detail"], "No parent directory with id 3 found");
+
+        let (response, body) = problem(
+            DaError::AccessDenied {
+                requested_by: 2,
+                resource_owner: 1,
+            }
+            .into(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "access_denied");
+        assert!(!body["detail"].as_str().unwrap().contains('1'));
+
+        let (response, body) = problem(
+            DaError::VersionMismatch {
+                sid,
+                expected: 2,
+                actual: 4,
+            }
+            .into(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(body["code"], "version_mismatch");
+        assert!(body.get("operation").is_none());
+
+        // The errors of a batch are the ones of the failed operation, along with its index
+        let (response, body) = problem(
+            DaError::BatchOperationFailed {
+                index: 2,
+                source: Box::new(DaError::AccessDenied {
+                    requested_by: 2,
+                    resource_owner: 1,
+                }),
+            }
+            .into(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "access_denied");
+        assert_eq!(body["operation"], 2);
+        assert!(!body["detail"].as_str().unwrap().contains('1'));
+    }
+}
@@ -0,0 +1,75 @@
+//! Wrappers of the axum extractors whose rejections are turned into `ApiError`,
+//! so that malformed requests are answered with the same problem details as the other errors.
+use async_trait::async_trait;
+use axum::extract::{FromRequest, FromRequestParts};
+use axum::http::{header, request::Parts, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+#[derive(Debug, FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[derive(Debug, FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
+
+#[derive(Debug, FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
+
+/// A response sent with the `ETag` of a directory at the given version (ex: `"3"`).
+#[derive(Debug)]
+pub struct WithETag<T>(pub u64, pub T);
+
+impl<T: IntoResponse> IntoResponse for WithETag<T> {
+    fn into_response(self) -> Response {
+        // Quotes and digits are always valid in a header value
+        let etag = HeaderValue::try_from(format!("\"{}\"", self.0)).unwrap();
+        ([(header::ETAG, etag)], self.1).into_response()
+    }
+}
+
+/// The versions listed by the `If-Match` header of a request, as given by the `ETag` of a directory (ex: `"3", "4"`).
+/// None if the header is absent or `*` (any version). Weak tags (ex: `W/"3"`) and tags that are not a version never match,
+/// they are left out of the list.
+#[derive(Debug, Clone)]
+pub struct IfMatch(pub Option<Vec<u64>>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(header::IF_MATCH) else {
+            return Ok(Self(None));
+        };
+        let value = value.to_str().unwrap_or_default().trim();
+        if value == "*" {
+            return Ok(Self(None));
+        }
+        let mut versions = Vec::new();
+        for tag in value.split(',').map(str::trim) {
+            let (weak, tag) = match tag.strip_prefix("W/") {
+                Some(tag) => (true, tag),
+                None => (false, tag),
+            };
+            let tag = tag
+                .strip_prefix('"')
+                .and_then(|tag| tag.strip_suffix('"'))
+                .ok_or_else(|| ApiError::BadRequest(format!("Invalid If-Match header: {value}")))?;
+            if let (false, Ok(version)) = (weak, tag.parse()) {
+                versions.push(version);
+            }
+        }
+        Ok(Self(Some(versions)))
+    }
+}
@@ -1,5 +1,6 @@
 pub mod app;
 pub mod error;
+pub mod extract;
 pub mod middlewares;
 pub mod prelude;
 pub mod routes;
@@ -2,20 +2,22 @@
 ///
 ///
 /// # Actual implementation
-/// The actual implementation simply take the user id from the `X-EntityUid` header.
+/// The entity uid is taken from the bearer token of the `Authorization` header, verified by the `Authenticator`
+/// that `create_app` adds to the request extensions (see the `auth` module).
+/// In the insecure development mode it is simply taken from the `X-Entity-Uid` header.
 ///
 /// # What's next
-/// In the future, the entity uid must be securly retrieved from another source.
-/// A common implementation will be to use openid connect provider like keycloak, auth0, etc.
-///
-/// Doing it this way implies not many changes in the backend code:
-/// - the `ApiUser` struct must be updated to match the authenticated user data
-/// - the `FromRequestParts` implementation must be updated to retrieve the user id from the authentication token and validate it
+/// Tokens are verified against locally configured keys, the JWKS of an openid connect provider (keycloak, auth0, etc)
+/// can be used by exporting it to a file. Fetching and rotating the keys from the provider is not supported yet.
 ///
 /// The frontend of course will have to be updated accordingly as well (ex: send the token instead of the user id and perform authentication flow with the oidc provider)
 /// but this is beyond the scope of this middleware.
-use axum::{extract::FromRequestParts, http::request::Parts};
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
 
+use crate::middlewares::auth::Authenticator;
 use crate::prelude::*;
 
 #[derive(Debug, Clone)]
@@ -28,17 +30,37 @@ impl<S: Send + Sync> FromRequestParts<S> for ApiUser {
     type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let uid = parts
-            .headers
-            .get("X-Entity-Uid")
-            .ok_or_else(|| ApiError::Forbidden("No X-Entity-Uid header".to_string()))
-            .and_then(|value| {
-                value
-                    .to_str()
-                    .ok()
-                    .and_then(|value| value.parse().ok())
-                    .ok_or_else(|| ApiError::Forbidden("Invalid X-Entity-Uid header".to_string()))
-            })?;
+        let authenticator = parts.extensions.get::<Authenticator>().ok_or_else(|| {
+            error!("No authenticator configured");
+            ApiError::InternalServerError
+        })?;
+        let uid = match authenticator {
+            Authenticator::Jwt(authenticator) => {
+                let token = parts
+                    .headers
+                    .get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.split_once(' '))
+                    // Authentication schemes are case-insensitive (RFC 7235)
+                    .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+                    .map(|(_, token)| token)
+                    .ok_or_else(|| ApiError::Unauthorized("No bearer token".to_string()))?;
+                authenticator.authenticate(token.trim())?
+            }
+            Authenticator::InsecureHeader => parts
+                .headers
+                .get("X-Entity-Uid")
+                .ok_or_else(|| ApiError::Unauthorized("No X-Entity-Uid header".to_string()))
+                .and_then(|value| {
+                    value
+                        .to_str()
+                        .ok()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or_else(|| {
+                            ApiError::Unauthorized("Invalid X-Entity-Uid header".to_string())
+                        })
+                })?,
+        };
         Ok(ApiUser { uid })
     }
 }
@@ -1 +1,2 @@
 pub mod api_user;
+pub mod auth;
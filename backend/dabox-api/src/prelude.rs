@@ -2,9 +2,9 @@ pub use crate::error::*;
 
 pub use crate::middlewares::api_user::ApiUser;
 
+pub(crate) use crate::extract::{IfMatch, Json, Path, Query};
 pub(crate) use async_trait::async_trait;
-pub(crate) use axum::extract::{self, Path, State};
-pub(crate) use axum::Json;
+pub(crate) use axum::extract::{self, State};
 pub(crate) use dabox_core::prelude::*;
 pub(crate) use serde::Deserialize;
 pub(crate) use std::future::Future;
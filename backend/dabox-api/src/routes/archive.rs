@@ -0,0 +1,137 @@
+use crate::prelude::*;
+use crate::routes::file::is_length_limit_error;
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use dabox_core::da_archive::{export_archive, import_archive};
+
+/// Maximum size of an imported archive in bytes, the whole archive is imported at once.
+pub const MAX_ARCHIVE_SIZE: usize = 8 * 1024 * 1024;
+/// Media type of the archives serialized as NDJSON.
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// How an archive is serialized.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    #[default]
+    Json,
+    Ndjson,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// The exported directory (the whole tree of the user if none)
+    sid: Option<DaDirectorySid>,
+    #[serde(default)]
+    format: ArchiveFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    /// The directory the archive is imported into (the root of the tree of the user if none)
+    parent: Option<DaDirectorySid>,
+    #[serde(default)]
+    strategy: DaImportStrategy,
+}
+
+#[instrument(skip(repository))]
+pub async fn export_directories<R: DaRepository + 'static>(
+    Query(query): Query<ExportQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Response> {
+    let archive = export_archive(&**repository, user.uid(), query.sid).await?;
+    Ok(match query.format {
+        ArchiveFormat::Json => Json(archive).into_response(),
+        ArchiveFormat::Ndjson => (
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(NDJSON_CONTENT_TYPE),
+            )],
+            archive.to_ndjson(),
+        )
+            .into_response(),
+    })
+}
+
+/// Imports the archive of the request body, read as NDJSON if sent as `application/x-ndjson` and as JSON otherwise.
+#[instrument(skip(repository, headers, body))]
+pub async fn import_directories<R: DaRepository + 'static>(
+    Query(query): Query<ImportQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+    headers: HeaderMap,
+    body: Body,
+) -> ApiResult<Json<DaImportResult>> {
+    let body = axum::body::to_bytes(body, MAX_ARCHIVE_SIZE)
+        .await
+        .map_err(|err| {
+            if is_length_limit_error(&err) {
+                ApiError::PayloadTooLarge(MAX_ARCHIVE_SIZE as u64)
+            } else {
+                ApiError::BadRequest(format!("Failed to read the archive: {err}"))
+            }
+        })?;
+    let body = std::str::from_utf8(&body)
+        .map_err(|_| ApiError::BadRequest("The archive is not valid UTF-8".to_string()))?;
+    let is_ndjson = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(NDJSON_CONTENT_TYPE));
+    let archive = if is_ndjson {
+        DaArchive::from_ndjson(body)?
+    } else {
+        DaArchive::from_json(body)?
+    };
+    Ok(Json(
+        import_archive(
+            &**repository,
+            user.uid(),
+            &archive,
+            query.parent,
+            query.strategy,
+        )
+        .await?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::create_app;
+    use crate::middlewares::auth::Authenticator;
+    use crate::routes::file::MaxUploadSize;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_max_archive_size() {
+        let app = create_app(
+            Arc::new(MemRepository::new()),
+            Authenticator::InsecureHeader,
+            MaxUploadSize(8),
+        );
+        let import = |body: Body| {
+            Request::post("/import")
+                .header("X-Entity-Uid", "1")
+                .body(body)
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(import(Body::from(vec![b' '; MAX_ARCHIVE_SIZE + 1])))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // A body failing for another reason is not reported as too large
+        let chunks = futures::stream::iter([Err::<&str, _>(std::io::Error::other("reset"))]);
+        let response = app
+            .oneshot(import(Body::from_stream(chunks)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::prelude::*;
+use chrono::{DateTime, Utc};
+use dabox_core::pagination::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+
+#[derive(Debug, Deserialize)]
+pub struct ListAuditEventsQuery {
+    /// Only the events of this directory
+    sid: Option<DaDirectorySid>,
+    /// Only the events made at or after this time (RFC 3339)
+    since: Option<DateTime<Utc>>,
+    /// Only the events made before this time (RFC 3339)
+    until: Option<DateTime<Utc>>,
+    /// The `next_cursor` of the previous page
+    cursor: Option<DaCursor>,
+    /// Maximum number of events in the page
+    limit: Option<u32>,
+}
+
+#[instrument(skip(repository))]
+pub async fn list_audit_events<R: DaRepository + 'static>(
+    Query(query): Query<ListAuditEventsQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaPage<DaAuditEvent>>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    let filter = DaAuditFilter {
+        sid: query.sid,
+        since: query.since,
+        until: query.until,
+    };
+    Ok(Json(
+        repository
+            .list_audit_events(user.uid(), filter, query.cursor, limit)
+            .await?,
+    ))
+}
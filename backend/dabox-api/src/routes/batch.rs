@@ -0,0 +1,27 @@
+use crate::prelude::*;
+
+/// Maximum number of operations in a batch, the whole batch is applied at once.
+pub const MAX_BATCH_OPERATIONS: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct PostBatchRequest {
+    operations: Vec<DaBatchOperation>,
+}
+
+#[instrument(skip(repository))]
+pub async fn post_batch<R: DaRepository + 'static>(
+    user: ApiUser,
+    repository: State<Arc<R>>,
+    Json(request): Json<PostBatchRequest>,
+) -> ApiResult<Json<DaBatchResult>> {
+    if request.operations.len() > MAX_BATCH_OPERATIONS {
+        return Err(ApiError::BadRequest(format!(
+            "A batch holds at most {MAX_BATCH_OPERATIONS} operations"
+        )));
+    }
+    Ok(Json(
+        repository
+            .apply_batch(user.uid(), &request.operations)
+            .await?,
+    ))
+}
@@ -1,4 +1,34 @@
+use crate::extract::WithETag;
 use crate::prelude::*;
+use dabox_core::pagination::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+
+#[derive(Debug, Deserialize)]
+pub struct GetDirectoryQuery {
+    /// Number of descendant levels to load (all of them if not set)
+    depth: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListChildrenQuery {
+    /// The `next_cursor` of the previous page
+    cursor: Option<DaCursor>,
+    /// Maximum number of children in the page
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListRootDirectoriesQuery {
+    /// The field the directories are sorted by (`sid` if not set)
+    #[serde(default)]
+    sort: DaSortKey,
+    /// The direction of the sort (`asc` if not set)
+    #[serde(default)]
+    order: DaSortOrder,
+    /// The `next_cursor` of the previous page
+    cursor: Option<DaCursor>,
+    /// Maximum number of directories in the page
+    limit: Option<u32>,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct PostDirectoryRequest {
@@ -11,16 +41,76 @@ pub struct PutDirectoryRequest {
     name: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MoveDirectoryRequest {
+    parent: Option<DaDirectorySid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopyDirectoryRequest {
+    parent: Option<DaDirectorySid>,
+    /// Name of the copy (defaults to the name of the copied directory)
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutGrantRequest {
+    permission: DaPermission,
+}
+
 #[instrument(skip(repository))]
 pub async fn get_directory<R: DaRepository + 'static>(
     Path(directory_sid): Path<DaDirectorySid>,
+    Query(query): Query<GetDirectoryQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<WithETag<Json<DaDirectory>>> {
+    let directory = repository
+        .get_directory_with_depth(user.uid(), directory_sid, query.depth)
+        .await?;
+    Ok(WithETag(directory.version, Json(directory)))
+}
+
+/// Resolves a `/`-separated path of directory names from the root directories of the user, empty segments are ignored.
+#[instrument(skip(repository))]
+pub async fn get_directory_by_path<R: DaRepository + 'static>(
+    Path(path): Path<String>,
+    Query(query): Query<GetDirectoryQuery>,
     user: ApiUser,
     repository: State<Arc<R>>,
 ) -> ApiResult<Json<DaDirectory>> {
-    let directory = repository.get_directory(user.uid(), directory_sid).await?;
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let directory = repository
+        .resolve_path(user.uid(), &segments, query.depth)
+        .await?;
     Ok(Json(directory))
 }
 
+#[instrument(skip(repository))]
+pub async fn list_root_directories<R: DaRepository + 'static>(
+    Query(query): Query<ListRootDirectoriesQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaPage<DaDirectory>>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    Ok(Json(
+        repository
+            .list_root_directories(
+                user.uid(),
+                DaSort::new(query.sort, query.order),
+                query.cursor,
+                limit,
+            )
+            .await?,
+    ))
+}
+
 #[instrument(skip(repository))]
 pub async fn post_directory<R: DaRepository + 'static>(
     user: ApiUser,
@@ -34,15 +124,76 @@ pub async fn post_directory<R: DaRepository + 'static>(
     ))
 }
 
+/// The version the directory is expected at for the versions listed by `If-Match`: the one it is currently at
+/// (which the update checks again), a version mismatch if the directory is at none of them.
+async fn expected_version<R: DaRepository>(
+    repository: &R,
+    requested_by: EntityUid,
+    directory_sid: DaDirectorySid,
+    IfMatch(versions): IfMatch,
+) -> ApiResult<Option<u64>> {
+    let Some(versions) = versions else {
+        return Ok(None);
+    };
+    if let [version] = versions[..] {
+        return Ok(Some(version));
+    }
+    let actual = repository
+        .get_directory_with_depth(requested_by, directory_sid, Some(0))
+        .await?
+        .version;
+    if versions.contains(&actual) {
+        return Ok(Some(actual));
+    }
+    Err(DaError::VersionMismatch {
+        sid: directory_sid,
+        expected: versions.first().copied().unwrap_or_default(),
+        actual,
+    }
+    .into())
+}
+
 #[instrument(skip(repository))]
 pub async fn put_directory<R: DaRepository + 'static>(
     user: ApiUser,
     repository: State<Arc<R>>,
     Path(directory_sid): Path<DaDirectorySid>,
+    if_match: IfMatch,
     Json(request): Json<PutDirectoryRequest>,
+) -> ApiResult<WithETag<Json<DaDirectory>>> {
+    let expected_version =
+        expected_version(repository.as_ref(), user.uid(), directory_sid, if_match).await?;
+    repository
+        .rename_directory_if_version(user.uid(), directory_sid, &request.name, expected_version)
+        .await?;
+    let directory = repository.get_directory(user.uid(), directory_sid).await?;
+    Ok(WithETag(directory.version, Json(directory)))
+}
+
+#[instrument(skip(repository))]
+pub async fn delete_directory<R: DaRepository + 'static>(
+    Path(directory_sid): Path<DaDirectorySid>,
+    if_match: IfMatch,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<()> {
+    let expected_version =
+        expected_version(repository.as_ref(), user.uid(), directory_sid, if_match).await?;
+    repository
+        .delete_directory_if_version(user.uid(), directory_sid, expected_version)
+        .await?;
+    Ok(())
+}
+
+#[instrument(skip(repository))]
+pub async fn move_directory<R: DaRepository + 'static>(
+    user: ApiUser,
+    repository: State<Arc<R>>,
+    Path(directory_sid): Path<DaDirectorySid>,
+    Json(request): Json<MoveDirectoryRequest>,
 ) -> ApiResult<Json<DaDirectory>> {
     repository
-        .rename_directory(user.uid(), directory_sid, &request.name)
+        .move_directory(user.uid(), directory_sid, request.parent)
         .await?;
     Ok(Json(
         repository.get_directory(user.uid(), directory_sid).await?,
@@ -50,13 +201,152 @@ pub async fn put_directory<R: DaRepository + 'static>(
 }
 
 #[instrument(skip(repository))]
-pub async fn delete_directory<R: DaRepository + 'static>(
+pub async fn copy_directory<R: DaRepository + 'static>(
+    user: ApiUser,
+    repository: State<Arc<R>>,
+    Path(directory_sid): Path<DaDirectorySid>,
+    Json(request): Json<CopyDirectoryRequest>,
+) -> ApiResult<Json<DaDirectory>> {
+    Ok(Json(
+        repository
+            .copy_directory(
+                user.uid(),
+                directory_sid,
+                request.parent,
+                request.name.as_deref(),
+            )
+            .await?,
+    ))
+}
+
+#[instrument(skip(repository))]
+pub async fn list_children<R: DaRepository + 'static>(
+    Path(directory_sid): Path<DaDirectorySid>,
+    Query(query): Query<ListChildrenQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaPage<DaDirectory>>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    Ok(Json(
+        repository
+            .list_children(user.uid(), directory_sid, query.cursor, limit)
+            .await?,
+    ))
+}
+
+/// The ancestors of a directory from the root down, for breadcrumbs.
+#[instrument(skip(repository))]
+pub async fn list_ancestors<R: DaRepository + 'static>(
     Path(directory_sid): Path<DaDirectorySid>,
     user: ApiUser,
     repository: State<Arc<R>>,
+) -> ApiResult<Json<Vec<DaAncestor>>> {
+    Ok(Json(
+        repository.list_ancestors(user.uid(), directory_sid).await?,
+    ))
+}
+
+#[instrument(skip(repository))]
+pub async fn list_grants<R: DaRepository + 'static>(
+    Path(directory_sid): Path<DaDirectorySid>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<Vec<DaGrant>>> {
+    Ok(Json(
+        repository.list_grants(user.uid(), directory_sid).await?,
+    ))
+}
+
+#[instrument(skip(repository))]
+pub async fn put_grant<R: DaRepository + 'static>(
+    Path((directory_sid, grantee)): Path<(DaDirectorySid, EntityUid)>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+    Json(request): Json<PutGrantRequest>,
+) -> ApiResult<Json<DaGrant>> {
+    Ok(Json(
+        repository
+            .grant_access(user.uid(), directory_sid, grantee, request.permission)
+            .await?,
+    ))
+}
+
+#[instrument(skip(repository))]
+pub async fn delete_grant<R: DaRepository + 'static>(
+    Path((directory_sid, grantee)): Path<(DaDirectorySid, EntityUid)>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
 ) -> ApiResult<()> {
     repository
-        .delete_directory(user.uid(), directory_sid)
+        .revoke_access(user.uid(), directory_sid, grantee)
         .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::create_app;
+    use crate::middlewares::auth::Authenticator;
+    use crate::routes::file::MaxUploadSize;
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_if_match() {
+        let repository = Arc::new(MemRepository::new());
+        let dir = repository.create_directory(1, "dir", None).await.unwrap();
+        let app = create_app(
+            repository.clone(),
+            Authenticator::InsecureHeader,
+            MaxUploadSize::default(),
+        );
+        let rename = |if_match: &str| {
+            Request::put(format!("/directory/{}", dir.sid))
+                .header("X-Entity-Uid", "1")
+                .header(header::IF_MATCH, if_match)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"name": "renamed"}"#))
+                .unwrap()
+        };
+        let status = |if_match: String| {
+            let app = app.clone();
+            async move { app.oneshot(rename(&if_match)).await.unwrap().status() }
+        };
+        let version = dir.version;
+
+        // Weak tags and tags that are not a version never match
+        assert_eq!(
+            status(format!("W/\"{version}\"")).await,
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(
+            status("\"abc\"".to_string()).await,
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(
+            status(format!("\"{}\"", version + 1)).await,
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(status("3".to_string()).await, StatusCode::BAD_REQUEST);
+
+        // The directory matches any tag of the list
+        assert_eq!(
+            status(format!(
+                "W/\"{version}\", \"{}\", \"{version}\"",
+                version + 1
+            ))
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(format!("\"{version}\", \"{}\"", version + 7)).await,
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(status("*".to_string()).await, StatusCode::OK);
+    }
+}
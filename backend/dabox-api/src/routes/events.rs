@@ -0,0 +1,43 @@
+use crate::prelude::*;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Streams the changes of the directories the user can see as Server-Sent Events (one JSON `DaChangeEvent` per message,
+/// or `DaDeletedSid` for the deletion of a directory above the ones the user was granted access to).
+/// A subscriber that falls behind gets a `lagged` event with the number of missed changes instead of them,
+/// it should re-fetch the directories it displays.
+#[instrument(skip(repository))]
+pub async fn stream_events<R: DaRepository + 'static>(
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let uid = user.uid();
+    let receiver = repository.subscribe();
+    let stream = futures::stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            let change = match receiver.recv().await {
+                Ok(change) => change,
+                Err(RecvError::Lagged(missed)) => {
+                    let event = Event::default().event("lagged").data(missed.to_string());
+                    return Some((Ok(event), receiver));
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            // The grantees of the descendants of a deleted directory only get its sid
+            let data = if change.is_visible_to(uid) {
+                Event::default().json_data(&change)
+            } else if let Some(deleted) = change.deleted_sid_for(uid) {
+                Event::default().json_data(deleted)
+            } else {
+                continue;
+            };
+            match data {
+                Ok(event) => return Some((Ok(event), receiver)),
+                Err(err) => error!("failed to serialize change event: {err}"),
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,243 @@
+use crate::prelude::*;
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use dabox_core::pagination::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use futures::TryStreamExt;
+use http_body_util::{LengthLimitError, Limited};
+
+/// Media type of the files uploaded without a `Content-Type` header.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+/// Maximum size of an uploaded file in bytes when none is configured.
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Maximum size of an uploaded file in bytes, added to the request extensions by `create_app`.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxUploadSize(pub u64);
+
+impl Default for MaxUploadSize {
+    fn default() -> Self {
+        Self(DEFAULT_MAX_UPLOAD_SIZE)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListFilesQuery {
+    /// The `next_cursor` of the previous page
+    cursor: Option<DaCursor>,
+    /// Maximum number of files in the page
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostFileQuery {
+    name: String,
+}
+
+#[instrument(skip(repository))]
+pub async fn list_files<R: DaRepository + 'static>(
+    Path(directory_sid): Path<DaDirectorySid>,
+    Query(query): Query<ListFilesQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaPage<DaFile>>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    Ok(Json(
+        repository
+            .list_files(user.uid(), directory_sid, query.cursor, limit)
+            .await?,
+    ))
+}
+
+/// Uploads the raw request body as a new file, the body is streamed to the blob store without being buffered.
+/// Bodies larger than the `MaxUploadSize` are rejected with `413 Payload Too Large`, up front if they announce their length.
+#[instrument(skip(repository, headers, body))]
+pub async fn post_file<R: DaRepository + 'static>(
+    Path(directory_sid): Path<DaDirectorySid>,
+    Query(query): Query<PostFileQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+    Extension(MaxUploadSize(max_upload_size)): Extension<MaxUploadSize>,
+    headers: HeaderMap,
+    body: Body,
+) -> ApiResult<Json<DaFile>> {
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > max_upload_size) {
+        return Err(ApiError::PayloadTooLarge(max_upload_size));
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or(DEFAULT_CONTENT_TYPE);
+    // The body errors are turned into I/O errors the blob store hands back, tagged so that they can be told apart below
+    // from the failures of the blob store itself
+    let limited = Limited::new(body, usize::try_from(max_upload_size).unwrap_or(usize::MAX));
+    let content: DaByteStream = Box::pin(Body::new(limited).into_data_stream().map_err(|err| {
+        if is_length_limit_error(&err) {
+            std::io::Error::new(std::io::ErrorKind::FileTooLarge, err)
+        } else {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+        }
+    }));
+    let file = repository
+        .create_file(
+            user.uid(),
+            directory_sid,
+            &query.name,
+            content_type,
+            content,
+        )
+        .await
+        .map_err(|err| match err {
+            DaError::BlobStore(err) if err.kind() == std::io::ErrorKind::FileTooLarge => {
+                ApiError::PayloadTooLarge(max_upload_size)
+            }
+            DaError::BlobStore(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                ApiError::BadRequest(format!("Failed to read the file: {err}"))
+            }
+            err => err.into(),
+        })?;
+    Ok(Json(file))
+}
+
+/// Whether the body stream failed because it exceeded the limit of `Limited`.
+pub(crate) fn is_length_limit_error(err: &axum::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        if err.is::<LengthLimitError>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+#[instrument(skip(repository))]
+pub async fn get_file<R: DaRepository + 'static>(
+    Path(file_sid): Path<DaFileSid>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaFile>> {
+    Ok(Json(repository.get_file(user.uid(), file_sid).await?))
+}
+
+/// Streams the content of a file with its media type.
+#[instrument(skip(repository))]
+pub async fn get_file_content<R: DaRepository + 'static>(
+    Path(file_sid): Path<DaFileSid>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Response> {
+    let (file, content) = repository.read_file(user.uid(), file_sid).await?;
+    let content_type = HeaderValue::from_str(&file.content_type)
+        .unwrap_or(HeaderValue::from_static(DEFAULT_CONTENT_TYPE));
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_LENGTH, HeaderValue::from(file.size)),
+        ],
+        Body::from_stream(content),
+    )
+        .into_response())
+}
+
+#[instrument(skip(repository))]
+pub async fn delete_file<R: DaRepository + 'static>(
+    Path(file_sid): Path<DaFileSid>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<()> {
+    repository.delete_file(user.uid(), file_sid).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::create_app;
+    use crate::middlewares::auth::Authenticator;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_max_upload_size() {
+        let repository =
+            Arc::new(MemRepository::new().with_blob_store(Arc::new(MemBlobStore::default())));
+        let dir = repository
+            .create_directory(1, "uploads", None)
+            .await
+            .unwrap();
+        let app = create_app(
+            repository.clone(),
+            Authenticator::InsecureHeader,
+            MaxUploadSize(8),
+        );
+        let upload = |name: &str, body: Body| {
+            Request::post(format!("/directory/{}/files?name={name}", dir.sid))
+                .header("X-Entity-Uid", "1")
+                .body(body)
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(upload("small", Body::from("12345678")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Announced by `Content-Length`
+        let response = app
+            .clone()
+            .oneshot(upload("large", Body::from("123456789")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Streamed without a length, the partial file is not kept
+        let chunks = futures::stream::iter(["12345", "6789"].map(Ok::<_, std::io::Error>));
+        let response = app
+            .oneshot(upload("streamed", Body::from_stream(chunks)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let files = repository.list_files(1, dir.sid, None, 10).await.unwrap();
+        assert_eq!(files.items.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_upload() {
+        let repository =
+            Arc::new(MemRepository::new().with_blob_store(Arc::new(MemBlobStore::default())));
+        let dir = repository
+            .create_directory(1, "uploads", None)
+            .await
+            .unwrap();
+        let app = create_app(
+            repository.clone(),
+            Authenticator::InsecureHeader,
+            MaxUploadSize::default(),
+        );
+
+        // The client goes away partway, the partial file is not kept
+        let chunks = futures::stream::iter([
+            Ok("1234"),
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset)),
+        ]);
+        let request = Request::post(format!("/directory/{}/files?name=broken", dir.sid))
+            .header("X-Entity-Uid", "1")
+            .body(Body::from_stream(chunks))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let files = repository.list_files(1, dir.sid, None, 10).await.unwrap();
+        assert!(files.items.is_empty());
+    }
+}
@@ -1 +1,9 @@
+pub mod archive;
+pub mod audit;
+pub mod batch;
 pub mod directory;
+pub mod events;
+pub mod file;
+pub mod search;
+pub mod tag;
+pub mod trash;
@@ -0,0 +1,41 @@
+use crate::prelude::*;
+use dabox_core::pagination::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    /// The searched text
+    q: String,
+    #[serde(default)]
+    mode: DaSearchMode,
+    /// Only the descendants of this directory
+    scope: Option<DaDirectorySid>,
+    /// The `next_cursor` of the previous page
+    cursor: Option<DaCursor>,
+    /// Maximum number of results in the page
+    limit: Option<u32>,
+}
+
+#[instrument(skip(repository))]
+pub async fn search_directories<R: DaRepository + 'static>(
+    Query(query): Query<SearchQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaPage<DaSearchResult>>> {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::BadRequest("The search text is empty".to_string()));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    let search = DaSearchQuery {
+        text: query.q,
+        mode: query.mode,
+        scope: query.scope,
+    };
+    Ok(Json(
+        repository
+            .search_directories(user.uid(), search, query.cursor, limit)
+            .await?,
+    ))
+}
@@ -0,0 +1,84 @@
+use crate::prelude::*;
+use dabox_core::pagination::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+
+/// Maximum number of tags in a listing of tagged directories.
+pub const MAX_QUERY_TAGS: usize = 32;
+
+#[derive(Debug, Deserialize)]
+pub struct TaggedDirectoriesQuery {
+    /// The tags separated by commas
+    tags: String,
+    #[serde(default, rename = "match")]
+    mode: DaTagMatch,
+    /// The `next_cursor` of the previous page
+    cursor: Option<DaCursor>,
+    /// Maximum number of directories in the page
+    limit: Option<u32>,
+}
+
+#[instrument(skip(repository))]
+pub async fn put_tag<R: DaRepository + 'static>(
+    Path((directory_sid, tag)): Path<(DaDirectorySid, String)>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<Vec<String>>> {
+    Ok(Json(
+        repository.add_tag(user.uid(), directory_sid, &tag).await?,
+    ))
+}
+
+#[instrument(skip(repository))]
+pub async fn delete_tag<R: DaRepository + 'static>(
+    Path((directory_sid, tag)): Path<(DaDirectorySid, String)>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<Vec<String>>> {
+    Ok(Json(
+        repository
+            .remove_tag(user.uid(), directory_sid, &tag)
+            .await?,
+    ))
+}
+
+#[instrument(skip(repository))]
+pub async fn list_tags<R: DaRepository + 'static>(
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<Vec<DaTagCount>>> {
+    Ok(Json(repository.list_tags(user.uid()).await?))
+}
+
+#[instrument(skip(repository))]
+pub async fn list_tagged_directories<R: DaRepository + 'static>(
+    Query(query): Query<TaggedDirectoriesQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaPage<DaDirectory>>> {
+    let tags: Vec<&str> = query
+        .tags
+        .split(',')
+        .filter(|tag| !tag.trim().is_empty())
+        .collect();
+    if tags.is_empty() {
+        return Err(ApiError::BadRequest("No tag was given".to_string()));
+    }
+    if tags.len() > MAX_QUERY_TAGS {
+        return Err(ApiError::BadRequest(format!(
+            "At most {MAX_QUERY_TAGS} tags can be combined"
+        )));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    Ok(Json(
+        repository
+            .list_tagged_directories(
+                user.uid(),
+                DaTagQuery::new(tags, query.mode),
+                query.cursor,
+                limit,
+            )
+            .await?,
+    ))
+}
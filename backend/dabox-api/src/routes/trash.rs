@@ -0,0 +1,52 @@
+use crate::prelude::*;
+use dabox_core::pagination::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+
+#[derive(Debug, Deserialize)]
+pub struct ListTrashQuery {
+    /// The `next_cursor` of the previous page
+    cursor: Option<DaCursor>,
+    /// Maximum number of entries in the page
+    limit: Option<u32>,
+}
+
+#[instrument(skip(repository))]
+pub async fn list_trash<R: DaRepository + 'static>(
+    Query(query): Query<ListTrashQuery>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaPage<DaTrashEntry>>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    Ok(Json(
+        repository
+            .list_trash(user.uid(), query.cursor, limit)
+            .await?,
+    ))
+}
+
+#[instrument(skip(repository))]
+pub async fn restore_directory<R: DaRepository + 'static>(
+    Path(directory_sid): Path<DaDirectorySid>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<Json<DaDirectory>> {
+    Ok(Json(
+        repository
+            .restore_directory(user.uid(), directory_sid)
+            .await?,
+    ))
+}
+
+#[instrument(skip(repository))]
+pub async fn purge_directory<R: DaRepository + 'static>(
+    Path(directory_sid): Path<DaDirectorySid>,
+    user: ApiUser,
+    repository: State<Arc<R>>,
+) -> ApiResult<()> {
+    repository
+        .purge_directory(user.uid(), directory_sid)
+        .await?;
+    Ok(())
+}
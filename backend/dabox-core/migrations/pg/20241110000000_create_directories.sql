@@ -0,0 +1,16 @@
+-- Directories are partitioned by owner, a sid is only meaningful within its owner's tree.
+CREATE SEQUENCE directories_sid_seq;
+
+CREATE TABLE directories (
+    owner BIGINT NOT NULL,
+    sid BIGINT NOT NULL DEFAULT nextval('directories_sid_seq'),
+    name TEXT NOT NULL,
+    parent_sid BIGINT,
+    depth INTEGER NOT NULL,
+    PRIMARY KEY (owner, sid),
+    FOREIGN KEY (owner, parent_sid) REFERENCES directories (owner, sid) ON DELETE CASCADE
+);
+
+ALTER SEQUENCE directories_sid_seq OWNED BY directories.sid;
+
+CREATE INDEX directories_parent_idx ON directories (owner, parent_sid);
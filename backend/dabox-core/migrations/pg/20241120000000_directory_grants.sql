@@ -0,0 +1,12 @@
+-- Sids come from a global sequence, they identify a directory without its owner (e.g. when it is shared).
+CREATE UNIQUE INDEX directories_sid_idx ON directories (sid);
+
+-- Permissions granted on a directory (and its descendants) to entities other than its owner.
+CREATE TABLE directory_grants (
+    owner BIGINT NOT NULL,
+    sid BIGINT NOT NULL,
+    grantee BIGINT NOT NULL,
+    permission TEXT NOT NULL CHECK (permission IN ('read', 'write')),
+    PRIMARY KEY (owner, sid, grantee),
+    FOREIGN KEY (owner, sid) REFERENCES directories (owner, sid) ON DELETE CASCADE
+);
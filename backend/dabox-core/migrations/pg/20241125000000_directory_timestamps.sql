@@ -0,0 +1,9 @@
+-- Creation and modification metadata of the directories.
+-- Existing directories are considered created now by their owner.
+ALTER TABLE directories
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by BIGINT;
+
+UPDATE directories SET created_by = owner;
+ALTER TABLE directories ALTER COLUMN created_by SET NOT NULL;
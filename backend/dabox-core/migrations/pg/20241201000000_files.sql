@@ -0,0 +1,16 @@
+-- Files stored in the directories, their content lives in the blob store under `blob_key`.
+-- Files are removed with their directory, the blob store is cleaned up by the repository.
+CREATE TABLE files (
+    sid BIGSERIAL PRIMARY KEY,
+    directory_sid BIGINT NOT NULL REFERENCES directories (sid) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    content_type TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    blob_key TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    created_by BIGINT NOT NULL
+);
+
+CREATE INDEX files_directory_idx ON files (directory_sid, sid);
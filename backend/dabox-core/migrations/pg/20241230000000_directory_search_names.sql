@@ -0,0 +1,3 @@
+-- The names of the directories as compared by the searches (see `fold_name`), written by the repository with the names.
+-- The names of the existing directories are folded by `migrate`.
+ALTER TABLE directories ADD COLUMN search_name TEXT;
@@ -0,0 +1,4 @@
+-- The searches filter the directories by folded name first, the trigram index serves their `LIKE` patterns
+-- including the ones that start with a wildcard, and the `%` operator of the fuzzy searches.
+-- The `pg_trgm` extension must be installed beforehand: creating it requires rights the server's role usually lacks.
+CREATE INDEX directories_search_name_idx ON directories USING GIN (search_name gin_trgm_ops);
@@ -0,0 +1,18 @@
+-- Directories are partitioned by owner, a sid is only meaningful within its owner's tree.
+CREATE TABLE directories (
+    owner INTEGER NOT NULL,
+    sid INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    parent_sid INTEGER,
+    depth INTEGER NOT NULL,
+    PRIMARY KEY (owner, sid),
+    FOREIGN KEY (owner, parent_sid) REFERENCES directories (owner, sid) ON DELETE CASCADE
+);
+
+CREATE INDEX directories_parent_idx ON directories (owner, parent_sid);
+
+-- Per owner sid sequence (SQLite can't auto-increment a composite primary key).
+CREATE TABLE directory_sid_counters (
+    owner INTEGER PRIMARY KEY NOT NULL,
+    next_sid INTEGER NOT NULL
+);
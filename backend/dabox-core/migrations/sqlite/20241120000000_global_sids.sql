@@ -0,0 +1,38 @@
+-- Sids become unique across owners so that a directory can be addressed (and shared) by its sid alone.
+-- Existing directories are renumbered since their sids were only unique for a given owner.
+CREATE TABLE directory_sid_map AS
+    SELECT owner, sid AS old_sid, ROW_NUMBER() OVER (ORDER BY owner, sid) - 1 AS new_sid
+    FROM directories;
+
+CREATE TABLE directories_new (
+    owner INTEGER NOT NULL,
+    sid INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    parent_sid INTEGER,
+    depth INTEGER NOT NULL,
+    PRIMARY KEY (owner, sid),
+    FOREIGN KEY (owner, parent_sid) REFERENCES directories_new (owner, sid) ON DELETE CASCADE
+);
+
+-- Parents are inserted before their children to satisfy the foreign key
+INSERT INTO directories_new (owner, sid, name, parent_sid, depth)
+SELECT d.owner, m.new_sid, d.name, p.new_sid, d.depth
+FROM directories d
+JOIN directory_sid_map m ON m.owner = d.owner AND m.old_sid = d.sid
+LEFT JOIN directory_sid_map p ON p.owner = d.owner AND p.old_sid = d.parent_sid
+ORDER BY d.depth;
+
+DROP TABLE directories;
+ALTER TABLE directories_new RENAME TO directories;
+CREATE INDEX directories_parent_idx ON directories (owner, parent_sid);
+CREATE UNIQUE INDEX directories_sid_idx ON directories (sid);
+
+DROP TABLE directory_sid_map;
+DROP TABLE directory_sid_counters;
+
+-- Single row sequence of the global sids
+CREATE TABLE directory_sid_sequence (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+    next_sid INTEGER NOT NULL
+);
+INSERT INTO directory_sid_sequence (id, next_sid) SELECT 0, COALESCE(MAX(sid) + 1, 0) FROM directories;
@@ -0,0 +1,9 @@
+-- Permissions granted on a directory (and its descendants) to entities other than its owner.
+CREATE TABLE directory_grants (
+    owner INTEGER NOT NULL,
+    sid INTEGER NOT NULL,
+    grantee INTEGER NOT NULL,
+    permission TEXT NOT NULL CHECK (permission IN ('read', 'write')),
+    PRIMARY KEY (owner, sid, grantee),
+    FOREIGN KEY (owner, sid) REFERENCES directories (owner, sid) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- Creation and modification metadata of the directories (RFC 3339 strings).
+-- Existing directories are considered created now by their owner.
+-- SQLite doesn't allow non-constant defaults on added columns, the placeholders are replaced right away.
+ALTER TABLE directories ADD COLUMN created_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z';
+ALTER TABLE directories ADD COLUMN updated_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z';
+ALTER TABLE directories ADD COLUMN created_by INTEGER NOT NULL DEFAULT 0;
+
+UPDATE directories SET
+    created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    created_by = owner;
@@ -0,0 +1,16 @@
+-- Files stored in the directories, their content lives in the blob store under `blob_key`.
+-- Files are removed with their directory, the blob store is cleaned up by the repository.
+CREATE TABLE files (
+    sid INTEGER PRIMARY KEY AUTOINCREMENT,
+    directory_sid INTEGER NOT NULL REFERENCES directories (sid) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    blob_key TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    created_by INTEGER NOT NULL
+);
+
+CREATE INDEX files_directory_idx ON files (directory_sid, sid);
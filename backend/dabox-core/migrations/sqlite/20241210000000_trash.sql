@@ -0,0 +1,16 @@
+-- Deleted directories are kept in the trash of their owner until they are restored or purged.
+-- Every directory of a deleted subtree is flagged as trashed, the root of the subtree is detached from its parent
+-- and its original parent is kept in `trash` to restore it.
+ALTER TABLE directories ADD COLUMN trashed BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE trash (
+    owner INTEGER NOT NULL,
+    sid INTEGER NOT NULL,
+    parent_sid INTEGER,
+    deleted_at TEXT NOT NULL,
+    deleted_by INTEGER NOT NULL,
+    PRIMARY KEY (owner, sid),
+    FOREIGN KEY (owner, sid) REFERENCES directories (owner, sid) ON DELETE CASCADE
+);
+
+CREATE INDEX trash_deleted_at_idx ON trash (deleted_at);
@@ -0,0 +1,17 @@
+-- Every mutating operation is recorded in the audit log of the owner of the directory it was made on.
+-- Events outlive the directories they refer to, so the log has no foreign key to `directories`.
+-- The old and new values are JSON documents stored as text.
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    owner INTEGER NOT NULL,
+    entity INTEGER,
+    operation TEXT NOT NULL,
+    sid INTEGER NOT NULL,
+    file_sid INTEGER,
+    old_value TEXT,
+    new_value TEXT,
+    at TEXT NOT NULL
+);
+
+CREATE INDEX audit_log_owner_idx ON audit_log (owner, id);
+CREATE INDEX audit_log_owner_sid_idx ON audit_log (owner, sid, id);
@@ -0,0 +1,2 @@
+-- Every directory has a version, incremented with `updated_at`, so that clients can detect concurrent updates.
+ALTER TABLE directories ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
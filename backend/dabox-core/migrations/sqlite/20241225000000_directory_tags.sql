@@ -0,0 +1,9 @@
+-- Free-form tags attached to the directories, they follow a directory to the trash and are removed when it is purged.
+CREATE TABLE directory_tags (
+    owner INTEGER NOT NULL,
+    sid INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (owner, sid, tag),
+    FOREIGN KEY (owner, sid) REFERENCES directories (owner, sid) ON DELETE CASCADE
+);
+CREATE INDEX directory_tags_tag_idx ON directory_tags (owner, tag);
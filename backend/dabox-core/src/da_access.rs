@@ -0,0 +1,58 @@
+/// This module contains the access control types used to share directories between entities.
+/// The owner of a directory always has full access to it, other entities need a grant on the directory or on one of its ancestors.
+use crate::prelude::*;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Permission granted to an entity on a directory and all its descendants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DaPermission {
+    /// Read the directories
+    Read,
+    /// Read, create, rename, move and delete the directories (implies `Read`)
+    Write,
+}
+
+/// A grant of a permission on a directory (inherited by its descendants).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaGrant {
+    /// The directory the grant applies to
+    pub sid: DaDirectorySid,
+    /// The entity that is granted the permission
+    pub grantee: EntityUid,
+    /// The granted permission
+    pub permission: DaPermission,
+}
+
+impl DaPermission {
+    /// Whether this permission allows an operation that requires `required`.
+    pub fn allows(self, required: DaPermission) -> bool {
+        self >= required
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+        }
+    }
+}
+
+impl Display for DaPermission {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DaPermission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            _ => Err(format!("unknown permission `{s}`")),
+        }
+    }
+}
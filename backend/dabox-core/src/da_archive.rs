@@ -0,0 +1,546 @@
+/// This module contains the archives of directory trees, written by `export_archive` and re-created by `import_archive`.
+/// An archive holds the names and the hierarchy of the directories, not their files nor their grants.
+use crate::prelude::*;
+use std::collections::VecDeque;
+
+/// Version of the archive format written by this version, the only one that can be imported.
+pub const DA_ARCHIVE_VERSION: u32 = 1;
+/// Number of root directories listed per page while exporting or importing at the root of a tree.
+const ROOT_PAGE_LIMIT: u32 = 100;
+
+/// An exported subtree (or set of subtrees), serialized as a JSON document or as NDJSON
+/// (a header line with `version` and `exported_at`, then one line per directory).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaArchive {
+    /// Version of the format (`DA_ARCHIVE_VERSION`)
+    pub version: u32,
+    /// When the archive was exported
+    pub exported_at: DateTime<Utc>,
+    /// The exported directories, every directory comes after its parent
+    pub directories: Vec<DaArchiveEntry>,
+}
+
+/// A directory of an archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaArchiveEntry {
+    /// The sid of the directory when it was exported, only meaningful within the archive
+    pub sid: DaDirectorySid,
+    /// The parent directory in the archive (none for the exported directories themselves)
+    pub parent_sid: Option<DaDirectorySid>,
+    pub name: String,
+}
+
+/// The first line of an NDJSON archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct DaArchiveHeader {
+    version: u32,
+    exported_at: DateTime<Utc>,
+}
+
+/// What `import_archive` does with an imported directory named like an existing sibling
+/// (by the `SiblingNamePolicy` of the repository, or exactly if duplicates are allowed).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DaImportStrategy {
+    /// The existing directory is kept and the children of the imported one are imported into it
+    #[default]
+    Merge,
+    /// The imported directory is left out, along with its descendants
+    Skip,
+    /// The existing directory is deleted (moved to the trash) and replaced by the imported one
+    Overwrite,
+}
+
+/// The outcome of an import.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaImportResult {
+    /// The sids of the imported directories by their sid in the archive (the sid of the existing directory for the merged ones)
+    pub sids: BTreeMap<DaDirectorySid, DaDirectorySid>,
+    /// The sids in the archive of the skipped directories (not counting their descendants)
+    pub skipped: Vec<DaDirectorySid>,
+}
+
+/// Where the children of an archive directory are imported.
+enum ImportTarget {
+    /// Into an existing directory (none for the root of the tree), whose children may collide with them
+    Existing(Option<DaDirectorySid>),
+    /// Into a directory created by the import, under the temporary id of its batch operation
+    Created(String),
+}
+
+impl DaArchive {
+    /// An archive of the given directories, exported now.
+    pub fn new(directories: Vec<DaArchiveEntry>) -> Self {
+        Self {
+            version: DA_ARCHIVE_VERSION,
+            exported_at: Utc::now(),
+            directories,
+        }
+    }
+
+    /// Reads an archive serialized as a JSON document.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::InvalidArchive` if the document is malformed or not a valid archive (see `DaArchive::validate`).
+    pub fn from_json(json: &str) -> DaResult<Self> {
+        let archive: Self =
+            serde_json::from_str(json).map_err(|err| DaError::InvalidArchive(err.to_string()))?;
+        archive.validate()?;
+        Ok(archive)
+    }
+
+    /// Reads an archive serialized as NDJSON, empty lines are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::InvalidArchive` if a line is malformed or the archive is not valid (see `DaArchive::validate`).
+    pub fn from_ndjson(ndjson: &str) -> DaResult<Self> {
+        let mut lines = ndjson
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let invalid_line = |index: usize, err: serde_json::Error| {
+            DaError::InvalidArchive(format!("line {}: {err}", index + 1))
+        };
+        let (index, header) = lines
+            .next()
+            .ok_or_else(|| DaError::InvalidArchive("the archive is empty".to_string()))?;
+        let header: DaArchiveHeader =
+            serde_json::from_str(header).map_err(|err| invalid_line(index, err))?;
+        let directories = lines
+            .map(|(index, line)| serde_json::from_str(line).map_err(|err| invalid_line(index, err)))
+            .collect::<DaResult<_>>()?;
+        let archive = Self {
+            version: header.version,
+            exported_at: header.exported_at,
+            directories,
+        };
+        archive.validate()?;
+        Ok(archive)
+    }
+
+    /// Serializes the archive as NDJSON.
+    pub fn to_ndjson(&self) -> String {
+        let header = DaArchiveHeader {
+            version: self.version,
+            exported_at: self.exported_at,
+        };
+        let mut ndjson = serde_json::to_string(&header).expect("the header is serializable");
+        ndjson.push('\n');
+        for entry in &self.directories {
+            ndjson.push_str(&serde_json::to_string(entry).expect("the entries are serializable"));
+            ndjson.push('\n');
+        }
+        ndjson
+    }
+
+    /// Ensures that the archive can be imported.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::InvalidArchive` if the version is not supported, a sid appears twice
+    /// or a directory comes before its parent (or its parent is not in the archive).
+    pub fn validate(&self) -> DaResult<()> {
+        if self.version != DA_ARCHIVE_VERSION {
+            return Err(DaError::InvalidArchive(format!(
+                "unsupported version {}",
+                self.version
+            )));
+        }
+        let mut seen = BTreeSet::new();
+        for entry in &self.directories {
+            if let Some(parent_sid) = entry.parent_sid {
+                if !seen.contains(&parent_sid) {
+                    return Err(DaError::InvalidArchive(format!(
+                        "directory {} comes before its parent {parent_sid}",
+                        entry.sid
+                    )));
+                }
+            }
+            if !seen.insert(entry.sid) {
+                return Err(DaError::InvalidArchive(format!(
+                    "directory {} appears twice",
+                    entry.sid
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Exports a directory and its descendants, or every directory owned by `requested_by` if `sid` is none.
+///
+/// # Arguments
+///
+/// * `repository` - The repository the directories are read from.
+/// * `requested_by` - The Uid of the entity that is requesting the export (it must be able to read the directory).
+/// * `sid` - The exported directory (none to export the whole tree of `requested_by`).
+pub async fn export_archive<R: DaRepository>(
+    repository: &R,
+    requested_by: EntityUid,
+    sid: Option<DaDirectorySid>,
+) -> DaResult<DaArchive> {
+    let roots = match sid {
+        Some(sid) => vec![sid],
+        None => list_roots(repository, requested_by)
+            .await?
+            .into_iter()
+            .map(|root| root.sid)
+            .collect(),
+    };
+    let mut directories = Vec::new();
+    for root in roots {
+        let root = repository.get_directory(requested_by, root).await?;
+        flatten(&root, None, &mut directories);
+    }
+    Ok(DaArchive::new(directories))
+}
+
+/// Re-creates the directories of an archive under `parent` (or as root directories of `requested_by` if none),
+/// the directories get new sids, returned by their sid in the archive.
+/// The import is applied as a single batch: nothing is imported if one of the directories can't be created.
+///
+/// # Arguments
+///
+/// * `repository` - The repository the directories are created in.
+/// * `requested_by` - The Uid of the entity that is requesting the import (it must be able to write to `parent`).
+/// * `archive` - The imported archive.
+/// * `parent` - The directory the top directories of the archive are imported into.
+/// * `strategy` - What to do with the imported directories named like an existing sibling.
+///
+/// # Errors
+///
+/// Returns `DaError::InvalidArchive` if the archive is not valid,
+/// the errors of the batch are returned as `DaError::BatchOperationFailed` like `DaRepository::apply_batch` does.
+pub async fn import_archive<R: DaRepository>(
+    repository: &R,
+    requested_by: EntityUid,
+    archive: &DaArchive,
+    parent: Option<DaDirectorySid>,
+    strategy: DaImportStrategy,
+) -> DaResult<DaImportResult> {
+    archive.validate()?;
+    let config = repository.config();
+    let mut children: BTreeMap<Option<DaDirectorySid>, Vec<&DaArchiveEntry>> = BTreeMap::new();
+    for entry in &archive.directories {
+        children.entry(entry.parent_sid).or_default().push(entry);
+    }
+
+    let mut result = DaImportResult::default();
+    let mut operations = Vec::new();
+    let mut created = Vec::new();
+    let mut pending = VecDeque::from([(None, ImportTarget::Existing(parent))]);
+    while let Some((archive_sid, target)) = pending.pop_front() {
+        let Some(entries) = children.get(&archive_sid) else {
+            continue;
+        };
+        let (parent, mut siblings) = match target {
+            ImportTarget::Existing(Some(sid)) => (
+                Some(DaBatchRef::Sid(sid)),
+                repository
+                    .get_directory_with_depth(requested_by, sid, Some(1))
+                    .await?
+                    .children,
+            ),
+            ImportTarget::Existing(None) => (None, list_roots(repository, requested_by).await?),
+            ImportTarget::Created(temp_id) => (Some(DaBatchRef::TempId(temp_id)), Vec::new()),
+        };
+        for entry in entries {
+            let name = config.names.normalize(&entry.name)?;
+            let same_name = |sibling: &DaDirectory| {
+                sibling.name == name || config.sibling_names.conflicts(&sibling.name, &name)
+            };
+            match strategy {
+                DaImportStrategy::Merge => {
+                    if let Some(existing) = siblings.iter().find(|sibling| same_name(sibling)) {
+                        result.sids.insert(entry.sid, existing.sid);
+                        pending.push_back((
+                            Some(entry.sid),
+                            ImportTarget::Existing(Some(existing.sid)),
+                        ));
+                        continue;
+                    }
+                }
+                DaImportStrategy::Skip => {
+                    if siblings.iter().any(same_name) {
+                        result.skipped.push(entry.sid);
+                        continue;
+                    }
+                }
+                DaImportStrategy::Overwrite => {
+                    let (replaced, kept) = siblings.into_iter().partition(same_name);
+                    siblings = kept;
+                    operations.extend(replaced.into_iter().map(|existing: DaDirectory| {
+                        DaBatchOperation::Delete {
+                            sid: DaBatchRef::Sid(existing.sid),
+                        }
+                    }));
+                }
+            }
+            let temp_id = entry.sid.to_string();
+            operations.push(DaBatchOperation::Create {
+                temp_id: temp_id.clone(),
+                name,
+                parent: parent.clone(),
+            });
+            created.push(entry.sid);
+            pending.push_back((Some(entry.sid), ImportTarget::Created(temp_id)));
+        }
+    }
+
+    if !operations.is_empty() {
+        let batch = repository.apply_batch(requested_by, &operations).await?;
+        for sid in created {
+            result.sids.insert(sid, batch.created[&sid.to_string()]);
+        }
+    }
+    Ok(result)
+}
+
+/// Lists every root directory owned by `requested_by`, without their descendants.
+async fn list_roots<R: DaRepository>(
+    repository: &R,
+    requested_by: EntityUid,
+) -> DaResult<Vec<DaDirectory>> {
+    let mut roots = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = repository
+            .list_root_directories(requested_by, DaSort::default(), cursor, ROOT_PAGE_LIMIT)
+            .await?;
+        roots.extend(page.items);
+        match page.next_cursor {
+            Some(next_cursor) => cursor = Some(next_cursor),
+            None => return Ok(roots),
+        }
+    }
+}
+
+/// Appends a directory and its descendants to `entries`, parents first.
+fn flatten(
+    directory: &DaDirectory,
+    parent_sid: Option<DaDirectorySid>,
+    entries: &mut Vec<DaArchiveEntry>,
+) {
+    entries.push(DaArchiveEntry {
+        sid: directory.sid,
+        parent_sid,
+        name: directory.name.clone(),
+    });
+    for child in &directory.children {
+        flatten(child, Some(directory.sid), entries);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The names of the children of a directory, in creation order.
+    fn names(directory: &DaDirectory) -> Vec<&str> {
+        directory
+            .children
+            .iter()
+            .map(|child| child.name.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_export_import() {
+        let repo = MemRepository::new();
+        let owner = 1;
+        let docs = repo.create_directory(owner, "docs", None).await.unwrap();
+        let reports = repo
+            .create_directory(owner, "reports", Some(docs.sid))
+            .await
+            .unwrap();
+        repo.create_directory(owner, "2025", Some(reports.sid))
+            .await
+            .unwrap();
+        repo.create_directory(owner, "drafts", Some(docs.sid))
+            .await
+            .unwrap();
+        repo.create_directory(owner, "music", None).await.unwrap();
+
+        let archive = export_archive(&repo, owner, Some(docs.sid)).await.unwrap();
+        assert_eq!(archive.version, DA_ARCHIVE_VERSION);
+        let exported: Vec<_> = archive
+            .directories
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.parent_sid))
+            .collect();
+        assert_eq!(
+            exported,
+            vec![
+                ("docs", None),
+                ("reports", Some(docs.sid)),
+                ("2025", Some(reports.sid)),
+                ("drafts", Some(docs.sid)),
+            ]
+        );
+        let json = serde_json::to_string(&archive).unwrap();
+        assert_eq!(DaArchive::from_json(&json).unwrap(), archive);
+        let ndjson = archive.to_ndjson();
+        assert_eq!(ndjson.lines().count(), 5);
+        assert_eq!(DaArchive::from_ndjson(&ndjson).unwrap(), archive);
+        let bucket = export_archive(&repo, owner, None).await.unwrap();
+        assert_eq!(bucket.directories.len(), 5);
+
+        // Imported into another tree, the directories get new sids
+        let other = 2;
+        let target = repo.create_directory(other, "backup", None).await.unwrap();
+        let imported = import_archive(
+            &repo,
+            other,
+            &archive,
+            Some(target.sid),
+            DaImportStrategy::Merge,
+        )
+        .await
+        .unwrap();
+        assert_eq!(imported.sids.len(), 4);
+        assert!(imported.skipped.is_empty());
+        let copy = repo
+            .get_directory(other, imported.sids[&docs.sid])
+            .await
+            .unwrap();
+        assert_eq!(copy.parent_sid, Some(target.sid));
+        assert_eq!(names(&copy), vec!["reports", "drafts"]);
+        assert_eq!(names(&copy.children[0]), vec!["2025"]);
+        assert_ne!(copy.sid, docs.sid);
+
+        // Merged, the existing directories are reused and only the missing ones are created
+        repo.delete_directory(other, copy.children[1].sid)
+            .await
+            .unwrap();
+        let merged = import_archive(
+            &repo,
+            other,
+            &archive,
+            Some(target.sid),
+            DaImportStrategy::Merge,
+        )
+        .await
+        .unwrap();
+        assert_eq!(merged.sids[&docs.sid], copy.sid);
+        assert_eq!(merged.sids[&reports.sid], copy.children[0].sid);
+        let target_tree = repo.get_directory(other, target.sid).await.unwrap();
+        assert_eq!(names(&target_tree), vec!["docs"]);
+        assert_eq!(names(&target_tree.children[0]), vec!["reports", "drafts"]);
+
+        // Skipped, nothing is imported
+        let skipped = import_archive(
+            &repo,
+            other,
+            &archive,
+            Some(target.sid),
+            DaImportStrategy::Skip,
+        )
+        .await
+        .unwrap();
+        assert!(skipped.sids.is_empty());
+        assert_eq!(skipped.skipped, vec![docs.sid]);
+
+        // Overwritten, the existing directory goes to the trash
+        let overwritten = import_archive(
+            &repo,
+            other,
+            &archive,
+            Some(target.sid),
+            DaImportStrategy::Overwrite,
+        )
+        .await
+        .unwrap();
+        let target_tree = repo.get_directory(other, target.sid).await.unwrap();
+        assert_eq!(names(&target_tree), vec!["docs"]);
+        assert_eq!(target_tree.children[0].sid, overwritten.sids[&docs.sid]);
+        let trash = repo.list_trash(other, None, 10).await.unwrap();
+        assert!(trash.items.iter().any(|entry| entry.sid == copy.sid));
+
+        // At the root of a tree, the root directories are the siblings
+        let at_root = import_archive(&repo, owner, &bucket, None, DaImportStrategy::Skip)
+            .await
+            .unwrap();
+        assert_eq!(at_root.skipped.len(), 2);
+        assert!(at_root.sids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_import_errors() {
+        let repo = MemRepository::new().with_config(
+            DaRepositoryConfig::default().with_sibling_names(SiblingNamePolicy::CaseInsensitive),
+        );
+        let (owner, other) = (1, 2);
+        let root = repo.create_directory(owner, "Root", None).await.unwrap();
+        let entry = |sid: i64, parent_sid: Option<i64>, name: &str| DaArchiveEntry {
+            sid: DaDirectorySid(sid),
+            parent_sid: parent_sid.map(DaDirectorySid),
+            name: name.to_string(),
+        };
+
+        // Names collide the way the repository compares them
+        let archive = DaArchive::new(vec![entry(1, None, "root"), entry(2, Some(1), "a")]);
+        let merged = import_archive(&repo, owner, &archive, None, DaImportStrategy::Merge)
+            .await
+            .unwrap();
+        assert_eq!(merged.sids[&DaDirectorySid(1)], root.sid);
+
+        // The import is atomic
+        let archive = DaArchive::new(vec![entry(1, None, "x"), entry(2, Some(1), "a/b")]);
+        assert!(matches!(
+            import_archive(&repo, owner, &archive, None, DaImportStrategy::Merge).await,
+            Err(DaError::InvalidName(_))
+        ));
+        let archive = DaArchive::new(vec![entry(1, None, "x"), entry(2, None, "X")]);
+        assert!(matches!(
+            import_archive(&repo, owner, &archive, None, DaImportStrategy::Merge).await,
+            Err(DaError::BatchOperationFailed { index: 1, .. })
+        ));
+        assert!(matches!(
+            import_archive(
+                &repo,
+                other,
+                &archive,
+                Some(root.sid),
+                DaImportStrategy::Merge
+            )
+            .await,
+            Err(DaError::AccessDenied { .. })
+        ));
+        let roots = repo
+            .list_root_directories(owner, DaSort::default(), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(roots.items.len(), 1);
+
+        let invalid = [
+            DaArchive {
+                version: DA_ARCHIVE_VERSION + 1,
+                ..DaArchive::new(Vec::new())
+            },
+            DaArchive::new(vec![entry(2, Some(1), "a"), entry(1, None, "b")]),
+            DaArchive::new(vec![entry(1, None, "a"), entry(1, None, "b")]),
+        ];
+        for archive in invalid {
+            assert!(matches!(
+                archive.validate(),
+                Err(DaError::InvalidArchive(_))
+            ));
+        }
+        for ndjson in [
+            "",
+            "{}",
+            "{\"version\":1,\"exported_at\":\"2026-01-01T00:00:00Z\"}\n{",
+        ] {
+            assert!(matches!(
+                DaArchive::from_ndjson(ndjson),
+                Err(DaError::InvalidArchive(_))
+            ));
+        }
+        assert!(matches!(
+            DaArchive::from_json("[]"),
+            Err(DaError::InvalidArchive(_))
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+/// This module contains the audit log data structures.
+/// Every mutating operation of a repository is recorded in the audit log of the owner of the directory it applies to,
+/// with the values it changed.
+use crate::prelude::*;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A mutating operation of a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DaAuditOperation {
+    CreateDirectory,
+    RenameDirectory,
+    MoveDirectory,
+    CopyDirectory,
+    /// Moved to the trash
+    DeleteDirectory,
+    RestoreDirectory,
+    /// Permanently deleted from the trash
+    PurgeDirectory,
+    GrantAccess,
+    RevokeAccess,
+    TagDirectory,
+    UntagDirectory,
+    CreateFile,
+    DeleteFile,
+}
+
+/// An entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaAuditEvent {
+    /// Identifier of the event, increasing in the order the events were recorded
+    pub id: i64,
+    /// When the operation was made
+    pub at: DateTime<Utc>,
+    /// The entity that made the operation (none for the operations of the repository itself, e.g. purging the expired trash)
+    pub entity: Option<EntityUid>,
+    pub operation: DaAuditOperation,
+    /// The directory the operation applies to (the directory of the file for file operations, the copy for copies)
+    pub sid: DaDirectorySid,
+    /// The file the operation applies to, for file operations
+    pub file_sid: Option<DaFileSid>,
+    /// The values changed by the operation as they were before it (ex: `{"name": "old name"}` for a rename)
+    pub old_value: Option<serde_json::Value>,
+    /// The values changed by the operation as they are after it
+    pub new_value: Option<serde_json::Value>,
+}
+
+/// The criteria of an audit log listing, unset criteria match every event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DaAuditFilter {
+    /// Only the events of this directory
+    pub sid: Option<DaDirectorySid>,
+    /// Only the events made at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only the events made before this time
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// An operation to record, the backends complete it with its id and time once stored.
+#[derive(Debug, Clone)]
+pub(crate) struct DaAuditRecord {
+    pub entity: Option<EntityUid>,
+    pub operation: DaAuditOperation,
+    pub sid: DaDirectorySid,
+    pub file_sid: Option<DaFileSid>,
+    pub old_value: Option<serde_json::Value>,
+    pub new_value: Option<serde_json::Value>,
+}
+
+impl DaAuditOperation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::CreateDirectory => "create_directory",
+            Self::RenameDirectory => "rename_directory",
+            Self::MoveDirectory => "move_directory",
+            Self::CopyDirectory => "copy_directory",
+            Self::DeleteDirectory => "delete_directory",
+            Self::RestoreDirectory => "restore_directory",
+            Self::PurgeDirectory => "purge_directory",
+            Self::GrantAccess => "grant_access",
+            Self::RevokeAccess => "revoke_access",
+            Self::TagDirectory => "tag_directory",
+            Self::UntagDirectory => "untag_directory",
+            Self::CreateFile => "create_file",
+            Self::DeleteFile => "delete_file",
+        }
+    }
+}
+
+impl Display for DaAuditOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DaAuditOperation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| format!("unknown audit operation `{s}`"))
+    }
+}
+
+impl DaAuditFilter {
+    /// Whether an event matches every criterion of the filter.
+    pub fn matches(&self, event: &DaAuditEvent) -> bool {
+        self.sid.is_none_or(|sid| event.sid == sid)
+            && self.since.is_none_or(|since| event.at >= since)
+            && self.until.is_none_or(|until| event.at < until)
+    }
+}
+
+impl DaAuditRecord {
+    pub fn new(
+        entity: impl Into<Option<EntityUid>>,
+        operation: DaAuditOperation,
+        sid: DaDirectorySid,
+    ) -> Self {
+        Self {
+            entity: entity.into(),
+            operation,
+            sid,
+            file_sid: None,
+            old_value: None,
+            new_value: None,
+        }
+    }
+
+    pub fn file(mut self, file_sid: DaFileSid) -> Self {
+        self.file_sid = Some(file_sid);
+        self
+    }
+
+    /// Sets the values changed by the operation as they were before it.
+    pub fn before(mut self, value: serde_json::Value) -> Self {
+        self.old_value = Some(value);
+        self
+    }
+
+    /// Sets the values changed by the operation as they are after it.
+    pub fn after(mut self, value: serde_json::Value) -> Self {
+        self.new_value = Some(value);
+        self
+    }
+
+    pub fn into_event(self, id: i64, at: DateTime<Utc>) -> DaAuditEvent {
+        DaAuditEvent {
+            id,
+            at,
+            entity: self.entity,
+            operation: self.operation,
+            sid: self.sid,
+            file_sid: self.file_sid,
+            old_value: self.old_value,
+            new_value: self.new_value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operation_roundtrip() {
+        for operation in [
+            DaAuditOperation::CreateDirectory,
+            DaAuditOperation::PurgeDirectory,
+            DaAuditOperation::RevokeAccess,
+            DaAuditOperation::TagDirectory,
+            DaAuditOperation::DeleteFile,
+        ] {
+            assert_eq!(operation.as_str().parse(), Ok(operation));
+            assert_eq!(
+                serde_json::to_value(operation).unwrap(),
+                serde_json::Value::String(operation.to_string())
+            );
+        }
+        assert!("drop_table".parse::<DaAuditOperation>().is_err());
+    }
+}
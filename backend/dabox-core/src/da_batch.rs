@@ -0,0 +1,153 @@
+/// This module contains the batches of directory operations, applied all or none by `DaRepository::apply_batch`.
+/// The operations of a batch reference the directories created earlier in the same batch by the temporary ids the client gave them.
+use crate::prelude::*;
+
+/// A directory referenced by an operation of a batch.
+/// Serialized as the sid of an existing directory (ex: `12`) or as the temporary id of a directory created by the batch (ex: `"a"`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DaBatchRef {
+    Sid(DaDirectorySid),
+    TempId(String),
+}
+
+/// An operation of a batch, same as the `DaRepository` method of the same name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DaBatchOperation {
+    Create {
+        /// The id the next operations use to reference the created directory, unique within the batch
+        temp_id: String,
+        name: String,
+        parent: Option<DaBatchRef>,
+    },
+    Rename {
+        sid: DaBatchRef,
+        name: String,
+    },
+    Move {
+        sid: DaBatchRef,
+        parent: Option<DaBatchRef>,
+    },
+    Delete {
+        sid: DaBatchRef,
+    },
+}
+
+/// The outcome of an applied batch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaBatchResult {
+    /// The sids of the created directories, by temporary id
+    pub created: BTreeMap<String, DaDirectorySid>,
+}
+
+impl DaBatchOperation {
+    /// The directory the operation applies to (the parent for a creation), none for the creation of a root directory.
+    pub(crate) fn target(&self) -> Option<&DaBatchRef> {
+        match self {
+            Self::Create { parent, .. } => parent.as_ref(),
+            Self::Rename { sid, .. } | Self::Move { sid, .. } | Self::Delete { sid } => Some(sid),
+        }
+    }
+}
+
+impl DaBatchResult {
+    /// The sid of a referenced directory.
+    pub(crate) fn resolve(&self, reference: &DaBatchRef) -> DaResult<DaDirectorySid> {
+        match reference {
+            DaBatchRef::Sid(sid) => Ok(*sid),
+            DaBatchRef::TempId(temp_id) => {
+                self.created.get(temp_id).copied().ok_or_else(|| {
+                    DaError::InvalidBatch(format!("unknown temporary id {temp_id:?}"))
+                })
+            }
+        }
+    }
+
+    /// The sid of an optional referenced directory (ex: the parent of a moved directory).
+    pub(crate) fn resolve_opt(
+        &self,
+        reference: Option<&DaBatchRef>,
+    ) -> DaResult<Option<DaDirectorySid>> {
+        reference
+            .map(|reference| self.resolve(reference))
+            .transpose()
+    }
+
+    /// Ensures that no directory was created by the batch with the temporary id yet.
+    pub(crate) fn check_temp_id(&self, temp_id: &str) -> DaResult<()> {
+        if self.created.contains_key(temp_id) {
+            return Err(DaError::InvalidBatch(format!(
+                "duplicate temporary id {temp_id:?}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Ensures that an operation applies to the tree of the owner of the batch.
+pub(crate) fn check_batch_owner(batch_owner: EntityUid, owner: EntityUid) -> DaResult<()> {
+    if batch_owner != owner {
+        return Err(DaError::InvalidBatch(
+            "the operations apply to the trees of several owners".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operation_format() {
+        let operations: Vec<DaBatchOperation> = serde_json::from_value(serde_json::json!([
+            { "op": "create", "temp_id": "a", "name": "docs", "parent": 3 },
+            { "op": "create", "temp_id": "b", "name": "2024", "parent": "a" },
+            { "op": "rename", "sid": "b", "name": "2025" },
+            { "op": "move", "sid": 4, "parent": null },
+            { "op": "delete", "sid": 5 },
+        ]))
+        .unwrap();
+        let sid = |sid: i64| DaDirectorySid(sid);
+        assert_eq!(
+            operations,
+            vec![
+                DaBatchOperation::Create {
+                    temp_id: "a".to_string(),
+                    name: "docs".to_string(),
+                    parent: Some(DaBatchRef::Sid(sid(3))),
+                },
+                DaBatchOperation::Create {
+                    temp_id: "b".to_string(),
+                    name: "2024".to_string(),
+                    parent: Some(DaBatchRef::TempId("a".to_string())),
+                },
+                DaBatchOperation::Rename {
+                    sid: DaBatchRef::TempId("b".to_string()),
+                    name: "2025".to_string(),
+                },
+                DaBatchOperation::Move {
+                    sid: DaBatchRef::Sid(sid(4)),
+                    parent: None,
+                },
+                DaBatchOperation::Delete {
+                    sid: DaBatchRef::Sid(sid(5)),
+                },
+            ]
+        );
+
+        let mut result = DaBatchResult::default();
+        result.created.insert("a".to_string(), sid(7));
+        assert_eq!(
+            result.resolve(operations[1].target().unwrap()).unwrap(),
+            sid(7)
+        );
+        assert!(matches!(
+            result.resolve(&DaBatchRef::TempId("c".to_string())),
+            Err(DaError::InvalidBatch(_))
+        ));
+        assert!(result.check_temp_id("a").is_err());
+        assert!(result.check_temp_id("b").is_ok());
+    }
+}
@@ -0,0 +1,132 @@
+//! A blob store that keeps every content in its own file of a local directory.
+use std::path::{Path, PathBuf};
+
+use futures::future::BoxFuture;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+use super::{new_blob_key, BlobDigest, DaBlob, DaBlobStore, DaByteStream};
+use crate::prelude::*;
+
+/// A local-filesystem implementation of the `DaBlobStore` trait.
+#[derive(Debug, Clone)]
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    /// Opens the store rooted at `root`, the directory is created if missing.
+    pub async fn open(root: impl Into<PathBuf>) -> DaResult<Self> {
+        let root = root.into();
+        tokio::fs::create_dir_all(&root).await?;
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of the content stored under `key`.
+    /// Keys are generated by the store, anything else is rejected so that a key can't point outside of the root.
+    fn path(&self, key: &str) -> DaResult<PathBuf> {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid blob key {key:?}"),
+            )
+            .into());
+        }
+        Ok(self.root.join(key))
+    }
+
+    async fn write(&self, path: &Path, mut content: DaByteStream) -> DaResult<BlobDigest> {
+        let mut file = tokio::fs::File::create_new(path).await?;
+        let mut digest = BlobDigest::default();
+        while let Some(chunk) = content.next().await {
+            let chunk = chunk?;
+            digest.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.sync_all().await?;
+        Ok(digest)
+    }
+}
+
+impl DaBlobStore for LocalBlobStore {
+    fn put(&self, content: DaByteStream) -> BoxFuture<'_, DaResult<DaBlob>> {
+        Box::pin(async move {
+            let key = new_blob_key();
+            let path = self.path(&key)?;
+            match self.write(&path, content).await {
+                Ok(digest) => Ok(digest.finish(key)),
+                Err(err) => {
+                    // Don't leave a partial content behind
+                    let _ = tokio::fs::remove_file(&path).await;
+                    Err(err)
+                }
+            }
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, DaResult<DaByteStream>> {
+        Box::pin(async move {
+            let file = tokio::fs::File::open(self.path(key)?).await?;
+            let stream: DaByteStream = Box::pin(ReaderStream::new(file));
+            Ok(stream)
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, DaResult<()>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(key)?).await {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn test_local_blob_store() {
+        let root = std::env::temp_dir().join(format!("dabox-blobs-{}", new_blob_key()));
+        let store = LocalBlobStore::open(&root).await.unwrap();
+
+        let content: DaByteStream = Box::pin(futures::stream::iter([
+            Ok(Bytes::from("hello ")),
+            Ok(Bytes::from("world")),
+        ]));
+        let blob = store.put(content).await.unwrap();
+        assert_eq!(blob.size, 11);
+        assert_eq!(
+            blob.content_hash,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+
+        let chunks: Vec<_> = store.get(&blob.key).await.unwrap().collect().await;
+        let content: Vec<u8> = chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect();
+        assert_eq!(content, b"hello world");
+
+        // A failing upload leaves nothing behind
+        let failing: DaByteStream = Box::pin(futures::stream::iter([
+            Ok(Bytes::from("partial")),
+            Err(std::io::Error::other("connection reset")),
+        ]));
+        assert!(store.put(failing).await.is_err());
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 1);
+
+        store.delete(&blob.key).await.unwrap();
+        store.delete(&blob.key).await.unwrap();
+        assert!(store.get(&blob.key).await.is_err());
+        assert!(store.get("../escape").await.is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
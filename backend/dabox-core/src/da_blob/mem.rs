@@ -0,0 +1,65 @@
+//! A memory-backed blob store, useful for testing and development purposes.
+use bytes::{Bytes, BytesMut};
+use futures::future::BoxFuture;
+
+use super::{new_blob_key, BlobDigest, DaBlob, DaBlobStore, DaByteStream};
+use crate::prelude::*;
+
+/// A memory-backed implementation of the `DaBlobStore` trait.
+/// Clones share the same contents.
+#[derive(Clone, Default)]
+pub struct MemBlobStore {
+    blobs: Arc<RwLock<HashMap<String, Bytes>>>,
+}
+
+impl MemBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of stored contents.
+    pub async fn len(&self) -> usize {
+        self.blobs.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.blobs.read().await.is_empty()
+    }
+}
+
+impl DaBlobStore for MemBlobStore {
+    fn put(&self, mut content: DaByteStream) -> BoxFuture<'_, DaResult<DaBlob>> {
+        Box::pin(async move {
+            let mut digest = BlobDigest::default();
+            let mut buffer = BytesMut::new();
+            while let Some(chunk) = content.next().await {
+                let chunk = chunk?;
+                digest.update(&chunk);
+                buffer.extend_from_slice(&chunk);
+            }
+            let blob = digest.finish(new_blob_key());
+            self.blobs
+                .write()
+                .await
+                .insert(blob.key.clone(), buffer.freeze());
+            Ok(blob)
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, DaResult<DaByteStream>> {
+        Box::pin(async move {
+            let content = self.blobs.read().await.get(key).cloned().ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, format!("No blob {key}"))
+            })?;
+            let stream: DaByteStream = Box::pin(futures::stream::once(async move { Ok(content) }));
+            Ok(stream)
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, DaResult<()>> {
+        Box::pin(async move {
+            self.blobs.write().await.remove(key);
+            Ok(())
+        })
+    }
+}
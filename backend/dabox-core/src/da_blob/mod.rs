@@ -0,0 +1,80 @@
+//! Storage of the file contents.
+//! Repositories only keep the metadata of the files, their content is stored in a `DaBlobStore` under an opaque key.
+//! Blobs are immutable: a new content is stored under a new key.
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+
+mod local;
+mod mem;
+
+pub use local::LocalBlobStore;
+pub use mem::MemBlobStore;
+
+/// A stream of file content.
+pub type DaByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// A stored content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaBlob {
+    /// The key of the content in the store
+    pub key: String,
+    /// The size of the content in bytes
+    pub size: u64,
+    /// The hex encoded SHA-256 of the content
+    pub content_hash: String,
+}
+
+/// A store of file contents.
+/// The trait is object safe so that the repositories can hold any store (`Arc<dyn DaBlobStore>`).
+pub trait DaBlobStore: Send + Sync {
+    /// Stores the whole content of the stream under a new key.
+    /// Nothing is stored if the stream fails.
+    fn put(&self, content: DaByteStream) -> BoxFuture<'_, DaResult<DaBlob>>;
+
+    /// Streams the content stored under `key`.
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, DaResult<DaByteStream>>;
+
+    /// Removes the content stored under `key` (removing a missing content is not an error).
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, DaResult<()>>;
+}
+
+/// Computes the size and hash of a content while it is stored.
+#[derive(Default)]
+pub(crate) struct BlobDigest {
+    hasher: Sha256,
+    size: u64,
+}
+
+impl BlobDigest {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+    }
+
+    pub fn finish(self, key: String) -> DaBlob {
+        DaBlob {
+            key,
+            size: self.size,
+            content_hash: hex::encode(self.hasher.finalize()),
+        }
+    }
+}
+
+/// Generates a new unique blob key.
+pub(crate) fn new_blob_key() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Removes the contents of deleted files.
+/// Failures are only logged: the files are already gone, at worst their content is left orphaned in the store.
+pub(crate) async fn delete_blobs(store: &dyn DaBlobStore, keys: Vec<String>) {
+    for key in keys {
+        if let Err(err) = store.delete(&key).await {
+            tracing::warn!(key, "Failed to delete blob: {err}");
+        }
+    }
+}
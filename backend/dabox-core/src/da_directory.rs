@@ -3,14 +3,14 @@
 use crate::prelude::*;
 use std::fmt::{Display, Formatter};
 
-#[cfg(feature = "database")]
+#[cfg(feature = "sqlx")]
 #[derive(
     Debug, Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, sqlx::Type,
 )]
 #[sqlx(transparent)]
 pub struct DaDirectorySid(pub(crate) i64);
 
-#[cfg(not(feature = "database"))]
+#[cfg(not(feature = "sqlx"))]
 #[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
 pub struct DaDirectorySid(pub(crate) i64);
 
@@ -27,6 +27,25 @@ pub struct DaDirectory {
     pub children: Vec<DaDirectory>,
     /// The depth of the directory in the hierarchy
     pub depth: u32,
+    /// Whether the directory has children that are not loaded in `children` (depth-limited reads)
+    pub has_unloaded_children: bool,
+    /// When the directory was created
+    pub created_at: DateTime<Utc>,
+    /// When the directory was last renamed, moved or had its children changed
+    pub updated_at: DateTime<Utc>,
+    /// Incremented every time `updated_at` changes (starts at 1), used to detect concurrent updates
+    pub version: u64,
+    /// The entity that created the directory (not necessarily its owner if it was created in a shared directory)
+    pub created_by: EntityUid,
+    /// The tags of the directory, in alphabetical order
+    pub tags: Vec<String>,
+}
+
+/// A directory of the chain returned by `DaRepository::list_ancestors`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaAncestor {
+    pub sid: DaDirectorySid,
+    pub name: String,
 }
 
 impl DaDirectory {
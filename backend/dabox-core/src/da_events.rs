@@ -0,0 +1,223 @@
+/// This module contains the live change feed of the repositories.
+/// Repositories publish a `DaChangeEvent` on their `DaEventBus` once a change of the directory tree is committed,
+/// subscribers receive every change and keep the ones their entity may see (see `DaChangeEvent::is_visible_to`).
+/// Who may see a change is resolved by the repository while making it, as the directory may be gone by the time it is received.
+use crate::prelude::*;
+use tokio::sync::broadcast;
+
+/// Number of events kept for the subscribers that are late, older events are dropped (the subscriber is told how many it missed).
+pub const DEFAULT_EVENT_BUS_CAPACITY: usize = 1024;
+
+/// A change of the directory tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DaChange {
+    /// The directory was created, copied or restored from the trash
+    Created {
+        name: String,
+        parent_sid: Option<DaDirectorySid>,
+    },
+    Renamed {
+        name: String,
+    },
+    Moved {
+        old_parent_sid: Option<DaDirectorySid>,
+        parent_sid: Option<DaDirectorySid>,
+    },
+    /// The directory was moved to the trash with its descendants
+    Deleted {
+        /// The parent the directory was deleted from
+        parent_sid: Option<DaDirectorySid>,
+    },
+}
+
+/// A change of a directory, as published on a `DaEventBus`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaChangeEvent {
+    /// The directory that changed
+    pub sid: DaDirectorySid,
+    /// The owner of the directory
+    pub owner: EntityUid,
+    /// The entity that made the change
+    pub changed_by: EntityUid,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub change: DaChange,
+    /// The entities granted access to the directory or to one of its ancestors when the change was made
+    /// (the old ancestors too for a move). Not sent to the subscribers.
+    #[serde(skip)]
+    pub grantees: BTreeSet<EntityUid>,
+    /// The entities granted access to one of the descendants of a deleted directory when it was deleted, which only see its sid
+    /// (see `DaDeletedSid`) unless they are `grantees` too. Not sent to the subscribers.
+    #[serde(skip)]
+    pub subtree_grantees: BTreeSet<EntityUid>,
+}
+
+/// What the grantees of the descendants of a deleted directory see of the deletion: the ancestors of their subtree,
+/// and thus the name and the parent of the deleted directory, are hidden from them (see `DaRepository::list_ancestors`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "deleted")]
+pub struct DaDeletedSid {
+    /// The deleted directory
+    pub sid: DaDirectorySid,
+}
+
+/// Broadcasts the changes of a repository to every subscriber.
+/// Cloning the bus shares it, publishing without subscribers is a no-op.
+#[derive(Debug, Clone)]
+pub struct DaEventBus {
+    sender: broadcast::Sender<DaChangeEvent>,
+}
+
+impl DaChangeEvent {
+    pub fn new(
+        sid: DaDirectorySid,
+        owner: EntityUid,
+        changed_by: EntityUid,
+        change: DaChange,
+    ) -> Self {
+        Self {
+            sid,
+            owner,
+            changed_by,
+            at: Utc::now(),
+            change,
+            grantees: BTreeSet::new(),
+            subtree_grantees: BTreeSet::new(),
+        }
+    }
+
+    /// Sets the entities that may see the event besides the owner, see `grantees`.
+    pub fn with_grantees(mut self, grantees: impl IntoIterator<Item = EntityUid>) -> Self {
+        self.grantees = grantees.into_iter().collect();
+        self
+    }
+
+    /// Sets the entities that may only see the sid of a deleted directory, see `subtree_grantees`.
+    pub fn with_subtree_grantees(mut self, grantees: impl IntoIterator<Item = EntityUid>) -> Self {
+        self.subtree_grantees = grantees.into_iter().collect();
+        self
+    }
+
+    /// Whether `entity` may see the event: the owner sees every change, other entities see the changes of the directories
+    /// they were granted access to (directly or through an ancestor) and of the directories above them.
+    /// The changes of the directories below them are hidden, like their ancestors are (see `DaRepository::list_ancestors`),
+    /// except for the deletions which they see the sid of (see `deleted_sid_for`).
+    pub fn is_visible_to(&self, entity: EntityUid) -> bool {
+        entity == self.owner || self.grantees.contains(&entity)
+    }
+
+    /// The sid of the deleted directory if `entity` may only see that of the event, see `subtree_grantees`.
+    pub fn deleted_sid_for(&self, entity: EntityUid) -> Option<DaDeletedSid> {
+        match self.change {
+            DaChange::Deleted { .. }
+                if !self.is_visible_to(entity) && self.subtree_grantees.contains(&entity) =>
+            {
+                Some(DaDeletedSid { sid: self.sid })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl DaEventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: DaChangeEvent) {
+        // Fails only when nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    /// Receives the events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<DaChangeEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for DaEventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_BUS_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_visibility() {
+        let repo = MemRepository::new();
+        let (owner, grantee, other) = (1, 2, 3);
+        let root = repo.create_directory(owner, "root", None).await.unwrap();
+        let shared = repo
+            .create_directory(owner, "shared", Some(root.sid))
+            .await
+            .unwrap();
+        let child = repo
+            .create_directory(owner, "child", Some(shared.sid))
+            .await
+            .unwrap();
+        repo.grant_access(owner, shared.sid, grantee, DaPermission::Read)
+            .await
+            .unwrap();
+        let mut events = repo.subscribe();
+
+        // The changes of the shared subtree are visible to the grantee, not those of the directories above it
+        repo.rename_directory(owner, child.sid, "renamed")
+            .await
+            .unwrap();
+        let renamed = events.try_recv().unwrap();
+        assert!(renamed.is_visible_to(owner));
+        assert!(renamed.is_visible_to(grantee));
+        assert!(!renamed.is_visible_to(other));
+        repo.rename_directory(owner, root.sid, "renamed")
+            .await
+            .unwrap();
+        assert!(!events.try_recv().unwrap().is_visible_to(grantee));
+
+        // Moves are visible to the entities that can read the directory, its old parent or its new parent
+        let outside = repo
+            .create_directory(owner, "outside", Some(root.sid))
+            .await
+            .unwrap();
+        assert!(!events.try_recv().unwrap().is_visible_to(grantee));
+        repo.move_directory(owner, outside.sid, Some(shared.sid))
+            .await
+            .unwrap();
+        assert!(events.try_recv().unwrap().is_visible_to(grantee));
+        repo.move_directory(owner, outside.sid, Some(root.sid))
+            .await
+            .unwrap();
+        let left = events.try_recv().unwrap();
+        assert!(left.is_visible_to(grantee));
+        assert!(!left.is_visible_to(other));
+        repo.move_directory(owner, outside.sid, None).await.unwrap();
+        assert!(!events.try_recv().unwrap().is_visible_to(grantee));
+
+        // The grantees of the descendants of a deleted directory only see its sid
+        let nested = repo
+            .create_directory(owner, "nested", Some(outside.sid))
+            .await
+            .unwrap();
+        repo.grant_access(owner, nested.sid, other, DaPermission::Read)
+            .await
+            .unwrap();
+        while events.try_recv().is_ok() {}
+        repo.delete_directory(owner, outside.sid).await.unwrap();
+        let deleted = events.try_recv().unwrap();
+        assert!(!deleted.is_visible_to(other));
+        assert_eq!(
+            serde_json::to_value(deleted.deleted_sid_for(other)).unwrap(),
+            serde_json::json!({ "type": "deleted", "sid": outside.sid })
+        );
+        assert!(!deleted.is_visible_to(grantee));
+        assert_eq!(deleted.deleted_sid_for(grantee), None);
+        repo.delete_directory(owner, shared.sid).await.unwrap();
+        let deleted = events.try_recv().unwrap();
+        assert!(deleted.is_visible_to(grantee));
+        assert_eq!(deleted.deleted_sid_for(grantee), None);
+    }
+}
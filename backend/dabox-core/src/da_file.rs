@@ -0,0 +1,44 @@
+/// This module contains the file data structure.
+/// The content of the files is not part of it, it is stored in a `DaBlobStore` and streamed on demand.
+use crate::prelude::*;
+use std::fmt::{Display, Formatter};
+
+#[cfg(feature = "sqlx")]
+#[derive(
+    Debug, Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, sqlx::Type,
+)]
+#[sqlx(transparent)]
+pub struct DaFileSid(pub(crate) i64);
+
+#[cfg(not(feature = "sqlx"))]
+#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub struct DaFileSid(pub(crate) i64);
+
+/// Represent a file stored inside a directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaFile {
+    /// The unique identifier of the file.
+    pub sid: DaFileSid,
+    /// The directory containing the file
+    pub directory_sid: DaDirectorySid,
+    /// The name of the file
+    pub name: String,
+    /// The size of the content in bytes
+    pub size: u64,
+    /// The media type of the content, as given on upload
+    pub content_type: String,
+    /// The hex encoded SHA-256 of the content
+    pub content_hash: String,
+    /// When the file was uploaded
+    pub created_at: DateTime<Utc>,
+    /// When the file was last modified
+    pub updated_at: DateTime<Utc>,
+    /// The entity that uploaded the file
+    pub created_by: EntityUid,
+}
+
+impl Display for DaFileSid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -1,7 +1,14 @@
 //! A memory-backed implementation of the `DaRepository` trait.
 //! The implementation is thread-safe and use a semi lock-free approach to archive good async performance.
 
+use crate::da_audit::DaAuditRecord;
+use crate::da_batch::check_batch_owner;
+use crate::da_blob::delete_blobs;
+use crate::da_repository::{check_version, path_prefix, readable_ancestors};
+use crate::da_tag::normalize_tag;
 use crate::prelude::*;
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Internal representation of a `DaDirectory` in the memory backend.
 #[derive(Debug, Clone)]
@@ -12,10 +19,143 @@ struct MemDaDirectory {
     name: Arc<RwLock<String>>,
     /// Parent directory unique identifier (none if the directory is the root)
     parent_sid: Option<DaDirectorySid>,
-    /// Children directory identifiers
-    children: Arc<RwLock<HashSet<DaDirectorySid>>>,
+    /// Children directory identifiers (ordered by sid to provide stable listings)
+    children: Arc<RwLock<BTreeSet<DaDirectorySid>>>,
+    /// Files of the directory (ordered by sid to provide stable listings)
+    files: Arc<RwLock<BTreeSet<DaFileSid>>>,
     /// The depth of the directory in the hierarchy
     depth: u32,
+    created_at: DateTime<Utc>,
+    /// Updated on rename, move and when a child is added or removed
+    updated_at: Arc<RwLock<DateTime<Utc>>>,
+    /// Incremented with `updated_at`
+    version: Arc<AtomicU64>,
+    created_by: EntityUid,
+    tags: Arc<RwLock<BTreeSet<String>>>,
+}
+
+/// Internal representation of a `DaFile` in the memory backend.
+#[derive(Debug, Clone)]
+struct MemDaFile {
+    file: DaFile,
+    /// Key of the content in the blob store
+    blob_key: String,
+}
+
+/// A deleted subtree in the trash of its owner.
+#[derive(Debug, Clone)]
+struct MemTrashEntry {
+    entry: DaTrashEntry,
+    /// The deleted directory and its descendants, as they were when deleted
+    directories: BTreeMap<DaDirectorySid, MemDaDirectory>,
+}
+
+/// The changes of a write to the directories of a bucket that are made outside of the directories, applied once the write succeeded.
+struct MemChanges {
+    /// The owner of the bucket
+    owner: EntityUid,
+    /// Directories to register in `owners`
+    created: Vec<DaDirectorySid>,
+    /// Subtrees to move to the trash
+    trashed: Vec<MemTrashEntry>,
+    records: Vec<DaAuditRecord>,
+    /// The changes to publish, with the directories whose grantees may see them (see `visible_through`)
+    /// and those whose grantees only see the sid of a deleted directory (see `DaChangeEvent::subtree_grantees`)
+    events: Vec<(DaChangeEvent, Vec<DaDirectorySid>, Vec<DaDirectorySid>)>,
+}
+
+/// The directories changed by a batch as they were before the batch, restored if an operation of the batch fails.
+#[derive(Default)]
+struct MemUndoLog {
+    /// None for the directories created by the batch
+    saved: BTreeMap<DaDirectorySid, Option<MemDaDirectory>>,
+}
+
+impl MemDaDirectory {
+    async fn touch(&self, now: DateTime<Utc>) {
+        *self.updated_at.write().await = now;
+        self.version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    /// A copy of the directory that doesn't share its mutable state with it.
+    async fn detached(&self) -> Self {
+        Self {
+            name: Arc::new(RwLock::new(self.name.read().await.clone())),
+            children: Arc::new(RwLock::new(self.children.read().await.clone())),
+            files: Arc::new(RwLock::new(self.files.read().await.clone())),
+            updated_at: Arc::new(RwLock::new(*self.updated_at.read().await)),
+            version: Arc::new(AtomicU64::new(self.version())),
+            tags: Arc::new(RwLock::new(self.tags.read().await.clone())),
+            ..self.clone()
+        }
+    }
+}
+
+impl MemChanges {
+    fn new(owner: EntityUid) -> Self {
+        Self {
+            owner,
+            created: vec![],
+            trashed: vec![],
+            records: vec![],
+            events: vec![],
+        }
+    }
+}
+
+impl MemUndoLog {
+    /// Saves a copy of the directories an operation is about to change, unless an earlier operation of the batch already did.
+    async fn save(
+        &mut self,
+        directories: &BTreeMap<DaDirectorySid, MemDaDirectory>,
+        sids: impl IntoIterator<Item = DaDirectorySid>,
+    ) {
+        for sid in sids {
+            if let (Some(dir), false) = (directories.get(&sid), self.saved.contains_key(&sid)) {
+                self.saved.insert(sid, Some(dir.detached().await));
+            }
+        }
+    }
+
+    /// Saves a copy of a directory and its descendants, and its parent (see `save`).
+    async fn save_subtree(
+        &mut self,
+        directories: &BTreeMap<DaDirectorySid, MemDaDirectory>,
+        id: DaDirectorySid,
+    ) {
+        let mut to_be_saved = vec![id];
+        if let Some(parent_sid) = directories.get(&id).and_then(|dir| dir.parent_sid) {
+            to_be_saved.push(parent_sid);
+        }
+        let mut to_be_visited = vec![id];
+        while let Some(sid) = to_be_visited.pop() {
+            if let Some(dir) = directories.get(&sid) {
+                let children = dir.children.read().await;
+                to_be_saved.extend(children.iter());
+                to_be_visited.extend(children.iter());
+            }
+        }
+        self.save(directories, to_be_saved).await;
+    }
+
+    /// Records a directory created by the batch, which is removed on rollback.
+    fn created(&mut self, sid: DaDirectorySid) {
+        self.saved.insert(sid, None);
+    }
+
+    /// Restores the directories as they were before the batch.
+    fn rollback(self, directories: &mut BTreeMap<DaDirectorySid, MemDaDirectory>) {
+        for (sid, dir) in self.saved {
+            match dir {
+                Some(dir) => directories.insert(sid, dir),
+                None => directories.remove(&sid),
+            };
+        }
+    }
 }
 
 /// A memory-backed implementation of the `DaRepositoryExt` trait.
@@ -23,21 +163,68 @@ struct MemDaDirectory {
 #[derive(Clone)]
 pub struct MemRepository {
     buckets: Arc<RwLock<HashMap<EntityUid, Bucket>>>,
+    /// Owner of every directory, sids are unique across the buckets so that a directory can be shared by its sid
+    owners: Arc<RwLock<HashMap<DaDirectorySid, EntityUid>>>,
+    sid_counter: Arc<AtomicI64>,
+    /// Every file, the directory of a file tells who owns it
+    files: Arc<RwLock<HashMap<DaFileSid, MemDaFile>>>,
+    file_sid_counter: Arc<AtomicI64>,
+    audit_id_counter: Arc<AtomicI64>,
+    blob_store: Arc<dyn DaBlobStore>,
+    config: Arc<DaRepositoryConfig>,
+    events: DaEventBus,
 }
 
+/// The directories owned by an entity.
 #[derive(Clone)]
 struct Bucket {
-    sid_counter: Arc<AtomicI64>,
     directories: Arc<RwLock<BTreeMap<DaDirectorySid, MemDaDirectory>>>,
+    /// Permissions granted to other entities, by directory
+    grants: Arc<RwLock<HashMap<DaDirectorySid, BTreeMap<EntityUid, DaPermission>>>>,
+    /// Deleted subtrees, by the sid of their root
+    trash: Arc<RwLock<BTreeMap<DaDirectorySid, MemTrashEntry>>>,
+    /// Operations made on the directories of the bucket, ordered by id
+    audit: Arc<RwLock<Vec<DaAuditEvent>>>,
+}
+
+impl Default for MemRepository {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemRepository {
     pub fn new() -> Self {
         Self {
             buckets: Arc::new(RwLock::new(HashMap::new())),
+            owners: Arc::new(RwLock::new(HashMap::new())),
+            sid_counter: Arc::new(AtomicI64::new(0)),
+            files: Arc::new(RwLock::new(HashMap::new())),
+            file_sid_counter: Arc::new(AtomicI64::new(0)),
+            audit_id_counter: Arc::new(AtomicI64::new(0)),
+            blob_store: Arc::new(MemBlobStore::new()),
+            config: Arc::new(DaRepositoryConfig::default()),
+            events: DaEventBus::default(),
         }
     }
 
+    pub fn with_config(mut self, config: DaRepositoryConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Stores the content of the files in `blob_store` instead of memory.
+    pub fn with_blob_store(mut self, blob_store: Arc<dyn DaBlobStore>) -> Self {
+        self.blob_store = blob_store;
+        self
+    }
+
+    /// Publishes the changes on `events` instead of a bus of its own.
+    pub fn with_event_bus(mut self, events: DaEventBus) -> Self {
+        self.events = events;
+        self
+    }
+
     async fn bucket(&self, uid: EntityUid) -> Bucket {
         let maybe_bucket = {
             let lock = self.buckets.read().await;
@@ -53,129 +240,1199 @@ impl MemRepository {
             }
         }
     }
-}
 
-impl DaRepository for MemRepository {
-    async fn create_directory(
+    /// Resolves the owner of a directory and ensures that `requested_by` has `permission` on it.
+    /// Returns the owner and its bucket.
+    async fn authorize(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        permission: DaPermission,
+    ) -> DaResult<(EntityUid, Bucket)> {
+        let owner = *self
+            .owners
+            .read()
+            .await
+            .get(&id)
+            .ok_or(DaError::DirectoryNotFound(id))?;
+        let bucket = self.bucket(owner).await;
+        if owner != requested_by && !bucket.is_granted(requested_by, id, permission).await {
+            return Err(DaError::AccessDenied {
+                requested_by,
+                resource_owner: owner,
+            });
+        }
+        Ok((owner, bucket))
+    }
+
+    /// Ensures that `requested_by` owns the directory, returns the bucket of the owner.
+    async fn authorize_owner(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> DaResult<Bucket> {
+        let owner = *self
+            .owners
+            .read()
+            .await
+            .get(&id)
+            .ok_or(DaError::DirectoryNotFound(id))?;
+        if owner != requested_by {
+            return Err(DaError::AccessDenied {
+                requested_by,
+                resource_owner: owner,
+            });
+        }
+        Ok(self.bucket(owner).await)
+    }
+
+    /// Ensures that no directory under `parent` (among the root directories if none) other than `except` is named like `name`,
+    /// according to the sibling name policy of the repository.
+    async fn check_sibling_names(
+        &self,
+        directories: &BTreeMap<DaDirectorySid, MemDaDirectory>,
+        parent: Option<DaDirectorySid>,
+        name: &str,
+        except: Option<DaDirectorySid>,
+    ) -> DaResult<()> {
+        let policy = self.config.sibling_names;
+        if !policy.is_unique() {
+            return Ok(());
+        }
+        let siblings: Vec<DaDirectorySid> = match parent {
+            Some(parent_sid) => directories
+                .get(&parent_sid)
+                .ok_or(DaError::ParentNotFound(parent_sid))?
+                .children
+                .read()
+                .await
+                .iter()
+                .copied()
+                .collect(),
+            None => directories
+                .values()
+                .filter(|dir| dir.parent_sid.is_none())
+                .map(|dir| dir.sid)
+                .collect(),
+        };
+        let mut names = vec![];
+        for sid in siblings.into_iter().filter(|sid| Some(*sid) != except) {
+            if let Some(dir) = directories.get(&sid) {
+                names.push(dir.name.read().await.clone());
+            }
+        }
+        policy.check(names.iter().map(String::as_str), name)
+    }
+
+    /// Ensures that `requested_by` has `permission` on the directory of a file.
+    /// Returns the file and the bucket of its owner.
+    async fn authorize_file(
+        &self,
+        requested_by: EntityUid,
+        id: DaFileSid,
+        permission: DaPermission,
+    ) -> DaResult<(MemDaFile, Bucket)> {
+        let file = self
+            .files
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or(DaError::FileNotFound(id))?;
+        let (_, bucket) = self
+            .authorize(requested_by, file.file.directory_sid, permission)
+            .await
+            .map_err(|err| match err {
+                // The directory has been deleted in the meantime
+                DaError::DirectoryNotFound(_) => DaError::FileNotFound(id),
+                err => err,
+            })?;
+        Ok((file, bucket))
+    }
+
+    /// Records an operation in the audit log of the owner of `bucket`.
+    async fn record(&self, bucket: &Bucket, record: DaAuditRecord) {
+        let mut audit_lock = bucket.audit.write().await;
+        // *thread safety* the id is taken while the log is locked so that the log stays ordered by id
+        let id = self
+            .audit_id_counter
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        audit_lock.push(record.into_event(id, Utc::now()));
+    }
+
+    /// Permanently removes a subtree taken out of the trash, with its grants, files and their contents.
+    /// `entity` is the entity purging the subtree (none if it expired).
+    async fn purge(&self, bucket: &Bucket, trashed: MemTrashEntry, entity: Option<EntityUid>) {
+        self.record(
+            bucket,
+            DaAuditRecord::new(entity, DaAuditOperation::PurgeDirectory, trashed.entry.sid),
+        )
+        .await;
+        let mut removed_files = vec![];
+        {
+            let mut grants_lock = bucket.grants.write().await;
+            for (sid, dir) in trashed.directories {
+                grants_lock.remove(&sid);
+                removed_files.extend(dir.files.read().await.iter().copied());
+            }
+        }
+        let blob_keys: Vec<String> = {
+            let mut files_lock = self.files.write().await;
+            removed_files
+                .into_iter()
+                .filter_map(|sid| files_lock.remove(&sid))
+                .map(|file| file.blob_key)
+                .collect()
+        };
+        // The contents are removed once the files are gone so that the store is not accessed while the map is locked
+        delete_blobs(self.blob_store.as_ref(), blob_keys).await;
+    }
+}
+
+impl DaRepository for MemRepository {
+    async fn create_directory(
+        &self,
+        requested_by: EntityUid,
+        name: &str,
+        parent: Option<DaDirectorySid>,
+    ) -> DaResult<DaDirectory> {
+        let name = &self.config.names.normalize(name)?;
+        // A directory created in a shared directory belongs to the owner of the shared directory
+        let (owner, bucket) = match parent {
+            Some(parent_sid) => self
+                .authorize(requested_by, parent_sid, DaPermission::Write)
+                .await
+                .map_err(DaError::for_parent)?,
+            None => (requested_by, self.bucket(requested_by).await),
+        };
+
+        // *thread safety* we use a block here to limit the scope of the global map lock
+        let sid = {
+            let mut directories_lock = bucket.directories.write().await;
+            let mut changes = MemChanges::new(owner);
+            let sid = self
+                .create_in(
+                    &mut directories_lock,
+                    &mut changes,
+                    requested_by,
+                    name,
+                    parent,
+                )
+                .await?;
+            // *thread safety* the owner is registered while the map is locked so that a concurrent deletion of the parent also unregisters it
+            self.apply(&bucket, changes).await;
+            sid
+        };
+
+        // Get the directory from the in-memory storage
+        let created_dir = self.get_directory(requested_by, sid).await;
+
+        // Ensure the directory is immediately available after creation
+        assert!(
+            created_dir.is_ok(),
+            "Directory should be immediately available after creation"
+        );
+        created_dir
+    }
+
+    async fn delete_directory_if_version(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        expected_version: Option<u64>,
+    ) -> DaResult<()> {
+        let (owner, bucket) = self
+            .authorize(requested_by, id, DaPermission::Write)
+            .await?;
+
+        // To ensure that there is no race-condition such as a child created between the get of all children and the removal of the directory itself
+        // we need to lock the global map exclusively. Better approach would be to use a scoped approach but we will keep it simple for now.
+        let mut dirs_lock = bucket.directories.write().await;
+        let mut changes = MemChanges::new(owner);
+        self.delete_in(
+            &mut dirs_lock,
+            &mut changes,
+            requested_by,
+            id,
+            expected_version,
+        )
+        .await?;
+        self.apply(&bucket, changes).await;
+        Ok(())
+    }
+
+    async fn rename_directory_if_version(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        new_name: &str,
+        expected_version: Option<u64>,
+    ) -> DaResult<()> {
+        let new_name = &self.config.names.normalize(new_name)?;
+        let (owner, bucket) = self
+            .authorize(requested_by, id, DaPermission::Write)
+            .await?;
+        // *thread safety* the map is locked exclusively so that siblings can't be renamed concurrently to the same name
+        let dirs_lock = bucket.directories.write().await;
+        let mut changes = MemChanges::new(owner);
+        self.rename_in(
+            &dirs_lock,
+            &mut changes,
+            requested_by,
+            id,
+            new_name,
+            expected_version,
+        )
+        .await?;
+        self.apply(&bucket, changes).await;
+        Ok(())
+    }
+    async fn get_directory_with_depth(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        depth: Option<u32>,
+    ) -> DaResult<DaDirectory> {
+        let (_, bucket) = self.authorize(requested_by, id, DaPermission::Read).await?;
+        self.clone().read_dir(bucket, id, depth).await
+    }
+
+    async fn resolve_path(
+        &self,
+        requested_by: EntityUid,
+        path: &[&str],
+        depth: Option<u32>,
+    ) -> DaResult<DaDirectory> {
+        // The names are looked up the way they are stored
+        let names = self.config.names.normalize_path(path);
+        let path: &[&str] = &names.iter().map(String::as_str).collect::<Vec<_>>();
+        let bucket = self.bucket(requested_by).await;
+        let sid = {
+            let dirs_lock = bucket.directories.read().await;
+            // Follow the children links from the roots, one name at a time
+            let mut candidates: Vec<DaDirectorySid> = dirs_lock
+                .values()
+                .filter(|dir| dir.parent_sid.is_none())
+                .map(|dir| dir.sid)
+                .collect();
+            let mut resolved = None;
+            for (idx, name) in path.iter().enumerate() {
+                let mut matches = vec![];
+                for sid in candidates {
+                    if let Some(dir) = dirs_lock.get(&sid) {
+                        if dir.name.read().await.as_str() == *name {
+                            matches.push(dir);
+                        }
+                    }
+                }
+                match matches.as_slice() {
+                    [dir] => {
+                        resolved = Some(dir.sid);
+                        candidates = dir.children.read().await.iter().copied().collect();
+                    }
+                    [] => return Err(DaError::PathNotFound(path_prefix(path, idx + 1))),
+                    _ => return Err(DaError::AmbiguousPath(path_prefix(path, idx + 1))),
+                }
+            }
+            resolved.ok_or_else(|| DaError::PathNotFound(path_prefix(path, 0)))?
+        };
+        self.clone().read_dir(bucket, sid, depth).await
+    }
+
+    async fn list_ancestors(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> DaResult<Vec<DaAncestor>> {
+        let (owner, bucket) = self.authorize(requested_by, id, DaPermission::Read).await?;
+        let dirs_lock = bucket.directories.read().await;
+        let grants_lock = bucket.grants.read().await;
+        let dir = dirs_lock.get(&id).ok_or(DaError::DirectoryNotFound(id))?;
+
+        let mut ancestors = Vec::new();
+        let mut parent_sid = dir.parent_sid;
+        while let Some(parent) = parent_sid.and_then(|sid| dirs_lock.get(&sid)) {
+            let granted = grants_lock
+                .get(&parent.sid)
+                .is_some_and(|grants| grants.contains_key(&requested_by));
+            let ancestor = DaAncestor {
+                sid: parent.sid,
+                name: parent.name.read().await.clone(),
+            };
+            ancestors.push((ancestor, granted));
+            parent_sid = parent.parent_sid;
+        }
+        ancestors.reverse();
+        Ok(readable_ancestors(ancestors, owner == requested_by))
+    }
+
+    async fn search_directories(
+        &self,
+        requested_by: EntityUid,
+        query: DaSearchQuery,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> DaResult<DaPage<DaSearchResult>> {
+        let bucket = match query.scope {
+            Some(scope) => self.authorize_owner(requested_by, scope).await?,
+            None => self.bucket(requested_by).await,
+        };
+        let dirs_lock = bucket.directories.read().await;
+
+        // Take one more result than requested to know whether there is a next page,
+        // the fuzzy results are ranked by score so all of them are needed
+        let mut results = Vec::new();
+        for dir in dirs_lock.values() {
+            let name = dir.name.read().await.clone();
+            if !query.matches(&name) {
+                continue;
+            }
+            let score = query.score(&name);
+            let mut result = DaSearchResult {
+                sid: dir.sid,
+                name: name.clone(),
+                parent_sid: dir.parent_sid,
+                depth: dir.depth,
+                path: vec![name],
+                score,
+            };
+            if let Some(cursor) = &cursor {
+                if !result.is_after(cursor)? {
+                    continue;
+                }
+            }
+            // Walk up to the root, through the scope if the directory is part of it
+            let mut in_scope = query.scope.is_none();
+            let mut parent_sid = dir.parent_sid;
+            while let Some(parent) = parent_sid.and_then(|sid| dirs_lock.get(&sid)) {
+                in_scope |= Some(parent.sid) == query.scope;
+                result.path.push(parent.name.read().await.clone());
+                parent_sid = parent.parent_sid;
+            }
+            if !in_scope {
+                continue;
+            }
+            result.path.reverse();
+            results.push(result);
+            if query.mode != DaSearchMode::Fuzzy && results.len() > limit as usize {
+                break;
+            }
+        }
+        results.sort_by(DaSearchResult::compare);
+        results.truncate(limit as usize + 1);
+
+        Ok(DaPage::from_overfetched(
+            results,
+            limit,
+            DaCursor::after_search_result,
+        ))
+    }
+
+    async fn move_directory(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        parent: Option<DaDirectorySid>,
+    ) -> DaResult<()> {
+        let (owner, bucket) = self
+            .authorize(requested_by, id, DaPermission::Write)
+            .await?;
+        if let Some(parent_sid) = parent {
+            // Directories can't be moved across the trees of different owners
+            let (parent_owner, _) = self
+                .authorize(requested_by, parent_sid, DaPermission::Write)
+                .await
+                .map_err(DaError::for_parent)?;
+            if parent_owner != owner {
+                return Err(DaError::AccessDenied {
+                    requested_by,
+                    resource_owner: parent_owner,
+                });
+            }
+        }
+
+        // The whole subtree is updated so we need an exclusive lock on the global map (same as `delete_directory`)
+        let mut dirs_lock = bucket.directories.write().await;
+        let mut changes = MemChanges::new(owner);
+        self.move_in(&mut dirs_lock, &mut changes, requested_by, id, parent)
+            .await?;
+        self.apply(&bucket, changes).await;
+        Ok(())
+    }
+    async fn copy_directory(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        parent: Option<DaDirectorySid>,
+        name: Option<&str>,
+    ) -> DaResult<DaDirectory> {
+        let name = name
+            .map(|name| self.config.names.normalize(name))
+            .transpose()?;
+        let (_, source_bucket) = self.authorize(requested_by, id, DaPermission::Read).await?;
+        // The copy belongs to the owner of its parent, the same way as a created directory
+        let (owner, bucket) = match parent {
+            Some(parent_sid) => self
+                .authorize(requested_by, parent_sid, DaPermission::Write)
+                .await
+                .map_err(DaError::for_parent)?,
+            None => (requested_by, self.bucket(requested_by).await),
+        };
+
+        // Take a snapshot of the subtree first, so that a directory can be copied into one of its descendants
+        let mut source = self.clone().read_dir(source_bucket, id, None).await?;
+        if let Some(name) = name {
+            source.name = name;
+        }
+
+        let now = Utc::now();
+        let mut directories_lock = bucket.directories.write().await;
+        let depth = match parent {
+            Some(parent_sid) => {
+                directories_lock
+                    .get(&parent_sid)
+                    .ok_or(DaError::ParentNotFound(parent_sid))?
+                    .depth
+                    + 1
+            }
+            None => 0,
+        };
+        self.config
+            .depth_limit
+            .check(owner, depth + subtree_height(&source))?;
+        self.check_sibling_names(&directories_lock, parent, &source.name, None)
+            .await?;
+
+        let copy_name = source.name.clone();
+        // Insert the copies from the top so that every parent exists before its children
+        let mut to_be_copied = vec![(source, parent, depth)];
+        let mut copy_sid = None;
+        let mut owners_lock = self.owners.write().await;
+        while let Some((dir, parent_sid, depth)) = to_be_copied.pop() {
+            let sid = DaDirectorySid(
+                self.sid_counter
+                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+            );
+            copy_sid.get_or_insert(sid);
+            if let Some(parent_dir) = parent_sid.and_then(|sid| directories_lock.get(&sid)) {
+                parent_dir.children.write().await.insert(sid);
+                parent_dir.touch(now).await;
+            }
+            let copy = MemDaDirectory {
+                sid,
+                name: Arc::new(RwLock::new(dir.name)),
+                parent_sid,
+                children: Arc::new(RwLock::new(BTreeSet::new())),
+                files: Arc::new(RwLock::new(BTreeSet::new())),
+                depth,
+                created_at: now,
+                updated_at: Arc::new(RwLock::new(now)),
+                version: Arc::new(AtomicU64::new(1)),
+                created_by: requested_by,
+                tags: Arc::new(RwLock::new(dir.tags.into_iter().collect())),
+            };
+            if directories_lock.insert(sid, copy).is_some() {
+                panic!("Directory sid collision (sid sequence is broken)");
+            }
+            owners_lock.insert(sid, owner);
+            to_be_copied.extend(
+                dir.children
+                    .into_iter()
+                    .map(|child| (child, Some(sid), depth + 1)),
+            );
+        }
+        drop(owners_lock);
+        let copy_sid = copy_sid.ok_or(DaError::DirectoryNotFound(id))?;
+        let grantees = grantees_in(
+            &*bucket.grants.read().await,
+            visible_through(&directories_lock, copy_sid, None),
+        );
+        drop(directories_lock);
+
+        self.record(
+            &bucket,
+            DaAuditRecord::new(requested_by, DaAuditOperation::CopyDirectory, copy_sid)
+                .after(json!({ "name": copy_name, "parent_sid": parent, "source_sid": id })),
+        )
+        .await;
+        self.events.publish(
+            DaChangeEvent::new(
+                copy_sid,
+                owner,
+                requested_by,
+                DaChange::Created {
+                    name: copy_name,
+                    parent_sid: parent,
+                },
+            )
+            .with_grantees(grantees),
+        );
+        self.clone().read_dir(bucket, copy_sid, None).await
+    }
+
+    async fn apply_batch(
+        &self,
+        requested_by: EntityUid,
+        operations: &[DaBatchOperation],
+    ) -> DaResult<DaBatchResult> {
+        // The batch applies to the tree of the owner of the directory targeted by the first operation
+        let owner = match operations.first().and_then(DaBatchOperation::target) {
+            Some(DaBatchRef::Sid(sid)) => *self
+                .owners
+                .read()
+                .await
+                .get(sid)
+                .ok_or_else(|| DaError::DirectoryNotFound(*sid).in_batch(0))?,
+            _ => requested_by,
+        };
+        let bucket = self.bucket(owner).await;
+
+        // *thread safety* the map is locked exclusively for the whole batch, the directories changed by the operations are saved
+        // first so that they can be restored if an operation fails
+        let mut dirs_lock = bucket.directories.write().await;
+        let mut undo = MemUndoLog::default();
+        let mut changes = MemChanges::new(owner);
+        let mut result = DaBatchResult::default();
+        for (index, operation) in operations.iter().enumerate() {
+            if let Err(err) = self
+                .apply_operation(
+                    &mut dirs_lock,
+                    &mut undo,
+                    &mut changes,
+                    &mut result,
+                    requested_by,
+                    operation,
+                )
+                .await
+            {
+                undo.rollback(&mut dirs_lock);
+                return Err(err.in_batch(index));
+            }
+        }
+        self.apply(&bucket, changes).await;
+        Ok(result)
+    }
+
+    async fn list_children(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> DaResult<DaPage<DaDirectory>> {
+        let (_, bucket) = self.authorize(requested_by, id, DaPermission::Read).await?;
+
+        // Take one more child than requested to know whether there is a next page
+        let children_sids: Vec<DaDirectorySid> = {
+            let dirs_lock = bucket.directories.read().await;
+            let dir = dirs_lock.get(&id).ok_or(DaError::DirectoryNotFound(id))?;
+            let children = dir.children.read().await;
+            let lower_bound = match cursor {
+                Some(cursor) => std::ops::Bound::Excluded(cursor.last_sid()),
+                None => std::ops::Bound::Unbounded,
+            };
+            children
+                .range((lower_bound, std::ops::Bound::Unbounded))
+                .take(limit as usize + 1)
+                .copied()
+                .collect()
+        };
+
+        let children = futures::stream::iter(
+            children_sids
+                .into_iter()
+                .map(|sid| self.clone().read_dir(bucket.clone(), sid, Some(0))),
+        )
+        .buffered(16)
+        // Children deleted in the meantime are skipped
+        .filter_map(|res| async move { res.ok() })
+        .collect()
+        .await;
+
+        Ok(DaPage::from_overfetched(children, limit, |dir| {
+            DaCursor::after(dir.sid)
+        }))
+    }
+
+    async fn list_root_directories(
+        &self,
+        requested_by: EntityUid,
+        sort: DaSort,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> DaResult<DaPage<DaDirectory>> {
+        let bucket = self.bucket(requested_by).await;
+        let roots_sids: Vec<DaDirectorySid> = {
+            let dirs_lock = bucket.directories.read().await;
+            dirs_lock
+                .values()
+                .filter(|dir| dir.parent_sid.is_none())
+                .map(|dir| dir.sid)
+                .collect()
+        };
+
+        let mut roots: Vec<DaDirectory> = futures::stream::iter(
+            roots_sids
+                .into_iter()
+                .map(|sid| self.clone().read_dir(bucket.clone(), sid, Some(0))),
+        )
+        .buffered(16)
+        // Directories deleted in the meantime are skipped
+        .filter_map(|res| async move { res.ok() })
+        .collect()
+        .await;
+        if let Some(cursor) = &cursor {
+            let mut after_cursor = Vec::with_capacity(roots.len());
+            for dir in roots {
+                if sort.is_after(&dir, cursor)? {
+                    after_cursor.push(dir);
+                }
+            }
+            roots = after_cursor;
+        }
+        roots.sort_by(|a, b| sort.compare(a, b));
+        roots.truncate(limit as usize + 1);
+
+        Ok(DaPage::from_overfetched(roots, limit, |dir| {
+            DaCursor::after_directory(dir, sort)
+        }))
+    }
+
+    async fn grant_access(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        grantee: EntityUid,
+        permission: DaPermission,
+    ) -> DaResult<DaGrant> {
+        let bucket = self.authorize_owner(requested_by, id).await?;
+        let old_permission = bucket
+            .grants
+            .write()
+            .await
+            .entry(id)
+            .or_default()
+            .insert(grantee, permission);
+        self.record(
+            &bucket,
+            DaAuditRecord::new(requested_by, DaAuditOperation::GrantAccess, id)
+                .before(json!({ "grantee": grantee, "permission": old_permission }))
+                .after(json!({ "grantee": grantee, "permission": permission })),
+        )
+        .await;
+        Ok(DaGrant {
+            sid: id,
+            grantee,
+            permission,
+        })
+    }
+
+    async fn revoke_access(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        grantee: EntityUid,
+    ) -> DaResult<()> {
+        let bucket = self.authorize_owner(requested_by, id).await?;
+        let old_permission = {
+            let mut grants_lock = bucket.grants.write().await;
+            let grants = grants_lock.get_mut(&id);
+            let old_permission = grants.and_then(|grants| grants.remove(&grantee));
+            if grants_lock.get(&id).is_some_and(BTreeMap::is_empty) {
+                grants_lock.remove(&id);
+            }
+            old_permission
+        };
+        self.record(
+            &bucket,
+            DaAuditRecord::new(requested_by, DaAuditOperation::RevokeAccess, id)
+                .before(json!({ "grantee": grantee, "permission": old_permission }))
+                .after(json!({ "grantee": grantee, "permission": null })),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn list_grants(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> DaResult<Vec<DaGrant>> {
+        let bucket = self.authorize_owner(requested_by, id).await?;
+        let grants_lock = bucket.grants.read().await;
+        Ok(grants_lock
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|(grantee, permission)| DaGrant {
+                sid: id,
+                grantee: *grantee,
+                permission: *permission,
+            })
+            .collect())
+    }
+
+    async fn add_tag(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        tag: &str,
+    ) -> DaResult<Vec<String>> {
+        let tag = normalize_tag(tag)?;
+        let (_, bucket) = self
+            .authorize(requested_by, id, DaPermission::Write)
+            .await?;
+        let tags = {
+            let dirs_lock = bucket.directories.read().await;
+            let dir = dirs_lock.get(&id).ok_or(DaError::DirectoryNotFound(id))?;
+            let mut tags_lock = dir.tags.write().await;
+            tags_lock.insert(tag.clone());
+            tags_lock.iter().cloned().collect()
+        };
+        self.record(
+            &bucket,
+            DaAuditRecord::new(requested_by, DaAuditOperation::TagDirectory, id)
+                .after(json!({ "tag": tag })),
+        )
+        .await;
+        Ok(tags)
+    }
+
+    async fn remove_tag(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        tag: &str,
+    ) -> DaResult<Vec<String>> {
+        let tag = normalize_tag(tag)?;
+        let (_, bucket) = self
+            .authorize(requested_by, id, DaPermission::Write)
+            .await?;
+        let tags = {
+            let dirs_lock = bucket.directories.read().await;
+            let dir = dirs_lock.get(&id).ok_or(DaError::DirectoryNotFound(id))?;
+            let mut tags_lock = dir.tags.write().await;
+            tags_lock.remove(&tag);
+            tags_lock.iter().cloned().collect()
+        };
+        self.record(
+            &bucket,
+            DaAuditRecord::new(requested_by, DaAuditOperation::UntagDirectory, id)
+                .before(json!({ "tag": tag })),
+        )
+        .await;
+        Ok(tags)
+    }
+
+    async fn list_tags(&self, requested_by: EntityUid) -> DaResult<Vec<DaTagCount>> {
+        let bucket = self.bucket(requested_by).await;
+        let dirs_lock = bucket.directories.read().await;
+        let mut counts = BTreeMap::<String, u64>::new();
+        for dir in dirs_lock.values() {
+            for tag in dir.tags.read().await.iter() {
+                *counts.entry(tag.clone()).or_default() += 1;
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(|(tag, count)| DaTagCount { tag, count })
+            .collect())
+    }
+
+    async fn list_tagged_directories(
+        &self,
+        requested_by: EntityUid,
+        query: DaTagQuery,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> DaResult<DaPage<DaDirectory>> {
+        let tags = query.normalized_tags()?;
+        let bucket = self.bucket(requested_by).await;
+        let last_sid = cursor.map(|cursor| cursor.last_sid());
+
+        // Take one more directory than requested to know whether there is a next page
+        let mut sids = Vec::new();
+        {
+            let dirs_lock = bucket.directories.read().await;
+            for dir in dirs_lock.values() {
+                if last_sid.is_some_and(|last_sid| dir.sid <= last_sid) {
+                    continue;
+                }
+                if query.mode.matches(&tags, &*dir.tags.read().await) {
+                    sids.push(dir.sid);
+                    if sids.len() > limit as usize {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let directories = futures::stream::iter(
+            sids.into_iter()
+                .map(|sid| self.clone().read_dir(bucket.clone(), sid, Some(0))),
+        )
+        .buffered(16)
+        // Directories deleted in the meantime are skipped
+        .filter_map(|res| async move { res.ok() })
+        .collect()
+        .await;
+        Ok(DaPage::from_overfetched(directories, limit, |dir| {
+            DaCursor::after(dir.sid)
+        }))
+    }
+
+    async fn create_file(
+        &self,
+        requested_by: EntityUid,
+        directory: DaDirectorySid,
+        name: &str,
+        content_type: &str,
+        content: DaByteStream,
+    ) -> DaResult<DaFile> {
+        let name = &self.config.names.normalize(name)?;
+        let (_, bucket) = self
+            .authorize(requested_by, directory, DaPermission::Write)
+            .await?;
+
+        // The content is stored first, the file only becomes visible once it is complete
+        let blob = self.blob_store.put(content).await?;
+
+        let sid = DaFileSid(
+            self.file_sid_counter
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+        );
+        let now = Utc::now();
+        let file = DaFile {
+            sid,
+            directory_sid: directory,
+            name: name.to_string(),
+            size: blob.size,
+            content_type: content_type.to_string(),
+            content_hash: blob.content_hash,
+            created_at: now,
+            updated_at: now,
+            created_by: requested_by,
+        };
+
+        {
+            // *thread safety* the file is registered while the map is locked so that a concurrent deletion of the directory also removes it
+            let dirs_lock = bucket.directories.read().await;
+            match dirs_lock.get(&directory) {
+                Some(dir) => {
+                    dir.files.write().await.insert(sid);
+                    dir.touch(now).await;
+                    self.files.write().await.insert(
+                        sid,
+                        MemDaFile {
+                            file: file.clone(),
+                            blob_key: blob.key,
+                        },
+                    );
+                }
+                None => {
+                    drop(dirs_lock);
+                    delete_blobs(self.blob_store.as_ref(), vec![blob.key]).await;
+                    return Err(DaError::DirectoryNotFound(directory));
+                }
+            }
+        }
+        self.record(
+            &bucket,
+            DaAuditRecord::new(requested_by, DaAuditOperation::CreateFile, directory)
+                .file(sid)
+                .after(json!({
+                    "name": file.name,
+                    "size": file.size,
+                    "content_type": file.content_type,
+                })),
+        )
+        .await;
+        Ok(file)
+    }
+
+    async fn get_file(&self, requested_by: EntityUid, id: DaFileSid) -> DaResult<DaFile> {
+        let (file, _) = self
+            .authorize_file(requested_by, id, DaPermission::Read)
+            .await?;
+        Ok(file.file)
+    }
+
+    async fn read_file(
         &self,
         requested_by: EntityUid,
-        name: &str,
-        parent: Option<DaDirectorySid>,
-    ) -> DaResult<DaDirectory> {
-        let bucket = self.bucket(requested_by).await;
-
-        // Generate a new sid from the global directory counter
-        let sid = DaDirectorySid(
-            bucket
-                .sid_counter
-                .fetch_add(1, std::sync::atomic::Ordering::SeqCst),
-        );
-
-        // Create in-memory representation of the directory
-        let mut directory = MemDaDirectory {
-            sid: sid,
-            name: Arc::new(RwLock::new(name.to_string())),
-            parent_sid: parent,
-            children: Arc::new(RwLock::new(HashSet::new())),
-            depth: 0,
-        };
+        id: DaFileSid,
+    ) -> DaResult<(DaFile, DaByteStream)> {
+        let (file, _) = self
+            .authorize_file(requested_by, id, DaPermission::Read)
+            .await?;
+        let content = self.blob_store.get(&file.blob_key).await?;
+        Ok((file.file, content))
+    }
 
-        // Add the directory to both the global map and the parent's children list
-        // *thread safety* we use a block here to limit the scope of the global map lock
+    async fn delete_file(&self, requested_by: EntityUid, id: DaFileSid) -> DaResult<()> {
+        let (file, bucket) = self
+            .authorize_file(requested_by, id, DaPermission::Write)
+            .await?;
         {
-            let mut directories_lock = bucket.directories.write().await;
-            if let Some(parent_sid) = parent {
-                let parent_dir = directories_lock
-                    .get(&parent_sid)
-                    .ok_or(DaError::DirectoryNotFound(parent_sid))?;
-                parent_dir.children.write().await.insert(sid);
-                directory.depth = parent_dir.depth + 1;
+            let dirs_lock = bucket.directories.read().await;
+            if self.files.write().await.remove(&id).is_none() {
+                return Err(DaError::FileNotFound(id));
             }
-
-            if directories_lock.insert(sid, directory.clone()).is_some() {
-                panic!("Directory sid collision (sid sequence is broken)");
+            if let Some(dir) = dirs_lock.get(&file.file.directory_sid) {
+                dir.files.write().await.remove(&id);
+                dir.touch(Utc::now()).await;
             }
         }
+        self.record(
+            &bucket,
+            DaAuditRecord::new(
+                requested_by,
+                DaAuditOperation::DeleteFile,
+                file.file.directory_sid,
+            )
+            .file(id)
+            .before(json!({ "name": file.file.name })),
+        )
+        .await;
+        delete_blobs(self.blob_store.as_ref(), vec![file.blob_key]).await;
+        Ok(())
+    }
 
-        // Get the directory from the in-memory storage
-        let created_dir = self.get_directory(requested_by, sid).await;
+    async fn list_files(
+        &self,
+        requested_by: EntityUid,
+        directory: DaDirectorySid,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> DaResult<DaPage<DaFile>> {
+        let (_, bucket) = self
+            .authorize(requested_by, directory, DaPermission::Read)
+            .await?;
 
-        // Ensure the directory is immediately available after creation
-        assert!(
-            created_dir.is_ok(),
-            "Directory should be immediately available after creation"
-        );
-        created_dir
+        // Take one more file than requested to know whether there is a next page
+        let files_sids: Vec<DaFileSid> = {
+            let dirs_lock = bucket.directories.read().await;
+            let dir = dirs_lock
+                .get(&directory)
+                .ok_or(DaError::DirectoryNotFound(directory))?;
+            let files = dir.files.read().await;
+            let lower_bound = match cursor {
+                Some(cursor) => std::ops::Bound::Excluded(cursor.last_file_sid()),
+                None => std::ops::Bound::Unbounded,
+            };
+            files
+                .range((lower_bound, std::ops::Bound::Unbounded))
+                .take(limit as usize + 1)
+                .copied()
+                .collect()
+        };
+
+        let files_lock = self.files.read().await;
+        let files = files_sids
+            .iter()
+            // Files deleted in the meantime are skipped
+            .filter_map(|sid| files_lock.get(sid))
+            .map(|file| file.file.clone())
+            .collect();
+        Ok(DaPage::from_overfetched(files, limit, |file| {
+            DaCursor::after_file(file.sid)
+        }))
     }
 
-    async fn delete_directory(&self, requested_by: EntityUid, id: DaDirectorySid) -> DaResult<()> {
+    async fn list_trash(
+        &self,
+        requested_by: EntityUid,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> DaResult<DaPage<DaTrashEntry>> {
         let bucket = self.bucket(requested_by).await;
+        let trash_lock = bucket.trash.read().await;
+        let lower_bound = match cursor {
+            Some(cursor) => std::ops::Bound::Excluded(cursor.last_sid()),
+            None => std::ops::Bound::Unbounded,
+        };
+        // Take one more entry than requested to know whether there is a next page
+        let entries = trash_lock
+            .range((lower_bound, std::ops::Bound::Unbounded))
+            .take(limit as usize + 1)
+            .map(|(_, trashed)| trashed.entry.clone())
+            .collect();
+        Ok(DaPage::from_overfetched(entries, limit, |entry| {
+            DaCursor::after(entry.sid)
+        }))
+    }
 
-        // To ensure that there is no race-condition such as a child created between the get of all children and the removal of the directory itself
-        // we need to lock the global map exclusively. Better approach would be to use a scoped approach but we will keep it simple for now.
+    async fn restore_directory(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> DaResult<DaDirectory> {
+        // Only the owner has the subtree in its trash, the trashed directories are not registered in `owners`
+        let bucket = self.bucket(requested_by).await;
         let mut dirs_lock = bucket.directories.write().await;
+        let mut trash_lock = bucket.trash.write().await;
 
-        let parent_sid = dirs_lock
-            .get(&id)
-            .ok_or(DaError::DirectoryNotFound(id))?
-            .parent_sid;
-
-        let mut to_be_removed = vec![id];
+        let (parent, depth, old_depth) = {
+            let trashed = trash_lock.get(&id).ok_or(DaError::TrashEntryNotFound(id))?;
+            let root = trashed
+                .directories
+                .get(&id)
+                .ok_or(DaError::TrashEntryNotFound(id))?;
+            // The original parent may have been deleted in the meantime
+            let parent = trashed
+                .entry
+                .parent_sid
+                .filter(|parent_sid| dirs_lock.contains_key(parent_sid));
+            let depth = match parent.and_then(|parent_sid| dirs_lock.get(&parent_sid)) {
+                Some(parent_dir) => parent_dir.depth + 1,
+                None => 0,
+            };
+            let max_depth = trashed
+                .directories
+                .values()
+                .map(|dir| dir.depth)
+                .max()
+                .unwrap_or(root.depth);
+            self.config
+                .depth_limit
+                .check(requested_by, max_depth - root.depth + depth)?;
+            self.check_sibling_names(&dirs_lock, parent, &root.name.read().await, None)
+                .await?;
+            (parent, depth, root.depth)
+        };
 
-        while let Some(sid) = to_be_removed.pop() {
-            if let Some(dir) = dirs_lock.remove(&sid) {
-                to_be_removed.extend(dir.children.read().await.iter());
-            } else {
-                return Err(DaError::DirectoryNotFound(sid));
+        let trashed = trash_lock
+            .remove(&id)
+            .ok_or(DaError::TrashEntryNotFound(id))?;
+        let name = trashed.entry.name.clone();
+        let now = Utc::now();
+        {
+            let mut owners_lock = self.owners.write().await;
+            for (sid, dir) in trashed.directories {
+                let dir = MemDaDirectory {
+                    parent_sid: if sid == id { parent } else { dir.parent_sid },
+                    depth: dir.depth - old_depth + depth,
+                    ..dir
+                };
+                if sid == id {
+                    dir.touch(now).await;
+                }
+                owners_lock.insert(sid, requested_by);
+                dirs_lock.insert(sid, dir);
             }
         }
-
-        for sid in to_be_removed {
-            dirs_lock.remove(&sid);
+        if let Some(parent_dir) = parent.and_then(|parent_sid| dirs_lock.get(&parent_sid)) {
+            parent_dir.children.write().await.insert(id);
+            parent_dir.touch(now).await;
         }
+        // The grants of the subtree were kept in the trash
+        let grantees = grantees_in(
+            &*bucket.grants.read().await,
+            visible_through(&dirs_lock, id, None),
+        );
+        drop(trash_lock);
+        drop(dirs_lock);
 
-        // Remove the directory from the parent's children list
-        // *note* we're doing this after the loop to ensure that we are able to remove orphaned children
-        if let Some(parent_sid) = parent_sid {
-            dirs_lock
-                .get_mut(&parent_sid)
-                .ok_or(DaError::DirectoryNotFound(parent_sid))?
-                .children
-                .write()
-                .await
-                .remove(&id);
-        }
-        Ok(())
+        self.record(
+            &bucket,
+            DaAuditRecord::new(requested_by, DaAuditOperation::RestoreDirectory, id)
+                .after(json!({ "parent_sid": parent })),
+        )
+        .await;
+        self.events.publish(
+            DaChangeEvent::new(
+                id,
+                requested_by,
+                requested_by,
+                DaChange::Created {
+                    name,
+                    parent_sid: parent,
+                },
+            )
+            .with_grantees(grantees),
+        );
+        self.clone().read_dir(bucket, id, None).await
     }
 
-    async fn rename_directory(
-        &self,
-        requested_by: EntityUid,
-        id: DaDirectorySid,
-        new_name: &str,
-    ) -> DaResult<()> {
+    async fn purge_directory(&self, requested_by: EntityUid, id: DaDirectorySid) -> DaResult<()> {
         let bucket = self.bucket(requested_by).await;
-        let dirs_lock = bucket.directories.read().await;
-        let dir_lock = dirs_lock.get(&id).ok_or(DaError::DirectoryNotFound(id))?;
-        let mut name_lock = dir_lock.name.write().await;
-        *name_lock = new_name.to_string();
+        let trashed = bucket
+            .trash
+            .write()
+            .await
+            .remove(&id)
+            .ok_or(DaError::TrashEntryNotFound(id))?;
+        self.purge(&bucket, trashed, Some(requested_by)).await;
         Ok(())
     }
 
-    async fn get_directory(
+    async fn list_audit_events(
         &self,
         requested_by: EntityUid,
-        id: DaDirectorySid,
-    ) -> DaResult<DaDirectory> {
-        self.clone().read_dir(requested_by, id).await
+        filter: DaAuditFilter,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> DaResult<DaPage<DaAuditEvent>> {
+        let bucket = self.bucket(requested_by).await;
+        let audit_lock = bucket.audit.read().await;
+        let start = cursor.map_or(0, |cursor| {
+            audit_lock.partition_point(|event| event.id <= cursor.last_event_id())
+        });
+        // Take one more event than requested to know whether there is a next page
+        let events = audit_lock[start..]
+            .iter()
+            .filter(|event| filter.matches(event))
+            .take(limit as usize + 1)
+            .cloned()
+            .collect();
+        Ok(DaPage::from_overfetched(events, limit, |event| {
+            DaCursor::after_event(event.id)
+        }))
+    }
+
+    fn config(&self) -> &DaRepositoryConfig {
+        &self.config
+    }
+
+    fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DaChangeEvent> {
+        self.events.subscribe()
+    }
+
+    async fn purge_expired_trash(&self) -> DaResult<usize> {
+        let Some(expiry) = self.config.trash_expiry(Utc::now()) else {
+            return Ok(0);
+        };
+        let buckets: Vec<Bucket> = self.buckets.read().await.values().cloned().collect();
+        let mut purged = 0;
+        for bucket in buckets {
+            let expired: Vec<MemTrashEntry> = {
+                let mut trash_lock = bucket.trash.write().await;
+                let sids: Vec<DaDirectorySid> = trash_lock
+                    .values()
+                    .filter(|trashed| trashed.entry.deleted_at < expiry)
+                    .map(|trashed| trashed.entry.sid)
+                    .collect();
+                sids.iter()
+                    .filter_map(|sid| trash_lock.remove(sid))
+                    .collect()
+            };
+            for trashed in expired {
+                self.purge(&bucket, trashed, None).await;
+                purged += 1;
+            }
+        }
+        Ok(purged)
     }
 }
 
 impl MemRepository {
     #[async_recursion::async_recursion]
-    async fn read_dir(self, requested_by: EntityUid, id: DaDirectorySid) -> DaResult<DaDirectory> {
-        let bucket = self.bucket(requested_by).await;
-
+    async fn read_dir(
+        self,
+        bucket: Bucket,
+        id: DaDirectorySid,
+        depth: Option<u32>,
+    ) -> DaResult<DaDirectory> {
         let dir = {
             let dirs_lock = bucket.directories.read().await;
             dirs_lock
@@ -184,18 +1441,38 @@ impl MemRepository {
                 .clone()
         };
         let name = dir.name.read().await.clone();
+        let updated_at = *dir.updated_at.read().await;
+        let version = dir.version();
+        let tags = dir.tags.read().await.iter().cloned().collect();
 
         // Get a copy of all the children sids
         let children_sids = dir.children.read().await.clone();
 
+        // Stop at the depth boundary, only reporting whether there is something left to load
+        if depth == Some(0) {
+            return Ok(DaDirectory {
+                name,
+                depth: dir.depth,
+                sid: dir.sid,
+                parent_sid: dir.parent_sid,
+                children: vec![],
+                has_unloaded_children: !children_sids.is_empty(),
+                created_at: dir.created_at,
+                updated_at,
+                version,
+                created_by: dir.created_by,
+                tags,
+            });
+        }
+
         // Create a stream of futures that will each resolve to a `DaDirectory`
-        let children = futures::stream::iter(
-            children_sids
-                .into_iter()
-                .map(|sid| self.get_directory(requested_by, sid)),
-        )
+        let children = futures::stream::iter(children_sids.into_iter().map(|sid| {
+            self.clone()
+                .read_dir(bucket.clone(), sid, depth.map(|depth| depth - 1))
+        }))
         // Buffer up to 16 futures at a time to avoid excessive memory allocations (even if in our actual use case children aggreation is simple we might want to add more complex logic in the future that inevitability will require buffering)
-        .buffer_unordered(16)
+        // *note* the buffering keeps the sid order of the children
+        .buffered(16)
         // Filter out any errors and return the directories
         .filter_map(|res| async move {
             match res {
@@ -213,26 +1490,553 @@ impl MemRepository {
             sid: dir.sid,
             parent_sid: dir.parent_sid,
             children: children.collect().await,
+            has_unloaded_children: false,
+            created_at: dir.created_at,
+            updated_at,
+            version,
+            created_by: dir.created_by,
+            tags,
         })
     }
+
+    /// Creates a directory in the tree of `changes.owner` (see `DaRepository::create_directory`), the name is already normalised.
+    async fn create_in(
+        &self,
+        directories: &mut BTreeMap<DaDirectorySid, MemDaDirectory>,
+        changes: &mut MemChanges,
+        requested_by: EntityUid,
+        name: &str,
+        parent: Option<DaDirectorySid>,
+    ) -> DaResult<DaDirectorySid> {
+        // Generate a new sid from the global directory counter
+        let sid = DaDirectorySid(
+            self.sid_counter
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+        );
+
+        // Create in-memory representation of the directory
+        let now = Utc::now();
+        let mut directory = MemDaDirectory {
+            sid,
+            name: Arc::new(RwLock::new(name.to_string())),
+            parent_sid: parent,
+            children: Arc::new(RwLock::new(BTreeSet::new())),
+            files: Arc::new(RwLock::new(BTreeSet::new())),
+            depth: 0,
+            created_at: now,
+            updated_at: Arc::new(RwLock::new(now)),
+            version: Arc::new(AtomicU64::new(1)),
+            created_by: requested_by,
+            tags: Arc::new(RwLock::new(BTreeSet::new())),
+        };
+
+        // Add the directory to both the map and the parent's children list
+        if let Some(parent_sid) = parent {
+            let parent_dir = directories
+                .get(&parent_sid)
+                .ok_or(DaError::ParentNotFound(parent_sid))?;
+            self.config
+                .depth_limit
+                .check(changes.owner, parent_dir.depth + 1)?;
+            self.check_sibling_names(directories, parent, name, None)
+                .await?;
+            parent_dir.children.write().await.insert(sid);
+            parent_dir.touch(now).await;
+            directory.depth = parent_dir.depth + 1;
+        } else {
+            self.check_sibling_names(directories, None, name, None)
+                .await?;
+        }
+
+        if directories.insert(sid, directory).is_some() {
+            panic!("Directory sid collision (sid sequence is broken)");
+        }
+        changes.created.push(sid);
+        changes.records.push(
+            DaAuditRecord::new(requested_by, DaAuditOperation::CreateDirectory, sid)
+                .after(json!({ "name": name, "parent_sid": parent })),
+        );
+        changes.events.push((
+            DaChangeEvent::new(
+                sid,
+                changes.owner,
+                requested_by,
+                DaChange::Created {
+                    name: name.to_string(),
+                    parent_sid: parent,
+                },
+            ),
+            visible_through(directories, sid, None),
+            vec![],
+        ));
+        Ok(sid)
+    }
+
+    /// Moves a directory of the tree of `changes.owner` to its trash (see `DaRepository::delete_directory_if_version`).
+    async fn delete_in(
+        &self,
+        directories: &mut BTreeMap<DaDirectorySid, MemDaDirectory>,
+        changes: &mut MemChanges,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        expected_version: Option<u64>,
+    ) -> DaResult<()> {
+        let dir = directories.get(&id).ok_or(DaError::DirectoryNotFound(id))?;
+        check_version(id, expected_version, dir.version())?;
+        let parent_sid = dir.parent_sid;
+        // The subtree can't be walked anymore once removed
+        let grantee_sids = visible_through(directories, id, None);
+        let subtree_sids = descendants_of(directories, id).await;
+
+        let mut to_be_removed = vec![id];
+        let mut removed = BTreeMap::new();
+
+        while let Some(sid) = to_be_removed.pop() {
+            if let Some(dir) = directories.remove(&sid) {
+                to_be_removed.extend(dir.children.read().await.iter());
+                removed.insert(sid, dir);
+            } else {
+                return Err(DaError::DirectoryNotFound(sid));
+            }
+        }
+
+        // Remove the directory from the parent's children list
+        // *note* we're doing this after the loop to ensure that we are able to remove orphaned children
+        let now = Utc::now();
+        if let Some(parent_sid) = parent_sid {
+            let parent_dir = directories
+                .get(&parent_sid)
+                .ok_or(DaError::DirectoryNotFound(parent_sid))?;
+            parent_dir.children.write().await.remove(&id);
+            parent_dir.touch(now).await;
+        }
+
+        let name = removed
+            .get(&id)
+            .ok_or(DaError::DirectoryNotFound(id))?
+            .name
+            .read()
+            .await
+            .clone();
+        changes.trashed.push(MemTrashEntry {
+            entry: DaTrashEntry {
+                sid: id,
+                name,
+                parent_sid,
+                deleted_at: now,
+                deleted_by: requested_by,
+            },
+            directories: removed,
+        });
+        changes.records.push(
+            DaAuditRecord::new(requested_by, DaAuditOperation::DeleteDirectory, id)
+                .before(json!({ "parent_sid": parent_sid })),
+        );
+        changes.events.push((
+            DaChangeEvent::new(
+                id,
+                changes.owner,
+                requested_by,
+                DaChange::Deleted { parent_sid },
+            ),
+            grantee_sids,
+            subtree_sids,
+        ));
+        Ok(())
+    }
+
+    /// Renames a directory of the tree of `changes.owner` (see `DaRepository::rename_directory_if_version`), the name is already normalised.
+    async fn rename_in(
+        &self,
+        directories: &BTreeMap<DaDirectorySid, MemDaDirectory>,
+        changes: &mut MemChanges,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        new_name: &str,
+        expected_version: Option<u64>,
+    ) -> DaResult<()> {
+        let dir = directories.get(&id).ok_or(DaError::DirectoryNotFound(id))?;
+        check_version(id, expected_version, dir.version())?;
+        self.check_sibling_names(directories, dir.parent_sid, new_name, Some(id))
+            .await?;
+        let old_name = std::mem::replace(&mut *dir.name.write().await, new_name.to_string());
+        dir.touch(Utc::now()).await;
+        changes.records.push(
+            DaAuditRecord::new(requested_by, DaAuditOperation::RenameDirectory, id)
+                .before(json!({ "name": old_name }))
+                .after(json!({ "name": new_name })),
+        );
+        changes.events.push((
+            DaChangeEvent::new(
+                id,
+                changes.owner,
+                requested_by,
+                DaChange::Renamed {
+                    name: new_name.to_string(),
+                },
+            ),
+            visible_through(directories, id, None),
+            vec![],
+        ));
+        Ok(())
+    }
+
+    /// Moves a directory of the tree of `changes.owner` under a new parent of the same tree (see `DaRepository::move_directory`).
+    async fn move_in(
+        &self,
+        directories: &mut BTreeMap<DaDirectorySid, MemDaDirectory>,
+        changes: &mut MemChanges,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        parent: Option<DaDirectorySid>,
+    ) -> DaResult<()> {
+        let (old_parent_sid, old_depth) = directories
+            .get(&id)
+            .map(|dir| (dir.parent_sid, dir.depth))
+            .ok_or(DaError::DirectoryNotFound(id))?;
+
+        // Walk up from the new parent to the root, finding the moved directory means that the new parent is one of its descendants
+        let depth = match parent {
+            Some(parent_sid) => {
+                let parent_dir = directories
+                    .get(&parent_sid)
+                    .ok_or(DaError::ParentNotFound(parent_sid))?;
+                let mut ancestor = Some(parent_sid);
+                while let Some(ancestor_sid) = ancestor {
+                    if ancestor_sid == id {
+                        return Err(DaError::DirectoryCycle(id, parent_sid));
+                    }
+                    ancestor = directories
+                        .get(&ancestor_sid)
+                        .and_then(|ancestor_dir| ancestor_dir.parent_sid);
+                }
+                parent_dir.depth + 1
+            }
+            None => 0,
+        };
+
+        // Ensure the deepest directory of the subtree stays within the depth limit once moved
+        let mut max_depth = old_depth;
+        let mut to_be_visited = vec![id];
+        while let Some(sid) = to_be_visited.pop() {
+            let dir = directories
+                .get(&sid)
+                .ok_or(DaError::DirectoryNotFound(sid))?;
+            max_depth = max_depth.max(dir.depth);
+            to_be_visited.extend(dir.children.read().await.iter());
+        }
+        self.config
+            .depth_limit
+            .check(changes.owner, max_depth - old_depth + depth)?;
+        if let Some(dir) = directories.get(&id) {
+            let name = dir.name.read().await.clone();
+            self.check_sibling_names(directories, parent, &name, Some(id))
+                .await?;
+        }
+
+        let now = Utc::now();
+        if let Some(old_parent_dir) = old_parent_sid.and_then(|sid| directories.get(&sid)) {
+            old_parent_dir.children.write().await.remove(&id);
+            old_parent_dir.touch(now).await;
+        }
+        if let Some(parent_dir) = parent.and_then(|sid| directories.get(&sid)) {
+            parent_dir.children.write().await.insert(id);
+            parent_dir.touch(now).await;
+        }
+
+        // Recompute the depth of the whole subtree
+        let mut to_be_updated = vec![(id, depth)];
+        while let Some((sid, depth)) = to_be_updated.pop() {
+            let dir = directories
+                .get_mut(&sid)
+                .ok_or(DaError::DirectoryNotFound(sid))?;
+            if sid == id {
+                dir.parent_sid = parent;
+                dir.touch(now).await;
+            }
+            dir.depth = depth;
+            to_be_updated.extend(
+                dir.children
+                    .read()
+                    .await
+                    .iter()
+                    .map(|child_sid| (*child_sid, depth + 1)),
+            );
+        }
+        changes.records.push(
+            DaAuditRecord::new(requested_by, DaAuditOperation::MoveDirectory, id)
+                .before(json!({ "parent_sid": old_parent_sid }))
+                .after(json!({ "parent_sid": parent })),
+        );
+        changes.events.push((
+            DaChangeEvent::new(
+                id,
+                changes.owner,
+                requested_by,
+                DaChange::Moved {
+                    old_parent_sid,
+                    parent_sid: parent,
+                },
+            ),
+            visible_through(directories, id, old_parent_sid),
+            vec![],
+        ));
+        Ok(())
+    }
+
+    /// Applies the changes of a write to the directories of `bucket`, while the directories are still locked.
+    async fn apply(&self, bucket: &Bucket, changes: MemChanges) {
+        // The directories of a trashed subtree are unregistered, their files and grants are kept until the subtree is purged
+        {
+            let mut owners_lock = self.owners.write().await;
+            for sid in changes.created {
+                owners_lock.insert(sid, changes.owner);
+            }
+            for trashed in &changes.trashed {
+                for sid in trashed.directories.keys() {
+                    owners_lock.remove(sid);
+                }
+            }
+        }
+        if !changes.trashed.is_empty() {
+            let mut trash_lock = bucket.trash.write().await;
+            for trashed in changes.trashed {
+                trash_lock.insert(trashed.entry.sid, trashed);
+            }
+        }
+        for record in changes.records {
+            self.record(bucket, record).await;
+        }
+        if !changes.events.is_empty() {
+            let grants_lock = bucket.grants.read().await;
+            for (event, sids, subtree_sids) in changes.events {
+                self.events.publish(
+                    event
+                        .with_grantees(grantees_in(&grants_lock, sids))
+                        .with_subtree_grantees(grantees_in(&grants_lock, subtree_sids)),
+                );
+            }
+        }
+    }
+
+    /// Applies an operation of a batch to the directories of `changes.owner` (see `DaRepository::apply_batch`),
+    /// after saving the directories it changes in `undo`.
+    async fn apply_operation(
+        &self,
+        directories: &mut BTreeMap<DaDirectorySid, MemDaDirectory>,
+        undo: &mut MemUndoLog,
+        changes: &mut MemChanges,
+        result: &mut DaBatchResult,
+        requested_by: EntityUid,
+        operation: &DaBatchOperation,
+    ) -> DaResult<()> {
+        match operation {
+            DaBatchOperation::Create {
+                temp_id,
+                name,
+                parent,
+            } => {
+                result.check_temp_id(temp_id)?;
+                let name = &self.config.names.normalize(name)?;
+                let parent = result.resolve_opt(parent.as_ref())?;
+                match parent {
+                    Some(parent_sid) => {
+                        self.authorize_in(directories, changes.owner, requested_by, parent_sid)
+                            .await?
+                    }
+                    None => check_batch_owner(changes.owner, requested_by)?,
+                }
+                undo.save(directories, parent).await;
+                let sid = self
+                    .create_in(directories, changes, requested_by, name, parent)
+                    .await?;
+                undo.created(sid);
+                result.created.insert(temp_id.clone(), sid);
+            }
+            DaBatchOperation::Rename { sid, name } => {
+                let name = &self.config.names.normalize(name)?;
+                let sid = result.resolve(sid)?;
+                self.authorize_in(directories, changes.owner, requested_by, sid)
+                    .await?;
+                undo.save(directories, [sid]).await;
+                self.rename_in(directories, changes, requested_by, sid, name, None)
+                    .await?;
+            }
+            DaBatchOperation::Move { sid, parent } => {
+                let sid = result.resolve(sid)?;
+                let parent = result.resolve_opt(parent.as_ref())?;
+                self.authorize_in(directories, changes.owner, requested_by, sid)
+                    .await?;
+                if let Some(parent_sid) = parent {
+                    self.authorize_in(directories, changes.owner, requested_by, parent_sid)
+                        .await?;
+                }
+                undo.save_subtree(directories, sid).await;
+                undo.save(directories, parent).await;
+                self.move_in(directories, changes, requested_by, sid, parent)
+                    .await?;
+            }
+            DaBatchOperation::Delete { sid } => {
+                let sid = result.resolve(sid)?;
+                self.authorize_in(directories, changes.owner, requested_by, sid)
+                    .await?;
+                undo.save_subtree(directories, sid).await;
+                self.delete_in(directories, changes, requested_by, sid, None)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensures that `requested_by` can write in a directory of `owner` changed by a batch.
+    /// The grants are looked up along the ancestors in `directories`, as the batch may have moved the directory.
+    async fn authorize_in(
+        &self,
+        directories: &BTreeMap<DaDirectorySid, MemDaDirectory>,
+        owner: EntityUid,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> DaResult<()> {
+        if !directories.contains_key(&id) {
+            let other = self.owners.read().await.get(&id).copied();
+            return match other {
+                // The access is checked first, so that the directories of other owners are not disclosed
+                Some(other) if other != owner => {
+                    self.authorize(requested_by, id, DaPermission::Write)
+                        .await?;
+                    check_batch_owner(owner, other)
+                }
+                _ => Err(DaError::DirectoryNotFound(id)),
+            };
+        }
+        let bucket = self.bucket(owner).await;
+        let grants_lock = bucket.grants.read().await;
+        if owner != requested_by
+            && !is_granted_in(
+                directories,
+                &grants_lock,
+                requested_by,
+                id,
+                DaPermission::Write,
+            )
+        {
+            return Err(DaError::AccessDenied {
+                requested_by,
+                resource_owner: owner,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Number of levels below `dir` in a fully loaded tree.
+fn subtree_height(dir: &DaDirectory) -> u32 {
+    dir.children
+        .iter()
+        .map(|child| subtree_height(child) + 1)
+        .max()
+        .unwrap_or(0)
 }
 
 impl Bucket {
     pub fn new() -> Self {
         Self {
-            sid_counter: Arc::new(AtomicI64::new(0)),
             directories: Arc::new(RwLock::new(BTreeMap::new())),
+            grants: Arc::new(RwLock::new(HashMap::new())),
+            trash: Arc::new(RwLock::new(BTreeMap::new())),
+            audit: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Whether `uid` is granted `permission` on the directory, either directly or through one of its ancestors.
+    async fn is_granted(
+        &self,
+        uid: EntityUid,
+        id: DaDirectorySid,
+        permission: DaPermission,
+    ) -> bool {
+        let dirs_lock = self.directories.read().await;
+        let grants_lock = self.grants.read().await;
+        is_granted_in(&dirs_lock, &grants_lock, uid, id, permission)
+    }
+}
+
+/// The directories whose grantees may see a change of the directory `id`: its ancestors and itself.
+/// The ancestors of `other` are included as well (the old parent of a moved directory).
+fn visible_through(
+    directories: &BTreeMap<DaDirectorySid, MemDaDirectory>,
+    id: DaDirectorySid,
+    other: Option<DaDirectorySid>,
+) -> Vec<DaDirectorySid> {
+    let mut sids = vec![];
+    for from in [Some(id), other] {
+        let mut current = from;
+        while let Some(sid) = current {
+            sids.push(sid);
+            current = directories.get(&sid).and_then(|dir| dir.parent_sid);
+        }
+    }
+    sids
+}
+
+/// The descendants of the directory `id`, whose grantees only see the sid of `id` when it is deleted.
+async fn descendants_of(
+    directories: &BTreeMap<DaDirectorySid, MemDaDirectory>,
+    id: DaDirectorySid,
+) -> Vec<DaDirectorySid> {
+    let mut sids = vec![];
+    let mut to_be_visited = match directories.get(&id) {
+        Some(dir) => dir.children.read().await.iter().copied().collect(),
+        None => vec![],
+    };
+    while let Some(sid) = to_be_visited.pop() {
+        if let Some(dir) = directories.get(&sid) {
+            sids.push(sid);
+            to_be_visited.extend(dir.children.read().await.iter());
+        }
+    }
+    sids
+}
+
+/// The entities granted access to one of `sids`.
+fn grantees_in(
+    grants: &HashMap<DaDirectorySid, BTreeMap<EntityUid, DaPermission>>,
+    sids: Vec<DaDirectorySid>,
+) -> BTreeSet<EntityUid> {
+    sids.iter()
+        .filter_map(|sid| grants.get(sid))
+        .flat_map(|grants| grants.keys().copied())
+        .collect()
+}
+
+/// Whether `uid` is granted `permission` on a directory of `directories`, either directly or through one of its ancestors.
+fn is_granted_in(
+    directories: &BTreeMap<DaDirectorySid, MemDaDirectory>,
+    grants: &HashMap<DaDirectorySid, BTreeMap<EntityUid, DaPermission>>,
+    uid: EntityUid,
+    id: DaDirectorySid,
+    permission: DaPermission,
+) -> bool {
+    let mut current = Some(id);
+    while let Some(sid) = current {
+        let granted = grants
+            .get(&sid)
+            .and_then(|grants| grants.get(&uid))
+            .is_some_and(|granted| granted.allows(permission));
+        if granted {
+            return true;
         }
+        current = directories.get(&sid).and_then(|dir| dir.parent_sid);
     }
+    false
 }
 
 /// Tests for the memory backend
 /// # Todo
 /// - Add tests for concurrency
-/// - Add tests to ensure that directory are well isolated by user
 #[cfg(test)]
 mod tests {
-    use crate::{entity::StaticEntity, prelude::*};
+    use crate::{da_repository::suite, entity::StaticEntity, prelude::*};
 
     #[tokio::test]
     async fn test_directory_creation_basic() {
@@ -318,4 +2122,6 @@ mod tests {
             .await
             .is_err());
     }
+
+    suite::backend_suite!(#[tokio::test] () => MemRepository::new());
 }
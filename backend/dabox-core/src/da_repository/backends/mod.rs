@@ -1,4 +1,10 @@
 pub mod mem;
 
+#[cfg(feature = "sqlx")]
+pub(crate) mod sql;
+
 #[cfg(feature = "database")]
 pub mod pg;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
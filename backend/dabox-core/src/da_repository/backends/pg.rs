@@ -1,46 +1,200 @@
-/// This is a placeholder for the Postgres-backed implementation of the `DaRepositoryExt` trait.
+//! A Postgres-backed implementation of the `DaRepository` trait.
+//! Directories are stored in a single `directories` table keyed by `(owner, sid)`, descendants are resolved with a recursive query
+//! and purging a subtree from the trash relies on the `ON DELETE CASCADE` foreign key of `parent_sid`.
+use sqlx::migrate::Migrator;
 use sqlx::PgPool;
 
+use super::sql::{sql_repository, SqlDaSearchResult, FUZZY_SEARCH_QUERY};
+use crate::da_search::fold_name;
 use crate::prelude::*;
 
-/// A Postgres-backed implementation of the `DaRepositoryExt` trait.
+/// Embedded migrations of the Postgres backend.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations/pg");
+
+/// A Postgres-backed implementation of the `DaRepository` trait.
 /// Able to handle a lot of concurrent requests based on the underlying Postgres server.
-#[allow(dead_code)]
 #[derive(Clone)]
 pub struct PgRepository {
     pool: PgPool,
+    blob_store: Arc<dyn DaBlobStore>,
+    config: Arc<DaRepositoryConfig>,
+    events: DaEventBus,
 }
 
 impl PgRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            blob_store: Arc::new(MemBlobStore::new()),
+            config: Arc::new(DaRepositoryConfig::default()),
+            events: DaEventBus::default(),
+        }
     }
-}
 
-impl DaRepository for PgRepository {
-    async fn create_directory(
-        &self,
-        _uid: EntityUid,
-        _name: &str,
-        _parent: Option<DaDirectorySid>,
-    ) -> DaResult<DaDirectory> {
-        todo!()
+    pub fn with_config(mut self, config: DaRepositoryConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Stores the content of the files in `blob_store` (contents are kept in memory by default).
+    pub fn with_blob_store(mut self, blob_store: Arc<dyn DaBlobStore>) -> Self {
+        self.blob_store = blob_store;
+        self
+    }
+
+    /// Publishes the changes on `events` instead of a bus of its own.
+    pub fn with_event_bus(mut self, events: DaEventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Connects to the database at `url` and applies the pending migrations.
+    pub async fn connect(url: &str) -> DaResult<Self> {
+        let repository = Self::new(PgPool::connect(url).await?);
+        repository.migrate().await?;
+        Ok(repository)
     }
 
-    async fn get_directory(&self, _uid: EntityUid, _sid: DaDirectorySid) -> DaResult<DaDirectory> {
-        todo!()
+    /// Applies the embedded migrations that are not yet applied to the database.
+    pub async fn migrate(&self) -> DaResult<()> {
+        MIGRATOR.run(&self.pool).await?;
+        self.backfill_search_names().await
     }
 
-    async fn delete_directory(&self, _uid: EntityUid, _sid: DaDirectorySid) -> DaResult<()> {
-        todo!()
+    /// Begins the transaction of a write, the rows and trees it changes are locked as it goes.
+    async fn begin_write(&self) -> DaResult<sqlx::Transaction<'static, sqlx::Postgres>> {
+        Ok(self.pool.begin().await?)
     }
 
-    async fn rename_directory(
+    /// Ranks the directories whose name is similar to the text of a fuzzy search with `pg_trgm` (see `FUZZY_SEARCH_QUERY`).
+    /// Returns up to `limit + 1` results after the cursor, without their path.
+    async fn fuzzy_search(
         &self,
-        _uid: EntityUid,
-        _sid: DaDirectorySid,
-        _new_name: &str,
-    ) -> DaResult<()> {
-        todo!()
+        requested_by: EntityUid,
+        query: &DaSearchQuery,
+        cursor: Option<&DaCursor>,
+        limit: u32,
+    ) -> DaResult<Vec<DaSearchResult>> {
+        let last_score = cursor.map(DaCursor::last_score).transpose()?;
+        let rows: Vec<SqlDaSearchResult> = sqlx::query_as(FUZZY_SEARCH_QUERY)
+            .bind(requested_by as i64)
+            .bind(fold_name(&query.text))
+            .bind(query.scope)
+            .bind(cursor.map(DaCursor::last_sid))
+            .bind(last_score)
+            .bind(limit as i64 + 1)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(DaSearchResult::from).collect())
+    }
+}
+
+sql_repository!(PgRepository, sqlx::Postgres, for_update: " FOR UPDATE");
+
+/// The first key of the advisory locks taken by dabox (`dabx` in ASCII), it sets them apart from the locks of other
+/// applications sharing the database.
+const OWNER_LOCK_CLASS: i32 = 0x6461_6278;
+
+/// Takes a transaction scoped advisory lock on the tree of `owner`.
+/// Operations that restructure the tree (e.g. moves) take it exclusively, so that concurrent moves can't create a cycle together
+/// and that a directory can't be created under a parent whose depth is being updated.
+///
+/// The lock is keyed by `OWNER_LOCK_CLASS` and the owner folded into 32 bits (the two-key form only takes `int4` keys),
+/// owners that share a key only wait for each other.
+async fn lock_owner(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    owner: EntityUid,
+    exclusive: bool,
+) -> DaResult<()> {
+    let query = if exclusive {
+        "SELECT pg_advisory_xact_lock($1, $2)"
+    } else {
+        "SELECT pg_advisory_xact_lock_shared($1, $2)"
+    };
+    sqlx::query(query)
+        .bind(OWNER_LOCK_CLASS)
+        .bind((owner ^ (owner >> 32)) as u32 as i32)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Draws `count` new directory sids from the `directories_sid_seq` sequence.
+async fn allocate_sids(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    count: usize,
+) -> DaResult<Vec<DaDirectorySid>> {
+    let sids =
+        sqlx::query_scalar("SELECT nextval('directories_sid_seq') FROM generate_series(1, $1)")
+            .bind(count as i64)
+            .fetch_all(&mut **tx)
+            .await?;
+    Ok(sids)
+}
+
+/// Tests for the Postgres backend
+/// They require a reachable Postgres server, the `DATABASE_URL` environment variable must point to it.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::da_repository::backends::sql::VALUES_BATCH;
+    use crate::da_repository::suite;
+
+    suite::backend_suite!(#[sqlx::test(migrations = "./migrations/pg")] (pool: PgPool) => PgRepository::new(pool));
+
+    #[sqlx::test(migrations = "./migrations/pg")]
+    async fn test_concurrent_batches(pool: PgPool) {
+        let repo = PgRepository::new(pool);
+        let owner = 1;
+        let root = repo.create_directory(owner, "root", None).await.unwrap();
+        let target = repo.create_directory(owner, "target", None).await.unwrap();
+
+        // Each batch creates a directory and then restructures the tree, the concurrent batches must all succeed
+        let batches = (0..8).map(|idx| {
+            let repo = repo.clone();
+            let batch: Vec<DaBatchOperation> = serde_json::from_value(serde_json::json!([
+                { "op": "create", "temp_id": "a", "name": format!("a-{idx}"), "parent": root.sid },
+                { "op": "move", "sid": "a", "parent": target.sid },
+            ]))
+            .unwrap();
+            tokio::spawn(async move { repo.apply_batch(owner, &batch).await })
+        });
+        for batch in futures::future::join_all(batches).await {
+            batch.unwrap().unwrap();
+        }
+        let target = repo.get_directory(owner, target.sid).await.unwrap();
+        assert_eq!(target.children.len(), 8);
+    }
+
+    #[sqlx::test(migrations = "./migrations/pg")]
+    async fn test_migrate_folds_search_names(pool: PgPool) {
+        let repo = PgRepository::new(pool.clone());
+        let dir = repo.create_directory(1, "Été", None).await.unwrap();
+        // Enough directories to take more than one batch of the backfill
+        let operations: Vec<_> = (0..VALUES_BATCH)
+            .map(|idx| DaBatchOperation::Create {
+                temp_id: idx.to_string(),
+                name: format!("Dir {idx}"),
+                parent: Some(DaBatchRef::Sid(dir.sid)),
+            })
+            .collect();
+        repo.apply_batch(1, &operations).await.unwrap();
+        sqlx::query("UPDATE directories SET search_name = NULL")
+            .execute(&pool)
+            .await
+            .unwrap();
+        repo.migrate().await.unwrap();
+        let unfolded: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM directories WHERE search_name IS NULL")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(unfolded, 0);
+        let query = DaSearchQuery::new("été", DaSearchMode::Prefix);
+        let page = repo.search_directories(1, query, None, 10).await.unwrap();
+        assert_eq!(page.items[0].sid, dir.sid);
+        let query = DaSearchQuery::new("DIR 999", DaSearchMode::Prefix);
+        let page = repo.search_directories(1, query, None, 10).await.unwrap();
+        assert_eq!(page.items[0].path, vec!["Été", "Dir 999"]);
     }
 }
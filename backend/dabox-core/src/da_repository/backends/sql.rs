@@ -0,0 +1,2204 @@
+//! Shared helpers of the SQL backends (Postgres and SQLite).
+//! Both backends store directories in a flat `directories` table, this module turns its rows back into `DaDirectory` trees
+//! and implements `DaRepository` once for both (see `sql_repository`).
+use sqlx::FromRow;
+
+use crate::prelude::*;
+
+/// A row of the `directories` table.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct SqlDaDirectory {
+    /// Unique identifier for the directory
+    pub sid: DaDirectorySid,
+    /// Name of the directory
+    pub name: String,
+    /// Parent directory unique identifier (none if the directory is the root)
+    pub parent_sid: Option<DaDirectorySid>,
+    /// The depth of the directory in the hierarchy
+    pub depth: i32,
+    /// Whether the directory has children beyond the loaded depth (only computed by depth-limited reads)
+    #[sqlx(default)]
+    pub has_unloaded_children: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub version: i64,
+    pub created_by: i64,
+    /// The tags of the directory separated by `TAG_SEPARATOR` (see `TAGS_COLUMN`), null if it has none
+    #[sqlx(default)]
+    pub tags: Option<String>,
+}
+
+impl From<SqlDaDirectory> for DaDirectory {
+    fn from(row: SqlDaDirectory) -> Self {
+        DaDirectory {
+            sid: row.sid,
+            name: row.name,
+            parent_sid: row.parent_sid,
+            children: vec![],
+            depth: row.depth as u32,
+            has_unloaded_children: row.has_unloaded_children,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            version: row.version as u64,
+            created_by: row.created_by as EntityUid,
+            tags: row
+                .tags
+                .map(|tags| tags.split(TAG_SEPARATOR).map(String::from).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Separates the tags of the `tags` column, tags can't contain control characters.
+pub(crate) const TAG_SEPARATOR: char = '\n';
+
+/// The `tags` column of the directory `d` of the owner `$1`, its tags in alphabetical order separated by `TAG_SEPARATOR`
+/// (the `\n` of the literal is a newline in the query).
+/// Valid in both Postgres and SQLite.
+pub(crate) const TAGS_COLUMN: &str = "(
+        SELECT string_agg(t.tag, '\n' ORDER BY t.tag) FROM directory_tags t WHERE t.owner = $1 AND t.sid = d.sid
+    ) AS tags";
+
+/// Assembles the flat rows of a subtree into a `DaDirectory` rooted at `root`.
+/// Returns `None` if `root` is not part of the rows.
+pub(crate) fn build_tree(root: DaDirectorySid, rows: Vec<SqlDaDirectory>) -> Option<DaDirectory> {
+    let mut by_parent: HashMap<DaDirectorySid, Vec<SqlDaDirectory>> = HashMap::new();
+    let mut root_row = None;
+    for row in rows {
+        if row.sid == root {
+            root_row = Some(row);
+        } else if let Some(parent_sid) = row.parent_sid {
+            by_parent.entry(parent_sid).or_default().push(row);
+        }
+    }
+
+    fn assemble(
+        row: SqlDaDirectory,
+        by_parent: &mut HashMap<DaDirectorySid, Vec<SqlDaDirectory>>,
+    ) -> DaDirectory {
+        let children = by_parent
+            .remove(&row.sid)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|child| assemble(child, by_parent))
+            .collect();
+        DaDirectory {
+            children,
+            ..row.into()
+        }
+    }
+
+    root_row.map(|row| assemble(row, &mut by_parent))
+}
+
+/// Selects the entities granted access to the directory `$2` of the owner `$1` or to one of its ancestors,
+/// and to the ancestors of the directory `$3` if not null (the old parent of a moved directory).
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) const GRANTEES_QUERY: &str = "WITH RECURSIVE ancestors AS (
+        SELECT sid, parent_sid FROM directories WHERE owner = $1 AND (sid = $2 OR sid = $3)
+        UNION ALL
+        SELECT d.sid, d.parent_sid FROM directories d JOIN ancestors a ON d.owner = $1 AND d.sid = a.parent_sid
+    )
+    SELECT DISTINCT grantee FROM directory_grants
+    WHERE owner = $1 AND sid IN (SELECT sid FROM ancestors)";
+
+/// Selects the entities granted access to one of the descendants of the directory `$2` of the owner `$1`, trashed directories aside.
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) const SUBTREE_GRANTEES_QUERY: &str = "WITH RECURSIVE subtree AS (
+        SELECT sid FROM directories WHERE owner = $1 AND parent_sid = $2 AND NOT trashed
+        UNION ALL
+        SELECT d.sid FROM directories d JOIN subtree s ON d.owner = $1 AND d.parent_sid = s.sid AND NOT d.trashed
+    )
+    SELECT DISTINCT grantee FROM directory_grants
+    WHERE owner = $1 AND sid IN (SELECT sid FROM subtree)";
+
+/// Resolves the owner of the directory `$1` (none if it is in the trash) and whether `$2` was granted the permission `$3` on it or on one of its ancestors.
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) const ACCESS_QUERY: &str = "WITH RECURSIVE ancestors AS (
+        SELECT owner, sid, parent_sid FROM directories WHERE sid = $1
+        UNION ALL
+        SELECT d.owner, d.sid, d.parent_sid
+        FROM directories d JOIN ancestors a ON d.owner = a.owner AND d.sid = a.parent_sid
+    )
+    SELECT
+        (SELECT owner FROM directories WHERE sid = $1 AND NOT trashed),
+        EXISTS (
+            SELECT 1 FROM directory_grants g JOIN ancestors a ON g.owner = a.owner AND g.sid = a.sid
+            WHERE g.grantee = $2 AND (g.permission = 'write' OR $3 = 'read')
+        )";
+
+/// Turns the result of `ACCESS_QUERY` into the owner of the directory, or the reason why access is refused.
+pub(crate) fn access_decision(
+    requested_by: EntityUid,
+    id: DaDirectorySid,
+    owner: Option<i64>,
+    granted: bool,
+) -> DaResult<EntityUid> {
+    let owner = owner.ok_or(DaError::DirectoryNotFound(id))? as EntityUid;
+    if owner != requested_by && !granted {
+        return Err(DaError::AccessDenied {
+            requested_by,
+            resource_owner: owner,
+        });
+    }
+    Ok(owner)
+}
+
+/// Selects the directories named `$3` under the parent `$2` (the root directories if null) of the owner `$1`.
+/// At most two rows are returned, which is enough to tell that a name is ambiguous.
+pub(crate) const PATH_SEGMENT_QUERY: &str = "SELECT sid FROM directories
+    WHERE owner = $1 AND ((parent_sid IS NULL AND $2 IS NULL) OR parent_sid = $2) AND name = $3 AND NOT trashed
+    ORDER BY sid LIMIT 2";
+
+/// A row of the `directory_grants` table.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct SqlDaGrant {
+    pub sid: DaDirectorySid,
+    pub grantee: i64,
+    pub permission: String,
+}
+
+impl From<SqlDaGrant> for DaGrant {
+    fn from(row: SqlDaGrant) -> Self {
+        DaGrant {
+            sid: row.sid,
+            grantee: row.grantee as EntityUid,
+            // The `permission` column is constrained to the known permissions
+            permission: row.permission.parse().unwrap_or(DaPermission::Read),
+        }
+    }
+}
+
+/// Builds the query listing the root directories of the owner `$1` in the order of `sort`.
+/// `$2` and `$3` are the sid and name of the cursor (both null for the first page), `$4` is the maximum number of rows.
+pub(crate) fn root_directories_query(sort: DaSort) -> String {
+    let (after, direction) = match sort.order {
+        DaSortOrder::Asc => (">", "ASC"),
+        DaSortOrder::Desc => ("<", "DESC"),
+    };
+    let (after_cursor, order_by) = match sort.key {
+        DaSortKey::Sid => (format!("sid {after} $2"), format!("sid {direction}")),
+        DaSortKey::Name => (
+            format!("(name, sid) {after} ($3, $2)"),
+            format!("name {direction}, sid {direction}"),
+        ),
+    };
+    format!(
+        "SELECT sid, name, parent_sid, depth, created_at, updated_at, version, created_by,
+            EXISTS (
+                SELECT 1 FROM directories c WHERE c.owner = $1 AND c.parent_sid = d.sid
+            ) AS has_unloaded_children,
+            {TAGS_COLUMN}
+        FROM directories d
+        WHERE owner = $1 AND parent_sid IS NULL AND NOT trashed AND ($2 IS NULL OR {after_cursor})
+        ORDER BY {order_by} LIMIT $4"
+    )
+}
+
+/// Counts the directories of the owner `$1` carrying each tag, trashed directories aside.
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) const TAG_COUNTS_QUERY: &str = "SELECT t.tag, COUNT(*) FROM directory_tags t
+    JOIN directories d ON d.owner = t.owner AND d.sid = t.sid
+    WHERE t.owner = $1 AND NOT d.trashed
+    GROUP BY t.tag ORDER BY t.tag";
+
+/// Selects the tags of the directory `$2` of the owner `$1` in alphabetical order.
+pub(crate) const DIRECTORY_TAGS_QUERY: &str =
+    "SELECT tag FROM directory_tags WHERE owner = $1 AND sid = $2 ORDER BY tag";
+
+/// Builds the query listing the directories of the owner `$1` carrying at least `$4` of the `tag_count` tags bound from `$5`
+/// (`tag_count` to carry them all, 1 to carry any of them), trashed directories aside.
+/// `$2` is the sid of the cursor (null for the first page) and `$3` the maximum number of rows.
+pub(crate) fn tagged_directories_query(tag_count: usize) -> String {
+    let tags = (0..tag_count)
+        .map(|i| format!("${}", i + 5))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "SELECT sid, name, parent_sid, depth, created_at, updated_at, version, created_by,
+            EXISTS (
+                SELECT 1 FROM directories c WHERE c.owner = $1 AND c.parent_sid = d.sid
+            ) AS has_unloaded_children,
+            {TAGS_COLUMN}
+        FROM directories d
+        WHERE owner = $1 AND NOT trashed AND ($2 IS NULL OR sid > $2) AND sid IN (
+            SELECT sid FROM directory_tags WHERE owner = $1 AND tag IN ({tags})
+            GROUP BY sid HAVING COUNT(*) >= $4
+        )
+        ORDER BY sid LIMIT $3"
+    )
+}
+
+/// Selects the ancestors of the directory `$2` of the owner `$1` from the root down,
+/// with whether `$3` was granted a permission on each of them.
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) const ANCESTORS_QUERY: &str = "WITH RECURSIVE ancestors AS (
+        SELECT sid, parent_sid, name, depth FROM directories WHERE owner = $1 AND sid = $2
+        UNION ALL
+        SELECT d.sid, d.parent_sid, d.name, d.depth
+        FROM directories d JOIN ancestors a ON d.owner = $1 AND d.sid = a.parent_sid
+    )
+    SELECT sid, name, EXISTS (
+            SELECT 1 FROM directory_grants g WHERE g.owner = $1 AND g.sid = ancestors.sid AND g.grantee = $3
+        ) AS granted
+    FROM ancestors WHERE sid <> $2 ORDER BY depth";
+
+/// Searches the directories of the owner `$1` whose folded name matches the `LIKE` pattern `$2`, limited to the descendants
+/// of `$3` unless it is null. `$4` is the sid of the cursor (null for the first page) and `$5` the maximum number of rows.
+/// The directories are filtered by name first (see the `directories_search_name_idx` index in Postgres), only a scoped search
+/// walks the subtree of its scope. The placeholders are valid in both Postgres and SQLite.
+pub(crate) const SEARCH_QUERY: &str = "WITH RECURSIVE scope AS (
+        SELECT sid FROM directories WHERE owner = $1 AND sid = $3
+        UNION ALL
+        SELECT d.sid FROM directories d JOIN scope s ON d.owner = $1 AND d.parent_sid = s.sid
+    )
+    SELECT sid, name, parent_sid, depth FROM directories
+    WHERE owner = $1 AND NOT trashed AND search_name LIKE $2 ESCAPE '\\' AND ($4 IS NULL OR sid > $4)
+        AND ($3 IS NULL OR (sid <> $3 AND sid IN (SELECT sid FROM scope)))
+    ORDER BY sid LIMIT $5";
+
+/// Searches the directories of the owner `$1` whose folded name is similar to the folded text `$2` (`pg_trgm`'s `%` operator),
+/// limited to the descendants of `$3` unless it is null, by decreasing similarity then by sid.
+/// `$4` and `$5` are the sid and score of the cursor (both null for the first page), `$6` is the maximum number of rows.
+/// Postgres only, SQLite scores the names itself (see `DaSearchQuery::score`).
+#[cfg(feature = "database")]
+pub(crate) const FUZZY_SEARCH_QUERY: &str = "WITH RECURSIVE scope AS (
+        SELECT sid FROM directories WHERE owner = $1 AND sid = $3
+        UNION ALL
+        SELECT d.sid FROM directories d JOIN scope s ON d.owner = $1 AND d.parent_sid = s.sid
+    ), matches AS (
+        SELECT sid, name, parent_sid, depth, similarity(search_name, $2) AS score FROM directories
+        WHERE owner = $1 AND NOT trashed AND search_name % $2
+            AND ($3 IS NULL OR (sid <> $3 AND sid IN (SELECT sid FROM scope)))
+    )
+    SELECT sid, name, parent_sid, depth, score FROM matches
+    WHERE $4 IS NULL OR score < $5 OR (score = $5 AND sid > $4)
+    ORDER BY score DESC, sid LIMIT $6";
+
+/// Number of rows bound as a `VALUES` list by each statement of a chunked write (the `search_name` backfill, the copies of a subtree),
+/// at most 3 placeholders each.
+pub(crate) const VALUES_BATCH: usize = 1000;
+
+/// The `VALUES` list of `row_count` rows of `columns` placeholders each, numbered from `$first`.
+fn values_list(row_count: usize, columns: usize, first: usize) -> String {
+    (0..row_count)
+        .map(|row| {
+            let placeholders = (0..columns)
+                .map(|column| format!("${}", first + row * columns + column))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("({placeholders})")
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Builds the statement setting the folded names of `row_count` directories, bound from `$1` as (owner, sid, folded name) triples.
+/// The placeholders are valid in both Postgres and SQLite (which name the columns of `VALUES` alike).
+pub(crate) fn search_names_update(row_count: usize) -> String {
+    let values = values_list(row_count, 3, 1);
+    format!(
+        "UPDATE directories SET search_name = v.column3 FROM (VALUES {values}) AS v
+        WHERE directories.owner = v.column1 AND directories.sid = v.column2"
+    )
+}
+
+/// Selects the directory `$2` of the owner `$1` and its descendants as (sid, parent sid, depth), parents before their children.
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) const COPY_SUBTREE_QUERY: &str = "WITH RECURSIVE subtree AS (
+        SELECT sid, parent_sid, depth FROM directories WHERE owner = $1 AND sid = $2
+        UNION ALL
+        SELECT d.sid, d.parent_sid, d.depth FROM directories d JOIN subtree s ON d.owner = $1 AND d.parent_sid = s.sid
+    )
+    SELECT sid, parent_sid, depth FROM subtree ORDER BY depth, sid";
+
+/// Builds the statement copying `row_count` directories of the owner `$2` to the owner `$1`, bound from `$9` as
+/// (sid, sid of the copy, parent of the copy) triples. The copy of the directory `$3` is named `$4` (folded `$5`),
+/// the depth of the copies is shifted by `$6`, `$7` is the time of the copy and `$8` the entity copying.
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) fn copy_directories_insert(row_count: usize) -> String {
+    let values = values_list(row_count, 3, 9);
+    format!(
+        "INSERT INTO directories (owner, sid, name, search_name, parent_sid, depth, created_at, updated_at, created_by)
+        SELECT $1, v.column2,
+            CASE WHEN s.sid = $3 THEN $4 ELSE s.name END,
+            CASE WHEN s.sid = $3 THEN $5 ELSE s.search_name END,
+            v.column3, s.depth + $6, $7, $7, $8
+        FROM (VALUES {values}) AS v JOIN directories s ON s.owner = $2 AND s.sid = v.column1"
+    )
+}
+
+/// Builds the statement copying the tags of `row_count` directories of the owner `$2` to their copies of the owner `$1`,
+/// bound from `$3` as (sid, sid of the copy) pairs. The placeholders are valid in both Postgres and SQLite.
+pub(crate) fn copy_tags_insert(row_count: usize) -> String {
+    let values = values_list(row_count, 2, 3);
+    format!(
+        "INSERT INTO directory_tags (owner, sid, tag)
+        SELECT $1, v.column2, t.tag
+        FROM (VALUES {values}) AS v JOIN directory_tags t ON t.owner = $2 AND t.sid = v.column1"
+    )
+}
+
+/// A row of `SEARCH_QUERY` or `FUZZY_SEARCH_QUERY`.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct SqlDaSearchResult {
+    pub sid: DaDirectorySid,
+    pub name: String,
+    pub parent_sid: Option<DaDirectorySid>,
+    pub depth: i32,
+    /// The similarity of the name to the text of a fuzzy search
+    #[sqlx(default)]
+    pub score: Option<f32>,
+}
+
+impl From<SqlDaSearchResult> for DaSearchResult {
+    /// The result without the ancestors of the directory in its path.
+    fn from(row: SqlDaSearchResult) -> Self {
+        DaSearchResult {
+            sid: row.sid,
+            name: row.name.clone(),
+            parent_sid: row.parent_sid,
+            depth: row.depth as u32,
+            path: vec![row.name],
+            score: row.score,
+        }
+    }
+}
+
+/// Builds the query selecting the names on the path of the `sid_count` directories of the owner `$1` bound from `$2`,
+/// with the sid of the directory whose path they are on, each path from the root down to the directory itself.
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) fn search_paths_query(sid_count: usize) -> String {
+    let sids = (0..sid_count)
+        .map(|i| format!("${}", i + 2))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "WITH RECURSIVE paths AS (
+            SELECT sid AS result_sid, parent_sid, name, depth FROM directories WHERE owner = $1 AND sid IN ({sids})
+            UNION ALL
+            SELECT p.result_sid, d.parent_sid, d.name, d.depth
+            FROM directories d JOIN paths p ON d.owner = $1 AND d.sid = p.parent_sid
+        )
+        SELECT result_sid, name FROM paths ORDER BY result_sid, depth"
+    )
+}
+
+/// Sets the paths of search results given the rows of `search_paths_query` for their sids.
+pub(crate) fn set_search_paths(
+    results: &mut [DaSearchResult],
+    rows: Vec<(DaDirectorySid, String)>,
+) {
+    let mut paths: HashMap<DaDirectorySid, Vec<String>> = HashMap::new();
+    for (sid, name) in rows {
+        paths.entry(sid).or_default().push(name);
+    }
+    for result in results {
+        if let Some(path) = paths.remove(&result.sid) {
+            result.path = path;
+        }
+    }
+}
+
+/// A row of the `files` table.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct SqlDaFile {
+    pub sid: DaFileSid,
+    pub directory_sid: DaDirectorySid,
+    pub name: String,
+    pub size: i64,
+    pub content_type: String,
+    pub content_hash: String,
+    /// Key of the content in the blob store
+    pub blob_key: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub created_by: i64,
+}
+
+impl From<SqlDaFile> for DaFile {
+    fn from(row: SqlDaFile) -> Self {
+        DaFile {
+            sid: row.sid,
+            directory_sid: row.directory_sid,
+            name: row.name,
+            size: row.size as u64,
+            content_type: row.content_type,
+            content_hash: row.content_hash,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            created_by: row.created_by as EntityUid,
+        }
+    }
+}
+
+/// Selects the blob keys of the files of the directory `$2` of the owner `$1` and of its descendants.
+/// The placeholders are valid in both Postgres and SQLite.
+pub(crate) const SUBTREE_BLOB_KEYS_QUERY: &str = "WITH RECURSIVE subtree AS (
+        SELECT sid FROM directories WHERE owner = $1 AND sid = $2
+        UNION ALL
+        SELECT d.sid FROM directories d JOIN subtree s ON d.owner = $1 AND d.parent_sid = s.sid
+    )
+    SELECT blob_key FROM files WHERE directory_sid IN (SELECT sid FROM subtree)";
+
+/// Selects the names of the directories under the parent `$2` (the root directories if null) of the owner `$1`, except the directory `$3`.
+/// Names are compared by the repository so that the case-insensitive policy behaves the same in every backend.
+pub(crate) const SIBLING_NAMES_QUERY: &str = "SELECT name FROM directories
+    WHERE owner = $1 AND ((parent_sid IS NULL AND $2 IS NULL) OR parent_sid = $2)
+        AND ($3 IS NULL OR sid <> $3) AND NOT trashed";
+
+/// A row of the `trash` table joined with the name of the deleted directory.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct SqlDaTrashEntry {
+    pub sid: DaDirectorySid,
+    pub name: String,
+    pub parent_sid: Option<DaDirectorySid>,
+    pub deleted_at: DateTime<Utc>,
+    pub deleted_by: i64,
+}
+
+impl From<SqlDaTrashEntry> for DaTrashEntry {
+    fn from(row: SqlDaTrashEntry) -> Self {
+        DaTrashEntry {
+            sid: row.sid,
+            name: row.name,
+            parent_sid: row.parent_sid,
+            deleted_at: row.deleted_at,
+            deleted_by: row.deleted_by as EntityUid,
+        }
+    }
+}
+
+/// Lists the trash of the owner `$1` ordered by sid, `$2` is the sid of the cursor (null for the first page) and `$3` the maximum number of rows.
+pub(crate) const TRASH_QUERY: &str =
+    "SELECT t.sid, d.name, t.parent_sid, t.deleted_at, t.deleted_by
+    FROM trash t JOIN directories d ON d.owner = t.owner AND d.sid = t.sid
+    WHERE t.owner = $1 AND ($2 IS NULL OR t.sid > $2)
+    ORDER BY t.sid LIMIT $3";
+
+/// Flags the directory `$2` of the owner `$1` and its descendants as trashed.
+pub(crate) const TRASH_SUBTREE_QUERY: &str = "WITH RECURSIVE subtree AS (
+        SELECT sid FROM directories WHERE owner = $1 AND sid = $2
+        UNION ALL
+        SELECT d.sid FROM directories d JOIN subtree s ON d.owner = $1 AND d.parent_sid = s.sid
+    )
+    UPDATE directories SET trashed = TRUE WHERE owner = $1 AND sid IN (SELECT sid FROM subtree)";
+
+/// Clears the trashed flag of the directory `$2` of the owner `$1` and its descendants, shifting their depth by `$3`.
+pub(crate) const RESTORE_SUBTREE_QUERY: &str = "WITH RECURSIVE subtree AS (
+        SELECT sid FROM directories WHERE owner = $1 AND sid = $2
+        UNION ALL
+        SELECT d.sid FROM directories d JOIN subtree s ON d.owner = $1 AND d.parent_sid = s.sid
+    )
+    UPDATE directories SET trashed = FALSE, depth = depth + $3
+    WHERE owner = $1 AND sid IN (SELECT sid FROM subtree)";
+
+/// Selects the maximum depth of the directory `$2` of the owner `$1` and its descendants.
+pub(crate) const SUBTREE_MAX_DEPTH_QUERY: &str = "WITH RECURSIVE subtree AS (
+        SELECT sid, depth FROM directories WHERE owner = $1 AND sid = $2
+        UNION ALL
+        SELECT d.sid, d.depth FROM directories d JOIN subtree s ON d.owner = $1 AND d.parent_sid = s.sid
+    )
+    SELECT MAX(depth) FROM subtree";
+
+/// Appends an event to the audit log, the placeholders are the owner, entity, operation, sid, file sid,
+/// old and new values (JSON text) and time of the event.
+pub(crate) const AUDIT_INSERT_QUERY: &str = "INSERT INTO audit_log
+        (owner, entity, operation, sid, file_sid, old_value, new_value, at)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+
+/// Lists the audit log of the owner `$1` ordered by id, filtered by the sid `$2`, the time range [`$3`, `$4`)
+/// (unset criteria are null), `$5` is the id of the cursor (null for the first page) and `$6` the maximum number of rows.
+pub(crate) const AUDIT_QUERY: &str =
+    "SELECT id, entity, operation, sid, file_sid, old_value, new_value, at
+    FROM audit_log
+    WHERE owner = $1 AND ($2 IS NULL OR sid = $2) AND ($3 IS NULL OR at >= $3) AND ($4 IS NULL OR at < $4)
+        AND ($5 IS NULL OR id > $5)
+    ORDER BY id LIMIT $6";
+
+/// A row of the `audit_log` table.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct SqlDaAuditEvent {
+    pub id: i64,
+    pub entity: Option<i64>,
+    pub operation: String,
+    pub sid: DaDirectorySid,
+    pub file_sid: Option<DaFileSid>,
+    /// JSON text
+    pub old_value: Option<String>,
+    /// JSON text
+    pub new_value: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+impl TryFrom<SqlDaAuditEvent> for DaAuditEvent {
+    type Error = DaError;
+
+    fn try_from(row: SqlDaAuditEvent) -> DaResult<Self> {
+        let decode_error = |e: Box<dyn std::error::Error + Send + Sync>| {
+            DaError::SqlxError(sqlx::Error::Decode(e))
+        };
+        let parse_value = |value: Option<String>| {
+            value
+                .map(|value| serde_json::from_str(&value))
+                .transpose()
+                .map_err(|e| decode_error(e.into()))
+        };
+        Ok(DaAuditEvent {
+            id: row.id,
+            at: row.at,
+            entity: row.entity.map(|entity| entity as EntityUid),
+            operation: row
+                .operation
+                .parse()
+                .map_err(|e: String| decode_error(e.into()))?,
+            sid: row.sid,
+            file_sid: row.file_sid,
+            old_value: parse_value(row.old_value)?,
+            new_value: parse_value(row.new_value)?,
+        })
+    }
+}
+
+/// Implements `DaRepository` for `$repository`, a SQL backend over the `$db` database, in a `repository` child module.
+/// Both backends share the schema and the queries of this module, only a few steps differ and are left to the invoking module:
+/// - `$repository` has the `pool`, `blob_store`, `config` and `events` fields;
+/// - `$repository::begin_write` begins the transaction of a write, `$repository::fuzzy_search` ranks the results of a fuzzy search
+///   (up to `limit + 1` of them after the cursor, without their path);
+/// - `lock_owner(tx, owner, exclusive)` locks the tree of `owner` for the rest of the transaction;
+/// - `allocate_sids(tx, count)` draws `count` new directory sids.
+///
+/// `$for_update` is appended to the queries that lock the rows they read, empty if the database has no row locks.
+macro_rules! sql_repository {
+    ($repository:ident, $db:ty, for_update: $for_update:literal) => {
+        mod repository {
+            use serde_json::json;
+
+            use super::{allocate_sids, lock_owner, $repository};
+            use $crate::da_audit::DaAuditRecord;
+            use $crate::da_batch::check_batch_owner;
+            use $crate::da_blob::{delete_blobs, DaBlob};
+            use $crate::da_repository::backends::sql::*;
+            use $crate::da_repository::{check_version, path_prefix, readable_ancestors};
+            use $crate::da_search::fold_name;
+            use $crate::da_tag::normalize_tag;
+            use $crate::prelude::*;
+
+            impl $repository {
+                /// Folds the names of the directories created before the `search_name` column, in batches all at once.
+                pub(super) async fn backfill_search_names(&self) -> DaResult<()> {
+                    let mut tx = self.begin_write().await?;
+                    let unfolded: Vec<(i64, DaDirectorySid, String)> =
+                        sqlx::query_as("SELECT owner, sid, name FROM directories WHERE search_name IS NULL")
+                            .fetch_all(&mut *tx)
+                            .await?;
+                    for batch in unfolded.chunks(VALUES_BATCH) {
+                        let sql = search_names_update(batch.len());
+                        let mut update = sqlx::query(&sql);
+                        for (owner, sid, name) in batch {
+                            update = update.bind(owner).bind(sid).bind(fold_name(name));
+                        }
+                        update.execute(&mut *tx).await?;
+                    }
+                    tx.commit().await?;
+                    Ok(())
+                }
+
+                /// Inserts the metadata of a file whose content is already stored.
+                /// The shared owner lock prevents a concurrent `delete_directory` from missing the file (and leaving its content behind).
+                async fn insert_file(
+                    &self,
+                    owner: EntityUid,
+                    requested_by: EntityUid,
+                    directory: DaDirectorySid,
+                    name: &str,
+                    content_type: &str,
+                    blob: &DaBlob,
+                ) -> DaResult<DaFile> {
+                    let mut tx = self.begin_write().await?;
+                    lock_owner(&mut tx, owner, false).await?;
+
+                    // Updating the directory also locks its row, a directory moved to the trash in the meantime is not found
+                    let now = Utc::now();
+                    let result = sqlx::query(
+                        "UPDATE directories SET updated_at = $3, version = version + 1 WHERE owner = $1 AND sid = $2 AND NOT trashed",
+                    )
+                    .bind(owner as i64)
+                    .bind(directory)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+                    if result.rows_affected() == 0 {
+                        return Err(DaError::DirectoryNotFound(directory));
+                    }
+
+                    let file: SqlDaFile = sqlx::query_as(
+                        "INSERT INTO files (directory_sid, name, size, content_type, content_hash, blob_key, created_at, updated_at, created_by)
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $7, $8)
+                         RETURNING sid, directory_sid, name, size, content_type, content_hash, blob_key, created_at, updated_at, created_by",
+                    )
+                    .bind(directory)
+                    .bind(name)
+                    .bind(blob.size as i64)
+                    .bind(content_type)
+                    .bind(&blob.content_hash)
+                    .bind(&blob.key)
+                    .bind(now)
+                    .bind(requested_by as i64)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::CreateFile, directory)
+                            .file(file.sid)
+                            .after(json!({
+                                "name": file.name,
+                                "size": file.size,
+                                "content_type": file.content_type,
+                            })),
+                    )
+                    .await?;
+
+                    tx.commit().await?;
+                    Ok(file.into())
+                }
+
+                /// Permanently deletes a subtree of the trash of `owner` with its files and grants, then the contents of the files.
+                /// With an `expiry`, the subtree is only purged if it was deleted before it.
+                /// `entity` is the entity purging the subtree (none if it expired).
+                async fn purge(
+                    &self,
+                    owner: EntityUid,
+                    id: DaDirectorySid,
+                    expiry: Option<DateTime<Utc>>,
+                    entity: Option<EntityUid>,
+                ) -> DaResult<()> {
+                    let mut tx = self.begin_write().await?;
+                    lock_owner(&mut tx, owner, true).await?;
+                    let result = sqlx::query(
+                        "DELETE FROM trash WHERE owner = $1 AND sid = $2 AND ($3 IS NULL OR deleted_at < $3)",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(expiry)
+                    .execute(&mut *tx)
+                    .await?;
+                    if result.rows_affected() == 0 {
+                        return Err(DaError::TrashEntryNotFound(id));
+                    }
+                    let blob_keys: Vec<String> = sqlx::query_scalar(SUBTREE_BLOB_KEYS_QUERY)
+                        .bind(owner as i64)
+                        .bind(id)
+                        .fetch_all(&mut *tx)
+                        .await?;
+                    // Descendants, files and grants are removed by the `ON DELETE CASCADE` constraints
+                    sqlx::query("DELETE FROM directories WHERE owner = $1 AND sid = $2")
+                        .bind(owner as i64)
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(entity, DaAuditOperation::PurgeDirectory, id),
+                    )
+                    .await?;
+                    tx.commit().await?;
+                    delete_blobs(self.blob_store.as_ref(), blob_keys).await;
+                    Ok(())
+                }
+
+                /// Creates a directory within `tx` (see `DaRepository::create_directory`), the name is already normalised.
+                /// Returns the directory and the change to publish once `tx` is committed.
+                async fn create_in(
+                    &self,
+                    tx: &mut sqlx::Transaction<'_, $db>,
+                    batch_owner: Option<EntityUid>,
+                    requested_by: EntityUid,
+                    name: &str,
+                    parent: Option<DaDirectorySid>,
+                ) -> DaResult<(SqlDaDirectory, DaChangeEvent)> {
+                    // A directory created in a shared directory belongs to the owner of the shared directory
+                    let owner = match parent {
+                        Some(parent_sid) => authorize(&mut **tx, requested_by, parent_sid, DaPermission::Write)
+                            .await
+                            .map_err(DaError::for_parent)?,
+                        None => requested_by,
+                    };
+                    // Unique names are checked against the siblings, concurrent writes of the owner must wait for the insert
+                    lock_owner_in(
+                        tx,
+                        owner,
+                        self.config.sibling_names.is_unique(),
+                        batch_owner,
+                    )
+                    .await?;
+
+                    // Updating the parent also locks its row, a parent moved to the trash in the meantime is not found
+                    let now = Utc::now();
+                    let depth = match parent {
+                        Some(parent_sid) => {
+                            let parent_depth: i32 = sqlx::query_scalar(
+                                "UPDATE directories SET updated_at = $3, version = version + 1 WHERE owner = $1 AND sid = $2 AND NOT trashed RETURNING depth",
+                            )
+                            .bind(owner as i64)
+                            .bind(parent_sid)
+                            .bind(now)
+                            .fetch_optional(&mut **tx)
+                            .await?
+                            .ok_or(DaError::ParentNotFound(parent_sid))?;
+                            parent_depth + 1
+                        }
+                        None => 0,
+                    };
+                    self.config.depth_limit.check(owner, depth as u32)?;
+                    check_sibling_names(tx, self.config.sibling_names, owner, parent, name, None).await?;
+
+                    let sid = allocate_sids(tx, 1).await?[0];
+                    let directory: SqlDaDirectory = sqlx::query_as(
+                        "INSERT INTO directories (owner, sid, name, search_name, parent_sid, depth, created_at, updated_at, created_by)
+                         VALUES ($1, $2, $3, $8, $4, $5, $6, $6, $7)
+                         RETURNING sid, name, parent_sid, depth, created_at, updated_at, version, created_by",
+                    )
+                    .bind(owner as i64)
+                    .bind(sid)
+                    .bind(name)
+                    .bind(parent)
+                    .bind(depth)
+                    .bind(now)
+                    .bind(requested_by as i64)
+                    .bind(fold_name(name))
+                    .fetch_one(&mut **tx)
+                    .await?;
+                    record(
+                        tx,
+                        owner,
+                        DaAuditRecord::new(
+                            requested_by,
+                            DaAuditOperation::CreateDirectory,
+                            directory.sid,
+                        )
+                        .after(json!({ "name": name, "parent_sid": parent })),
+                    )
+                    .await?;
+                    let event = DaChangeEvent::new(
+                        directory.sid,
+                        owner,
+                        requested_by,
+                        DaChange::Created {
+                            name: name.to_string(),
+                            parent_sid: parent,
+                        },
+                    )
+                    .with_grantees(fetch_grantees(&mut **tx, owner, directory.sid, None).await?);
+                    Ok((directory, event))
+                }
+
+                /// Moves a directory to the trash within `tx` (see `DaRepository::delete_directory_if_version`).
+                /// Returns the change to publish once `tx` is committed.
+                async fn delete_in(
+                    &self,
+                    tx: &mut sqlx::Transaction<'_, $db>,
+                    batch_owner: Option<EntityUid>,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    expected_version: Option<u64>,
+                ) -> DaResult<DaChangeEvent> {
+                    let owner = authorize(&mut **tx, requested_by, id, DaPermission::Write).await?;
+                    // The subtree must not change while it is moved to the trash
+                    lock_owner_in(tx, owner, true, batch_owner).await?;
+                    let (parent_sid, version): (Option<DaDirectorySid>, i64) = sqlx::query_as(
+                        concat!("SELECT parent_sid, version FROM directories WHERE owner = $1 AND sid = $2 AND NOT trashed", $for_update),
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .fetch_optional(&mut **tx)
+                    .await?
+                    .ok_or(DaError::DirectoryNotFound(id))?;
+                    check_version(id, expected_version, version as u64)?;
+                    // The subtree is detached from its ancestors once in the trash
+                    let grantees = fetch_grantees(&mut **tx, owner, id, None).await?;
+                    let subtree_grantees = fetch_subtree_grantees(&mut **tx, owner, id).await?;
+
+                    // Files and grants stay with their directories until the subtree is purged
+                    sqlx::query(TRASH_SUBTREE_QUERY)
+                        .bind(owner as i64)
+                        .bind(id)
+                        .execute(&mut **tx)
+                        .await?;
+                    // The directory is detached so that its parent can be deleted without it, the trash keeps the original parent
+                    sqlx::query("UPDATE directories SET parent_sid = NULL WHERE owner = $1 AND sid = $2")
+                        .bind(owner as i64)
+                        .bind(id)
+                        .execute(&mut **tx)
+                        .await?;
+                    let now = Utc::now();
+                    sqlx::query(
+                        "INSERT INTO trash (owner, sid, parent_sid, deleted_at, deleted_by) VALUES ($1, $2, $3, $4, $5)",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(parent_sid)
+                    .bind(now)
+                    .bind(requested_by as i64)
+                    .execute(&mut **tx)
+                    .await?;
+                    sqlx::query("UPDATE directories SET updated_at = $3, version = version + 1 WHERE owner = $1 AND sid = $2")
+                        .bind(owner as i64)
+                        .bind(parent_sid)
+                        .bind(now)
+                        .execute(&mut **tx)
+                        .await?;
+                    record(
+                        tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::DeleteDirectory, id)
+                            .before(json!({ "parent_sid": parent_sid })),
+                    )
+                    .await?;
+                    Ok(
+                        DaChangeEvent::new(id, owner, requested_by, DaChange::Deleted { parent_sid })
+                            .with_grantees(grantees)
+                            .with_subtree_grantees(subtree_grantees),
+                    )
+                }
+
+                /// Renames a directory within `tx` (see `DaRepository::rename_directory_if_version`), the name is already normalised.
+                /// Returns the change to publish once `tx` is committed.
+                async fn rename_in(
+                    &self,
+                    tx: &mut sqlx::Transaction<'_, $db>,
+                    batch_owner: Option<EntityUid>,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    new_name: &str,
+                    expected_version: Option<u64>,
+                ) -> DaResult<DaChangeEvent> {
+                    let owner = authorize(&mut **tx, requested_by, id, DaPermission::Write).await?;
+                    if self.config.sibling_names.is_unique() {
+                        // The siblings must not change until the rename is committed
+                        lock_owner_in(tx, owner, true, batch_owner).await?;
+                        let parent: Option<DaDirectorySid> = sqlx::query_scalar(
+                            "SELECT parent_sid FROM directories WHERE owner = $1 AND sid = $2",
+                        )
+                        .bind(owner as i64)
+                        .bind(id)
+                        .fetch_optional(&mut **tx)
+                        .await?
+                        .ok_or(DaError::DirectoryNotFound(id))?;
+                        check_sibling_names(
+                            tx,
+                            self.config.sibling_names,
+                            owner,
+                            parent,
+                            new_name,
+                            Some(id),
+                        )
+                        .await?;
+                    }
+                    // The row stays locked until the rename is committed, so the recorded old name and the checked version are the replaced ones
+                    let (old_name, version): (String, i64) = sqlx::query_as(
+                        concat!("SELECT name, version FROM directories WHERE owner = $1 AND sid = $2", $for_update),
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .fetch_optional(&mut **tx)
+                    .await?
+                    .ok_or(DaError::DirectoryNotFound(id))?;
+                    check_version(id, expected_version, version as u64)?;
+                    sqlx::query(
+                        "UPDATE directories SET name = $3, search_name = $5, updated_at = $4, version = version + 1
+                         WHERE owner = $1 AND sid = $2",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(new_name)
+                    .bind(Utc::now())
+                    .bind(fold_name(new_name))
+                    .execute(&mut **tx)
+                    .await?;
+                    record(
+                        tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::RenameDirectory, id)
+                            .before(json!({ "name": old_name }))
+                            .after(json!({ "name": new_name })),
+                    )
+                    .await?;
+                    Ok(DaChangeEvent::new(
+                        id,
+                        owner,
+                        requested_by,
+                        DaChange::Renamed {
+                            name: new_name.to_string(),
+                        },
+                    )
+                    .with_grantees(fetch_grantees(&mut **tx, owner, id, None).await?))
+                }
+
+                /// Moves a directory under a new parent within `tx` (see `DaRepository::move_directory`).
+                /// Returns the change to publish once `tx` is committed.
+                async fn move_in(
+                    &self,
+                    tx: &mut sqlx::Transaction<'_, $db>,
+                    batch_owner: Option<EntityUid>,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    parent: Option<DaDirectorySid>,
+                ) -> DaResult<DaChangeEvent> {
+                    let owner = authorize(&mut **tx, requested_by, id, DaPermission::Write).await?;
+                    if let Some(parent_sid) = parent {
+                        // Directories can't be moved across the trees of different owners
+                        let parent_owner = authorize(&mut **tx, requested_by, parent_sid, DaPermission::Write)
+                            .await
+                            .map_err(DaError::for_parent)?;
+                        if parent_owner != owner {
+                            return Err(DaError::AccessDenied {
+                                requested_by,
+                                resource_owner: parent_owner,
+                            });
+                        }
+                    }
+                    lock_owner_in(tx, owner, true, batch_owner).await?;
+
+                    let (old_depth, old_parent, name): (i32, Option<DaDirectorySid>, String) = sqlx::query_as(
+                        concat!("SELECT depth, parent_sid, name FROM directories WHERE owner = $1 AND sid = $2 AND NOT trashed", $for_update),
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .fetch_optional(&mut **tx)
+                    .await?
+                    .ok_or(DaError::DirectoryNotFound(id))?;
+
+                    let depth = match parent {
+                        Some(parent_sid) => {
+                            let parent_depth: i32 = sqlx::query_scalar(
+                                "SELECT depth FROM directories WHERE owner = $1 AND sid = $2 AND NOT trashed",
+                            )
+                            .bind(owner as i64)
+                            .bind(parent_sid)
+                            .fetch_optional(&mut **tx)
+                            .await?
+                            .ok_or(DaError::ParentNotFound(parent_sid))?;
+
+                            // The new parent must not be the moved directory or one of its descendants
+                            let is_cycle: bool = sqlx::query_scalar(
+                                "WITH RECURSIVE ancestors AS (
+                                    SELECT sid, parent_sid FROM directories WHERE owner = $1 AND sid = $2
+                                    UNION ALL
+                                    SELECT d.sid, d.parent_sid
+                                    FROM directories d JOIN ancestors a ON d.owner = $1 AND d.sid = a.parent_sid
+                                )
+                                SELECT EXISTS (SELECT 1 FROM ancestors WHERE sid = $3)",
+                            )
+                            .bind(owner as i64)
+                            .bind(parent_sid)
+                            .bind(id)
+                            .fetch_one(&mut **tx)
+                            .await?;
+                            if is_cycle {
+                                return Err(DaError::DirectoryCycle(id, parent_sid));
+                            }
+                            parent_depth + 1
+                        }
+                        None => 0,
+                    };
+
+                    // Ensure the deepest directory of the subtree stays within the depth limit once moved
+                    let max_depth: i32 = sqlx::query_scalar(SUBTREE_MAX_DEPTH_QUERY)
+                        .bind(owner as i64)
+                        .bind(id)
+                        .fetch_one(&mut **tx)
+                        .await?;
+                    self.config
+                        .depth_limit
+                        .check(owner, (max_depth - old_depth + depth) as u32)?;
+                    check_sibling_names(
+                        tx,
+                        self.config.sibling_names,
+                        owner,
+                        parent,
+                        &name,
+                        Some(id),
+                    )
+                    .await?;
+
+                    let now = Utc::now();
+                    sqlx::query(
+                        "UPDATE directories SET parent_sid = $3, updated_at = $4, version = version + 1 WHERE owner = $1 AND sid = $2",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(parent)
+                    .bind(now)
+                    .execute(&mut **tx)
+                    .await?;
+
+                    // Both the old and the new parent have their children changed
+                    sqlx::query(
+                        "UPDATE directories SET updated_at = $2, version = version + 1 WHERE owner = $1 AND (sid = $3 OR sid = $4)",
+                    )
+                    .bind(owner as i64)
+                    .bind(now)
+                    .bind(old_parent)
+                    .bind(parent)
+                    .execute(&mut **tx)
+                    .await?;
+
+                    sqlx::query(
+                        "WITH RECURSIVE subtree AS (
+                            SELECT sid FROM directories WHERE owner = $1 AND sid = $2
+                            UNION ALL
+                            SELECT d.sid FROM directories d JOIN subtree s ON d.owner = $1 AND d.parent_sid = s.sid
+                        )
+                        UPDATE directories SET depth = depth + $3
+                        WHERE owner = $1 AND sid IN (SELECT sid FROM subtree)",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(depth - old_depth)
+                    .execute(&mut **tx)
+                    .await?;
+                    record(
+                        tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::MoveDirectory, id)
+                            .before(json!({ "parent_sid": old_parent }))
+                            .after(json!({ "parent_sid": parent })),
+                    )
+                    .await?;
+                    Ok(DaChangeEvent::new(
+                        id,
+                        owner,
+                        requested_by,
+                        DaChange::Moved {
+                            old_parent_sid: old_parent,
+                            parent_sid: parent,
+                        },
+                    )
+                    .with_grantees(fetch_grantees(&mut **tx, owner, id, old_parent).await?))
+                }
+
+                /// Applies an operation of a batch within `tx` (see `DaRepository::apply_batch`).
+                /// Returns the change to publish once `tx` is committed.
+                async fn apply_operation(
+                    &self,
+                    tx: &mut sqlx::Transaction<'_, $db>,
+                    batch_owner: EntityUid,
+                    result: &mut DaBatchResult,
+                    requested_by: EntityUid,
+                    operation: &DaBatchOperation,
+                ) -> DaResult<DaChangeEvent> {
+                    match operation {
+                        DaBatchOperation::Create {
+                            temp_id,
+                            name,
+                            parent,
+                        } => {
+                            result.check_temp_id(temp_id)?;
+                            let name = &self.config.names.normalize(name)?;
+                            let parent = result.resolve_opt(parent.as_ref())?;
+                            let (directory, event) = self
+                                .create_in(tx, Some(batch_owner), requested_by, name, parent)
+                                .await?;
+                            result.created.insert(temp_id.clone(), directory.sid);
+                            Ok(event)
+                        }
+                        DaBatchOperation::Rename { sid, name } => {
+                            let name = &self.config.names.normalize(name)?;
+                            let sid = result.resolve(sid)?;
+                            self.rename_in(tx, Some(batch_owner), requested_by, sid, name, None)
+                                .await
+                        }
+                        DaBatchOperation::Move { sid, parent } => {
+                            let sid = result.resolve(sid)?;
+                            let parent = result.resolve_opt(parent.as_ref())?;
+                            self.move_in(tx, Some(batch_owner), requested_by, sid, parent)
+                                .await
+                        }
+                        DaBatchOperation::Delete { sid } => {
+                            let sid = result.resolve(sid)?;
+                            self.delete_in(tx, Some(batch_owner), requested_by, sid, None)
+                                .await
+                        }
+                    }
+                }
+            }
+
+            impl DaRepository for $repository {
+                async fn create_directory(
+                    &self,
+                    requested_by: EntityUid,
+                    name: &str,
+                    parent: Option<DaDirectorySid>,
+                ) -> DaResult<DaDirectory> {
+                    let name = &self.config.names.normalize(name)?;
+                    let mut tx = self.begin_write().await?;
+                    let (directory, event) = self
+                        .create_in(&mut tx, None, requested_by, name, parent)
+                        .await?;
+                    tx.commit().await?;
+                    self.events.publish(event);
+                    Ok(directory.into())
+                }
+
+                async fn get_directory_with_depth(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    depth: Option<u32>,
+                ) -> DaResult<DaDirectory> {
+                    let owner = authorize(&self.pool, requested_by, id, DaPermission::Read).await?;
+
+                    // `level` is the depth relative to the requested directory, the recursion stops at the requested depth
+                    let rows: Vec<SqlDaDirectory> = sqlx::query_as(&format!(
+                        "WITH RECURSIVE subtree AS (
+                            SELECT sid, name, parent_sid, depth, created_at, updated_at, version, created_by, 0 AS level
+                            FROM directories WHERE owner = $1 AND sid = $2
+                            UNION ALL
+                            SELECT d.sid, d.name, d.parent_sid, d.depth, d.created_at, d.updated_at, d.version, d.created_by,
+                                s.level + 1
+                            FROM directories d JOIN subtree s ON d.owner = $1 AND d.parent_sid = s.sid
+                            WHERE $3 IS NULL OR s.level < $3
+                        )
+                        SELECT sid, name, parent_sid, depth, created_at, updated_at, version, created_by,
+                            COALESCE(level = $3, FALSE) AND EXISTS (
+                                SELECT 1 FROM directories c WHERE c.owner = $1 AND c.parent_sid = d.sid
+                            ) AS has_unloaded_children,
+                            {TAGS_COLUMN}
+                        FROM subtree d ORDER BY depth, sid"
+                    ))
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(depth.map(|depth| depth as i32))
+                    .fetch_all(&self.pool)
+                    .await?;
+
+                    build_tree(id, rows).ok_or(DaError::DirectoryNotFound(id))
+                }
+
+                async fn resolve_path(
+                    &self,
+                    requested_by: EntityUid,
+                    path: &[&str],
+                    depth: Option<u32>,
+                ) -> DaResult<DaDirectory> {
+                    // The names are looked up the way they are stored
+                    let names = self.config.names.normalize_path(path);
+                    let path: &[&str] = &names.iter().map(String::as_str).collect::<Vec<_>>();
+                    let mut conn = self.pool.acquire().await?;
+                    let mut resolved: Option<DaDirectorySid> = None;
+                    for (idx, name) in path.iter().enumerate() {
+                        let sids: Vec<DaDirectorySid> = sqlx::query_scalar(PATH_SEGMENT_QUERY)
+                            .bind(requested_by as i64)
+                            .bind(resolved)
+                            .bind(*name)
+                            .fetch_all(&mut *conn)
+                            .await?;
+                        match sids.as_slice() {
+                            [sid] => resolved = Some(*sid),
+                            [] => return Err(DaError::PathNotFound(path_prefix(path, idx + 1))),
+                            _ => return Err(DaError::AmbiguousPath(path_prefix(path, idx + 1))),
+                        }
+                    }
+                    drop(conn);
+                    let sid = resolved.ok_or_else(|| DaError::PathNotFound(path_prefix(path, 0)))?;
+                    self.get_directory_with_depth(requested_by, sid, depth)
+                        .await
+                }
+
+                async fn list_ancestors(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                ) -> DaResult<Vec<DaAncestor>> {
+                    let owner = authorize(&self.pool, requested_by, id, DaPermission::Read).await?;
+                    let rows: Vec<(DaDirectorySid, String, bool)> = sqlx::query_as(ANCESTORS_QUERY)
+                        .bind(owner as i64)
+                        .bind(id)
+                        .bind(requested_by as i64)
+                        .fetch_all(&self.pool)
+                        .await?;
+                    let ancestors = rows
+                        .into_iter()
+                        .map(|(sid, name, granted)| (DaAncestor { sid, name }, granted))
+                        .collect();
+                    Ok(readable_ancestors(ancestors, owner == requested_by))
+                }
+
+                async fn search_directories(
+                    &self,
+                    requested_by: EntityUid,
+                    query: DaSearchQuery,
+                    cursor: Option<DaCursor>,
+                    limit: u32,
+                ) -> DaResult<DaPage<DaSearchResult>> {
+                    if let Some(scope) = query.scope {
+                        authorize_owner(&self.pool, requested_by, scope).await?;
+                    }
+
+                    // Take one more result than requested to know whether there is a next page
+                    let results = match query.mode {
+                        DaSearchMode::Fuzzy => {
+                            self.fuzzy_search(requested_by, &query, cursor.as_ref(), limit)
+                                .await?
+                        }
+                        _ => {
+                            let rows: Vec<SqlDaSearchResult> = sqlx::query_as(SEARCH_QUERY)
+                                .bind(requested_by as i64)
+                                .bind(query.like_pattern())
+                                .bind(query.scope)
+                                .bind(cursor.map(|cursor| cursor.last_sid()))
+                                .bind(limit as i64 + 1)
+                                .fetch_all(&self.pool)
+                                .await?;
+                            rows.into_iter().map(DaSearchResult::from).collect()
+                        }
+                    };
+                    let mut page = DaPage::from_overfetched(results, limit, DaCursor::after_search_result);
+
+                    // The paths are only resolved for the directories of the page
+                    if !page.items.is_empty() {
+                        let sql = search_paths_query(page.items.len());
+                        let mut paths = sqlx::query_as(&sql).bind(requested_by as i64);
+                        for result in &page.items {
+                            paths = paths.bind(result.sid);
+                        }
+                        set_search_paths(&mut page.items, paths.fetch_all(&self.pool).await?);
+                    }
+                    Ok(page)
+                }
+
+                async fn delete_directory_if_version(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    expected_version: Option<u64>,
+                ) -> DaResult<()> {
+                    let mut tx = self.begin_write().await?;
+                    let event = self
+                        .delete_in(&mut tx, None, requested_by, id, expected_version)
+                        .await?;
+                    tx.commit().await?;
+                    self.events.publish(event);
+                    Ok(())
+                }
+
+                async fn rename_directory_if_version(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    new_name: &str,
+                    expected_version: Option<u64>,
+                ) -> DaResult<()> {
+                    let new_name = &self.config.names.normalize(new_name)?;
+                    let mut tx = self.begin_write().await?;
+                    let event = self
+                        .rename_in(&mut tx, None, requested_by, id, new_name, expected_version)
+                        .await?;
+                    tx.commit().await?;
+                    self.events.publish(event);
+                    Ok(())
+                }
+
+                async fn move_directory(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    parent: Option<DaDirectorySid>,
+                ) -> DaResult<()> {
+                    let mut tx = self.begin_write().await?;
+                    let event = self
+                        .move_in(&mut tx, None, requested_by, id, parent)
+                        .await?;
+                    tx.commit().await?;
+                    self.events.publish(event);
+                    Ok(())
+                }
+
+                async fn copy_directory(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    parent: Option<DaDirectorySid>,
+                    name: Option<&str>,
+                ) -> DaResult<DaDirectory> {
+                    let name = name
+                        .map(|name| self.config.names.normalize(name))
+                        .transpose()?;
+                    let mut tx = self.begin_write().await?;
+                    let source_owner = authorize(&mut *tx, requested_by, id, DaPermission::Read).await?;
+                    // The copy belongs to the owner of its parent, the same way as a created directory
+                    let owner = match parent {
+                        Some(parent_sid) => authorize(&mut *tx, requested_by, parent_sid, DaPermission::Write)
+                            .await
+                            .map_err(DaError::for_parent)?,
+                        None => requested_by,
+                    };
+                    // Unique names are checked against the siblings, concurrent writes of the owner must wait for the insert
+                    lock_owner(&mut tx, owner, self.config.sibling_names.is_unique()).await?;
+
+                    // Updating the parent also locks its row, a parent moved to the trash in the meantime is not found
+                    let now = Utc::now();
+                    let depth = match parent {
+                        Some(parent_sid) => {
+                            let parent_depth: i32 = sqlx::query_scalar(
+                                "UPDATE directories SET updated_at = $3, version = version + 1 WHERE owner = $1 AND sid = $2 AND NOT trashed RETURNING depth",
+                            )
+                            .bind(owner as i64)
+                            .bind(parent_sid)
+                            .bind(now)
+                            .fetch_optional(&mut *tx)
+                            .await?
+                            .ok_or(DaError::ParentNotFound(parent_sid))?;
+                            parent_depth + 1
+                        }
+                        None => 0,
+                    };
+
+                    let (source_name, source_depth): (String, i32) = sqlx::query_as(
+                        "SELECT name, depth FROM directories WHERE owner = $1 AND sid = $2 AND NOT trashed",
+                    )
+                    .bind(source_owner as i64)
+                    .bind(id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .ok_or(DaError::DirectoryNotFound(id))?;
+                    let name = name.unwrap_or(source_name);
+                    check_sibling_names(
+                        &mut tx,
+                        self.config.sibling_names,
+                        owner,
+                        parent,
+                        &name,
+                        None,
+                    )
+                    .await?;
+
+                    // The subtree is read before any copy is inserted, so a directory can be copied into one of its descendants.
+                    // Parents come before their children, which are inserted in later chunks than their parent's copy
+                    let subtree: Vec<(DaDirectorySid, Option<DaDirectorySid>, i32)> = sqlx::query_as(COPY_SUBTREE_QUERY)
+                        .bind(source_owner as i64)
+                        .bind(id)
+                        .fetch_all(&mut *tx)
+                        .await?;
+                    let max_depth = subtree
+                        .iter()
+                        .map(|(_, _, row_depth)| row_depth - source_depth + depth)
+                        .max()
+                        .unwrap_or(depth);
+                    self.config.depth_limit.check(owner, max_depth as u32)?;
+
+                    // Every copied directory is mapped to its copy as (sid, sid of the copy, parent of the copy)
+                    let sids = allocate_sids(&mut tx, subtree.len()).await?;
+                    let copies: HashMap<DaDirectorySid, DaDirectorySid> = subtree
+                        .iter()
+                        .zip(&sids)
+                        .map(|((sid, _, _), copy_sid)| (*sid, *copy_sid))
+                        .collect();
+                    let mapping: Vec<(DaDirectorySid, DaDirectorySid, Option<DaDirectorySid>)> = subtree
+                        .iter()
+                        .map(|(sid, parent_sid, _)| {
+                            let copy_parent = if *sid == id {
+                                parent
+                            } else {
+                                parent_sid.and_then(|parent_sid| copies.get(&parent_sid).copied())
+                            };
+                            (*sid, copies[sid], copy_parent)
+                        })
+                        .collect();
+                    for chunk in mapping.chunks(VALUES_BATCH) {
+                        let sql = copy_directories_insert(chunk.len());
+                        let mut insert = sqlx::query(&sql)
+                            .bind(owner as i64)
+                            .bind(source_owner as i64)
+                            .bind(id)
+                            .bind(&name)
+                            .bind(fold_name(&name))
+                            .bind(depth - source_depth)
+                            .bind(now)
+                            .bind(requested_by as i64);
+                        for (sid, copy_sid, copy_parent) in chunk {
+                            insert = insert.bind(sid).bind(copy_sid).bind(copy_parent);
+                        }
+                        insert.execute(&mut *tx).await?;
+
+                        // The copies carry the tags of their originals
+                        let sql = copy_tags_insert(chunk.len());
+                        let mut insert = sqlx::query(&sql)
+                            .bind(owner as i64)
+                            .bind(source_owner as i64);
+                        for (sid, copy_sid, _) in chunk {
+                            insert = insert.bind(sid).bind(copy_sid);
+                        }
+                        insert.execute(&mut *tx).await?;
+                    }
+
+                    let copy_sid = *copies.get(&id).ok_or(DaError::DirectoryNotFound(id))?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::CopyDirectory, copy_sid)
+                            .after(json!({ "name": name, "parent_sid": parent, "source_sid": id })),
+                    )
+                    .await?;
+
+                    let grantees = fetch_grantees(&mut *tx, owner, copy_sid, None).await?;
+                    tx.commit().await?;
+                    self.events.publish(
+                        DaChangeEvent::new(
+                            copy_sid,
+                            owner,
+                            requested_by,
+                            DaChange::Created {
+                                name,
+                                parent_sid: parent,
+                            },
+                        )
+                        .with_grantees(grantees),
+                    );
+                    self.get_directory(requested_by, copy_sid).await
+                }
+
+                async fn apply_batch(
+                    &self,
+                    requested_by: EntityUid,
+                    operations: &[DaBatchOperation],
+                ) -> DaResult<DaBatchResult> {
+                    let mut tx = self.begin_write().await?;
+                    let mut result = DaBatchResult::default();
+                    let mut events: Vec<DaChangeEvent> = vec![];
+
+                    // The batch applies to the tree of the owner of the directory targeted by the first operation, which is locked exclusively
+                    // before the first operation: upgrading a shared lock taken by an operation would deadlock with a concurrent batch.
+                    // A missing directory is reported by the first operation.
+                    let batch_owner = match operations.first().and_then(DaBatchOperation::target) {
+                        Some(DaBatchRef::Sid(sid)) => {
+                            sqlx::query_scalar::<_, i64>("SELECT owner FROM directories WHERE sid = $1")
+                                .bind(sid)
+                                .fetch_optional(&mut *tx)
+                                .await?
+                                .map_or(requested_by, |owner| owner as EntityUid)
+                        }
+                        _ => requested_by,
+                    };
+                    lock_owner(&mut tx, batch_owner, true).await?;
+
+                    for (index, operation) in operations.iter().enumerate() {
+                        let event = self
+                            .apply_operation(&mut tx, batch_owner, &mut result, requested_by, operation)
+                            .await
+                            .and_then(|event| {
+                                check_batch_owner(batch_owner, event.owner)?;
+                                Ok(event)
+                            })
+                            .map_err(|err| err.in_batch(index))?;
+                        events.push(event);
+                    }
+                    tx.commit().await?;
+                    for event in events {
+                        self.events.publish(event);
+                    }
+                    Ok(result)
+                }
+
+                async fn list_children(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    cursor: Option<DaCursor>,
+                    limit: u32,
+                ) -> DaResult<DaPage<DaDirectory>> {
+                    let owner = authorize(&self.pool, requested_by, id, DaPermission::Read).await?;
+
+                    // Take one more child than requested to know whether there is a next page
+                    let rows: Vec<SqlDaDirectory> = sqlx::query_as(&format!(
+                        "SELECT sid, name, parent_sid, depth, created_at, updated_at, version, created_by,
+                            EXISTS (
+                                SELECT 1 FROM directories c WHERE c.owner = $1 AND c.parent_sid = d.sid
+                            ) AS has_unloaded_children,
+                            {TAGS_COLUMN}
+                        FROM directories d
+                        WHERE owner = $1 AND parent_sid = $2 AND ($3 IS NULL OR sid > $3)
+                        ORDER BY sid LIMIT $4"
+                    ))
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(cursor.map(|cursor| cursor.last_sid()))
+                    .bind(limit as i64 + 1)
+                    .fetch_all(&self.pool)
+                    .await?;
+
+                    Ok(DaPage::from_overfetched(
+                        rows.into_iter().map(DaDirectory::from).collect(),
+                        limit,
+                        |dir| DaCursor::after(dir.sid),
+                    ))
+                }
+
+                async fn list_root_directories(
+                    &self,
+                    requested_by: EntityUid,
+                    sort: DaSort,
+                    cursor: Option<DaCursor>,
+                    limit: u32,
+                ) -> DaResult<DaPage<DaDirectory>> {
+                    let cursor_name = match (&cursor, sort.key) {
+                        (Some(cursor), DaSortKey::Name) => Some(cursor.last_name()?.to_string()),
+                        _ => None,
+                    };
+
+                    // Take one more directory than requested to know whether there is a next page
+                    let rows: Vec<SqlDaDirectory> = sqlx::query_as(&root_directories_query(sort))
+                        .bind(requested_by as i64)
+                        .bind(cursor.map(|cursor| cursor.last_sid()))
+                        .bind(cursor_name)
+                        .bind(limit as i64 + 1)
+                        .fetch_all(&self.pool)
+                        .await?;
+
+                    Ok(DaPage::from_overfetched(
+                        rows.into_iter().map(DaDirectory::from).collect(),
+                        limit,
+                        |dir| DaCursor::after_directory(dir, sort),
+                    ))
+                }
+
+                async fn grant_access(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    grantee: EntityUid,
+                    permission: DaPermission,
+                ) -> DaResult<DaGrant> {
+                    let mut tx = self.begin_write().await?;
+                    let owner = authorize_owner(&mut *tx, requested_by, id).await?;
+                    let old_permission: Option<String> = sqlx::query_scalar(
+                        concat!("SELECT permission FROM directory_grants WHERE owner = $1 AND sid = $2 AND grantee = $3", $for_update),
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(grantee as i64)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+                    // The grant row is removed with the directory by the `ON DELETE CASCADE` constraint
+                    let grant: SqlDaGrant = sqlx::query_as(
+                        "INSERT INTO directory_grants (owner, sid, grantee, permission) VALUES ($1, $2, $3, $4)
+                         ON CONFLICT (owner, sid, grantee) DO UPDATE SET permission = excluded.permission
+                         RETURNING sid, grantee, permission",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(grantee as i64)
+                    .bind(permission.as_str())
+                    .fetch_one(&mut *tx)
+                    .await?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::GrantAccess, id)
+                            .before(json!({ "grantee": grantee, "permission": old_permission }))
+                            .after(json!({ "grantee": grantee, "permission": permission })),
+                    )
+                    .await?;
+                    tx.commit().await?;
+                    Ok(grant.into())
+                }
+
+                async fn revoke_access(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    grantee: EntityUid,
+                ) -> DaResult<()> {
+                    let mut tx = self.begin_write().await?;
+                    let owner = authorize_owner(&mut *tx, requested_by, id).await?;
+                    let old_permission: Option<String> = sqlx::query_scalar(
+                        "DELETE FROM directory_grants WHERE owner = $1 AND sid = $2 AND grantee = $3 RETURNING permission",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(grantee as i64)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::RevokeAccess, id)
+                            .before(json!({ "grantee": grantee, "permission": old_permission }))
+                            .after(json!({ "grantee": grantee, "permission": null })),
+                    )
+                    .await?;
+                    tx.commit().await?;
+                    Ok(())
+                }
+
+                async fn list_grants(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                ) -> DaResult<Vec<DaGrant>> {
+                    let owner = authorize_owner(&self.pool, requested_by, id).await?;
+                    let rows: Vec<SqlDaGrant> = sqlx::query_as(
+                        "SELECT sid, grantee, permission FROM directory_grants
+                         WHERE owner = $1 AND sid = $2 ORDER BY grantee",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .fetch_all(&self.pool)
+                    .await?;
+                    Ok(rows.into_iter().map(DaGrant::from).collect())
+                }
+
+                async fn add_tag(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    tag: &str,
+                ) -> DaResult<Vec<String>> {
+                    let tag = normalize_tag(tag)?;
+                    let mut tx = self.begin_write().await?;
+                    let owner = authorize(&mut *tx, requested_by, id, DaPermission::Write).await?;
+                    // The tag row is removed with the directory by the `ON DELETE CASCADE` constraint
+                    sqlx::query(
+                        "INSERT INTO directory_tags (owner, sid, tag) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(&tag)
+                    .execute(&mut *tx)
+                    .await?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::TagDirectory, id)
+                            .after(json!({ "tag": tag })),
+                    )
+                    .await?;
+                    let tags = sqlx::query_scalar(DIRECTORY_TAGS_QUERY)
+                        .bind(owner as i64)
+                        .bind(id)
+                        .fetch_all(&mut *tx)
+                        .await?;
+                    tx.commit().await?;
+                    Ok(tags)
+                }
+
+                async fn remove_tag(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                    tag: &str,
+                ) -> DaResult<Vec<String>> {
+                    let tag = normalize_tag(tag)?;
+                    let mut tx = self.begin_write().await?;
+                    let owner = authorize(&mut *tx, requested_by, id, DaPermission::Write).await?;
+                    sqlx::query("DELETE FROM directory_tags WHERE owner = $1 AND sid = $2 AND tag = $3")
+                        .bind(owner as i64)
+                        .bind(id)
+                        .bind(&tag)
+                        .execute(&mut *tx)
+                        .await?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::UntagDirectory, id)
+                            .before(json!({ "tag": tag })),
+                    )
+                    .await?;
+                    let tags = sqlx::query_scalar(DIRECTORY_TAGS_QUERY)
+                        .bind(owner as i64)
+                        .bind(id)
+                        .fetch_all(&mut *tx)
+                        .await?;
+                    tx.commit().await?;
+                    Ok(tags)
+                }
+
+                async fn list_tags(&self, requested_by: EntityUid) -> DaResult<Vec<DaTagCount>> {
+                    let rows: Vec<(String, i64)> = sqlx::query_as(TAG_COUNTS_QUERY)
+                        .bind(requested_by as i64)
+                        .fetch_all(&self.pool)
+                        .await?;
+                    Ok(rows
+                        .into_iter()
+                        .map(|(tag, count)| DaTagCount {
+                            tag,
+                            count: count as u64,
+                        })
+                        .collect())
+                }
+
+                async fn list_tagged_directories(
+                    &self,
+                    requested_by: EntityUid,
+                    query: DaTagQuery,
+                    cursor: Option<DaCursor>,
+                    limit: u32,
+                ) -> DaResult<DaPage<DaDirectory>> {
+                    let tags = query.normalized_tags()?;
+                    // A query without tags matches nothing (and `IN ()` is not valid SQL)
+                    if tags.is_empty() {
+                        return Ok(DaPage {
+                            items: vec![],
+                            next_cursor: None,
+                        });
+                    }
+                    let required = match query.mode {
+                        DaTagMatch::All => tags.len(),
+                        DaTagMatch::Any => 1,
+                    };
+
+                    // Take one more directory than requested to know whether there is a next page
+                    let sql = tagged_directories_query(tags.len());
+                    let mut rows = sqlx::query_as::<_, SqlDaDirectory>(&sql)
+                        .bind(requested_by as i64)
+                        .bind(cursor.map(|cursor| cursor.last_sid()))
+                        .bind(limit as i64 + 1)
+                        .bind(required as i64);
+                    for tag in tags {
+                        rows = rows.bind(tag);
+                    }
+                    let rows = rows.fetch_all(&self.pool).await?;
+
+                    Ok(DaPage::from_overfetched(
+                        rows.into_iter().map(DaDirectory::from).collect(),
+                        limit,
+                        |dir| DaCursor::after(dir.sid),
+                    ))
+                }
+
+                async fn create_file(
+                    &self,
+                    requested_by: EntityUid,
+                    directory: DaDirectorySid,
+                    name: &str,
+                    content_type: &str,
+                    content: DaByteStream,
+                ) -> DaResult<DaFile> {
+                    let name = &self.config.names.normalize(name)?;
+                    let owner = authorize(&self.pool, requested_by, directory, DaPermission::Write).await?;
+
+                    // The content is stored first, the file only becomes visible once it is complete
+                    let blob = self.blob_store.put(content).await?;
+                    let file = self
+                        .insert_file(owner, requested_by, directory, name, content_type, &blob)
+                        .await;
+                    if file.is_err() {
+                        delete_blobs(self.blob_store.as_ref(), vec![blob.key]).await;
+                    }
+                    file
+                }
+
+                async fn get_file(&self, requested_by: EntityUid, id: DaFileSid) -> DaResult<DaFile> {
+                    let mut conn = self.pool.acquire().await?;
+                    let (_, file) = authorize_file(&mut conn, requested_by, id, DaPermission::Read).await?;
+                    Ok(file.into())
+                }
+
+                async fn read_file(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaFileSid,
+                ) -> DaResult<(DaFile, DaByteStream)> {
+                    let mut conn = self.pool.acquire().await?;
+                    let (_, file) = authorize_file(&mut conn, requested_by, id, DaPermission::Read).await?;
+                    drop(conn);
+                    let content = self.blob_store.get(&file.blob_key).await?;
+                    Ok((file.into(), content))
+                }
+
+                async fn delete_file(&self, requested_by: EntityUid, id: DaFileSid) -> DaResult<()> {
+                    let mut tx = self.begin_write().await?;
+                    let (owner, _) = authorize_file(&mut tx, requested_by, id, DaPermission::Write).await?;
+                    let (directory, name, blob_key): (DaDirectorySid, String, String) = sqlx::query_as(
+                        "DELETE FROM files WHERE sid = $1 RETURNING directory_sid, name, blob_key",
+                    )
+                    .bind(id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .ok_or(DaError::FileNotFound(id))?;
+                    sqlx::query("UPDATE directories SET updated_at = $3, version = version + 1 WHERE owner = $1 AND sid = $2")
+                        .bind(owner as i64)
+                        .bind(directory)
+                        .bind(Utc::now())
+                        .execute(&mut *tx)
+                        .await?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::DeleteFile, directory)
+                            .file(id)
+                            .before(json!({ "name": name })),
+                    )
+                    .await?;
+                    tx.commit().await?;
+                    delete_blobs(self.blob_store.as_ref(), vec![blob_key]).await;
+                    Ok(())
+                }
+
+                async fn list_files(
+                    &self,
+                    requested_by: EntityUid,
+                    directory: DaDirectorySid,
+                    cursor: Option<DaCursor>,
+                    limit: u32,
+                ) -> DaResult<DaPage<DaFile>> {
+                    authorize(&self.pool, requested_by, directory, DaPermission::Read).await?;
+
+                    // Take one more file than requested to know whether there is a next page
+                    let rows: Vec<SqlDaFile> = sqlx::query_as(
+                        "SELECT sid, directory_sid, name, size, content_type, content_hash, blob_key, created_at, updated_at, created_by
+                        FROM files
+                        WHERE directory_sid = $1 AND ($2 IS NULL OR sid > $2)
+                        ORDER BY sid LIMIT $3",
+                    )
+                    .bind(directory)
+                    .bind(cursor.map(|cursor| cursor.last_file_sid()))
+                    .bind(limit as i64 + 1)
+                    .fetch_all(&self.pool)
+                    .await?;
+
+                    Ok(DaPage::from_overfetched(
+                        rows.into_iter().map(DaFile::from).collect(),
+                        limit,
+                        |file| DaCursor::after_file(file.sid),
+                    ))
+                }
+
+                async fn list_trash(
+                    &self,
+                    requested_by: EntityUid,
+                    cursor: Option<DaCursor>,
+                    limit: u32,
+                ) -> DaResult<DaPage<DaTrashEntry>> {
+                    // Take one more entry than requested to know whether there is a next page
+                    let rows: Vec<SqlDaTrashEntry> = sqlx::query_as(TRASH_QUERY)
+                        .bind(requested_by as i64)
+                        .bind(cursor.map(|cursor| cursor.last_sid()))
+                        .bind(limit as i64 + 1)
+                        .fetch_all(&self.pool)
+                        .await?;
+                    Ok(DaPage::from_overfetched(
+                        rows.into_iter().map(DaTrashEntry::from).collect(),
+                        limit,
+                        |entry| DaCursor::after(entry.sid),
+                    ))
+                }
+
+                async fn restore_directory(
+                    &self,
+                    requested_by: EntityUid,
+                    id: DaDirectorySid,
+                ) -> DaResult<DaDirectory> {
+                    // Only the owner has the subtree in its trash
+                    let owner = requested_by;
+                    let mut tx = self.begin_write().await?;
+                    lock_owner(&mut tx, owner, true).await?;
+                    let (original_parent, name, old_depth): (Option<DaDirectorySid>, String, i32) =
+                        sqlx::query_as(
+                            "SELECT t.parent_sid, d.name, d.depth
+                            FROM trash t JOIN directories d ON d.owner = t.owner AND d.sid = t.sid
+                            WHERE t.owner = $1 AND t.sid = $2",
+                        )
+                        .bind(owner as i64)
+                        .bind(id)
+                        .fetch_optional(&mut *tx)
+                        .await?
+                        .ok_or(DaError::TrashEntryNotFound(id))?;
+
+                    // The original parent may have been deleted in the meantime, the directory is then restored as a root
+                    let now = Utc::now();
+                    let parent_depth: Option<i32> = match original_parent {
+                        Some(parent_sid) => {
+                            sqlx::query_scalar(
+                                "UPDATE directories SET updated_at = $3, version = version + 1 WHERE owner = $1 AND sid = $2 AND NOT trashed RETURNING depth",
+                            )
+                            .bind(owner as i64)
+                            .bind(parent_sid)
+                            .bind(now)
+                            .fetch_optional(&mut *tx)
+                            .await?
+                        }
+                        None => None,
+                    };
+                    let parent = original_parent.filter(|_| parent_depth.is_some());
+                    let depth = parent_depth.map_or(0, |parent_depth| parent_depth + 1);
+
+                    let max_depth: i32 = sqlx::query_scalar(SUBTREE_MAX_DEPTH_QUERY)
+                        .bind(owner as i64)
+                        .bind(id)
+                        .fetch_one(&mut *tx)
+                        .await?;
+                    self.config
+                        .depth_limit
+                        .check(owner, (max_depth - old_depth + depth) as u32)?;
+                    check_sibling_names(
+                        &mut tx,
+                        self.config.sibling_names,
+                        owner,
+                        parent,
+                        &name,
+                        None,
+                    )
+                    .await?;
+
+                    sqlx::query(RESTORE_SUBTREE_QUERY)
+                        .bind(owner as i64)
+                        .bind(id)
+                        .bind(depth - old_depth)
+                        .execute(&mut *tx)
+                        .await?;
+                    sqlx::query(
+                        "UPDATE directories SET parent_sid = $3, updated_at = $4, version = version + 1 WHERE owner = $1 AND sid = $2",
+                    )
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(parent)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+                    sqlx::query("DELETE FROM trash WHERE owner = $1 AND sid = $2")
+                        .bind(owner as i64)
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                    record(
+                        &mut tx,
+                        owner,
+                        DaAuditRecord::new(requested_by, DaAuditOperation::RestoreDirectory, id)
+                            .after(json!({ "parent_sid": parent })),
+                    )
+                    .await?;
+                    let grantees = fetch_grantees(&mut *tx, owner, id, None).await?;
+                    tx.commit().await?;
+                    self.events.publish(
+                        DaChangeEvent::new(
+                            id,
+                            owner,
+                            requested_by,
+                            DaChange::Created {
+                                name,
+                                parent_sid: parent,
+                            },
+                        )
+                        .with_grantees(grantees),
+                    );
+                    self.get_directory(requested_by, id).await
+                }
+
+                async fn purge_directory(&self, requested_by: EntityUid, id: DaDirectorySid) -> DaResult<()> {
+                    self.purge(requested_by, id, None, Some(requested_by)).await
+                }
+
+                async fn list_audit_events(
+                    &self,
+                    requested_by: EntityUid,
+                    filter: DaAuditFilter,
+                    cursor: Option<DaCursor>,
+                    limit: u32,
+                ) -> DaResult<DaPage<DaAuditEvent>> {
+                    // Take one more event than requested to know whether there is a next page
+                    let rows: Vec<SqlDaAuditEvent> = sqlx::query_as(AUDIT_QUERY)
+                        .bind(requested_by as i64)
+                        .bind(filter.sid)
+                        .bind(filter.since)
+                        .bind(filter.until)
+                        .bind(cursor.map(|cursor| cursor.last_event_id()))
+                        .bind(limit as i64 + 1)
+                        .fetch_all(&self.pool)
+                        .await?;
+                    let events = rows
+                        .into_iter()
+                        .map(DaAuditEvent::try_from)
+                        .collect::<DaResult<_>>()?;
+                    Ok(DaPage::from_overfetched(events, limit, |event| {
+                        DaCursor::after_event(event.id)
+                    }))
+                }
+
+                fn config(&self) -> &DaRepositoryConfig {
+                    &self.config
+                }
+
+                fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DaChangeEvent> {
+                    self.events.subscribe()
+                }
+
+                async fn purge_expired_trash(&self) -> DaResult<usize> {
+                    let Some(expiry) = self.config.trash_expiry(Utc::now()) else {
+                        return Ok(0);
+                    };
+                    let expired: Vec<(i64, DaDirectorySid)> = sqlx::query_as(
+                        "SELECT owner, sid FROM trash WHERE deleted_at < $1 ORDER BY owner, sid",
+                    )
+                    .bind(expiry)
+                    .fetch_all(&self.pool)
+                    .await?;
+                    let mut purged = 0;
+                    for (owner, sid) in expired {
+                        match self
+                            .purge(owner as EntityUid, sid, Some(expiry), None)
+                            .await
+                        {
+                            Ok(()) => purged += 1,
+                            // Restored in the meantime
+                            Err(DaError::TrashEntryNotFound(_)) => {}
+                            Err(err) => return Err(err),
+                        }
+                    }
+                    Ok(purged)
+                }
+            }
+
+            /// Ensures that `requested_by` owns the directory or was granted `permission` on it (or on one of its ancestors).
+            /// Returns the owner of the directory.
+            async fn authorize<'e>(
+                executor: impl sqlx::Executor<'e, Database = $db>,
+                requested_by: EntityUid,
+                id: DaDirectorySid,
+                permission: DaPermission,
+            ) -> DaResult<EntityUid> {
+                let (owner, granted): (Option<i64>, bool) = sqlx::query_as(ACCESS_QUERY)
+                    .bind(id)
+                    .bind(requested_by as i64)
+                    .bind(permission.as_str())
+                    .fetch_one(executor)
+                    .await?;
+                access_decision(requested_by, id, owner, granted)
+            }
+
+            /// The entities that may see a change of the directory `id` besides its owner (see `GRANTEES_QUERY`).
+            async fn fetch_grantees<'e>(
+                executor: impl sqlx::Executor<'e, Database = $db>,
+                owner: EntityUid,
+                id: DaDirectorySid,
+                old_parent: Option<DaDirectorySid>,
+            ) -> DaResult<Vec<EntityUid>> {
+                let grantees: Vec<i64> = sqlx::query_scalar(GRANTEES_QUERY)
+                    .bind(owner as i64)
+                    .bind(id)
+                    .bind(old_parent)
+                    .fetch_all(executor)
+                    .await?;
+                Ok(grantees
+                    .into_iter()
+                    .map(|grantee| grantee as EntityUid)
+                    .collect())
+            }
+
+            /// The entities that only see the sid of the directory `id` when it is deleted (see `SUBTREE_GRANTEES_QUERY`).
+            async fn fetch_subtree_grantees<'e>(
+                executor: impl sqlx::Executor<'e, Database = $db>,
+                owner: EntityUid,
+                id: DaDirectorySid,
+            ) -> DaResult<Vec<EntityUid>> {
+                let grantees: Vec<i64> = sqlx::query_scalar(SUBTREE_GRANTEES_QUERY)
+                    .bind(owner as i64)
+                    .bind(id)
+                    .fetch_all(executor)
+                    .await?;
+                Ok(grantees
+                    .into_iter()
+                    .map(|grantee| grantee as EntityUid)
+                    .collect())
+            }
+
+            /// Ensures that `requested_by` owns the directory, grants don't allow to manage the grants of a directory.
+            async fn authorize_owner<'e>(
+                executor: impl sqlx::Executor<'e, Database = $db>,
+                requested_by: EntityUid,
+                id: DaDirectorySid,
+            ) -> DaResult<EntityUid> {
+                let owner: Option<i64> = sqlx::query_scalar("SELECT owner FROM directories WHERE sid = $1")
+                    .bind(id)
+                    .fetch_optional(executor)
+                    .await?;
+                access_decision(requested_by, id, owner, false)
+            }
+
+            /// Ensures that `requested_by` has `permission` on the directory of a file.
+            /// Returns the owner of the directory and the file.
+            async fn authorize_file(
+                conn: &mut <$db as sqlx::Database>::Connection,
+                requested_by: EntityUid,
+                id: DaFileSid,
+                permission: DaPermission,
+            ) -> DaResult<(EntityUid, SqlDaFile)> {
+                let file: SqlDaFile = sqlx::query_as(
+                    "SELECT sid, directory_sid, name, size, content_type, content_hash, blob_key, created_at, updated_at, created_by
+                    FROM files WHERE sid = $1",
+                )
+                .bind(id)
+                .fetch_optional(&mut *conn)
+                .await?
+                .ok_or(DaError::FileNotFound(id))?;
+                let owner = authorize(&mut *conn, requested_by, file.directory_sid, permission)
+                    .await
+                    .map_err(|err| match err {
+                        // The directory is in the trash
+                        DaError::DirectoryNotFound(_) => DaError::FileNotFound(id),
+                        err => err,
+                    })?;
+                Ok((owner, file))
+            }
+
+            /// Ensures that no directory under `parent` (among the root directories if none) other than `except` is named like `name`.
+            async fn check_sibling_names(
+                conn: &mut <$db as sqlx::Database>::Connection,
+                policy: SiblingNamePolicy,
+                owner: EntityUid,
+                parent: Option<DaDirectorySid>,
+                name: &str,
+                except: Option<DaDirectorySid>,
+            ) -> DaResult<()> {
+                if !policy.is_unique() {
+                    return Ok(());
+                }
+                let siblings: Vec<String> = sqlx::query_scalar(SIBLING_NAMES_QUERY)
+                    .bind(owner as i64)
+                    .bind(parent)
+                    .bind(except)
+                    .fetch_all(conn)
+                    .await?;
+                policy.check(siblings.iter().map(String::as_str), name)
+            }
+
+            /// Appends an operation to the audit log of `owner`, within the transaction of the operation.
+            async fn record(
+                conn: &mut <$db as sqlx::Database>::Connection,
+                owner: EntityUid,
+                record: DaAuditRecord,
+            ) -> DaResult<()> {
+                sqlx::query(AUDIT_INSERT_QUERY)
+                    .bind(owner as i64)
+                    .bind(record.entity.map(|entity| entity as i64))
+                    .bind(record.operation.as_str())
+                    .bind(record.sid)
+                    .bind(record.file_sid)
+                    .bind(record.old_value.map(|value| value.to_string()))
+                    .bind(record.new_value.map(|value| value.to_string()))
+                    .bind(Utc::now())
+                    .execute(conn)
+                    .await?;
+                Ok(())
+            }
+
+            /// Takes the lock on the tree of `owner` for an operation (see `lock_owner`), unless the operation is part of a batch.
+            /// The batch already holds the exclusive lock on the tree of `batch_owner`, the operation must apply to that tree.
+            async fn lock_owner_in(
+                tx: &mut sqlx::Transaction<'_, $db>,
+                owner: EntityUid,
+                exclusive: bool,
+                batch_owner: Option<EntityUid>,
+            ) -> DaResult<()> {
+                match batch_owner {
+                    Some(batch_owner) => check_batch_owner(batch_owner, owner),
+                    None => lock_owner(tx, owner, exclusive).await,
+                }
+            }
+        }
+    };
+}
+pub(crate) use sql_repository;
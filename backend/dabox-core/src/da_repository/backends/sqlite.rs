@@ -0,0 +1,184 @@
+//! A SQLite-backed implementation of the `DaRepository` trait.
+//! Meant for single-node deployments that need persistence without running a database server.
+//! The layout and the queries are shared with the Postgres backend (see `sql_repository`), except for sids that are generated
+//! from a single row sequence table and for the scoring of fuzzy searches.
+use std::str::FromStr;
+
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+
+use super::sql::{sql_repository, SqlDaSearchResult, SEARCH_QUERY};
+use crate::da_search::FUZZY_THRESHOLD;
+use crate::prelude::*;
+
+/// Embedded migrations of the SQLite backend.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// A SQLite-backed implementation of the `DaRepository` trait.
+/// Writes are serialized by SQLite itself, reads can still be served concurrently.
+#[derive(Clone)]
+pub struct SqliteRepository {
+    pool: SqlitePool,
+    blob_store: Arc<dyn DaBlobStore>,
+    config: Arc<DaRepositoryConfig>,
+    events: DaEventBus,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            blob_store: Arc::new(MemBlobStore::new()),
+            config: Arc::new(DaRepositoryConfig::default()),
+            events: DaEventBus::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: DaRepositoryConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Stores the content of the files in `blob_store` (contents are kept in memory by default).
+    pub fn with_blob_store(mut self, blob_store: Arc<dyn DaBlobStore>) -> Self {
+        self.blob_store = blob_store;
+        self
+    }
+
+    /// Publishes the changes on `events` instead of a bus of its own.
+    pub fn with_event_bus(mut self, events: DaEventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Opens (or creates) the database file at `url` and applies the pending migrations.
+    pub async fn connect(url: &str) -> DaResult<Self> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .foreign_keys(true);
+        let repository = Self::new(SqlitePool::connect_with(options).await?);
+        repository.migrate().await?;
+        Ok(repository)
+    }
+
+    /// Applies the embedded migrations that are not yet applied to the database.
+    pub async fn migrate(&self) -> DaResult<()> {
+        MIGRATOR.run(&self.pool).await?;
+        self.backfill_search_names().await
+    }
+
+    /// Begins a transaction that holds the database write lock.
+    /// The first statement is a (no-op) write: a transaction that reads first and writes later
+    /// fails with `SQLITE_BUSY` instead of waiting if another connection writes in the meantime.
+    async fn begin_write(&self) -> DaResult<sqlx::Transaction<'static, sqlx::Sqlite>> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE directory_sid_sequence SET next_sid = next_sid")
+            .execute(&mut *tx)
+            .await?;
+        Ok(tx)
+    }
+
+    /// Ranks the directories whose name is similar to the text of a fuzzy search.
+    /// SQLite can't score the names, every directory in the scope of the search is fetched and scored here (see `DaSearchQuery::score`).
+    /// Returns up to `limit + 1` results after the cursor, without their path.
+    async fn fuzzy_search(
+        &self,
+        requested_by: EntityUid,
+        query: &DaSearchQuery,
+        cursor: Option<&DaCursor>,
+        limit: u32,
+    ) -> DaResult<Vec<DaSearchResult>> {
+        let rows: Vec<SqlDaSearchResult> = sqlx::query_as(SEARCH_QUERY)
+            .bind(requested_by as i64)
+            .bind(query.like_pattern())
+            .bind(query.scope)
+            .bind(None::<DaDirectorySid>)
+            .bind(-1)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut ranked = Vec::new();
+        for row in rows {
+            let mut result = DaSearchResult::from(row);
+            result.score = query.score(&result.name);
+            let after_cursor = match cursor {
+                Some(cursor) => result.is_after(cursor)?,
+                None => true,
+            };
+            if after_cursor && result.score >= Some(FUZZY_THRESHOLD) {
+                ranked.push(result);
+            }
+        }
+        ranked.sort_by(DaSearchResult::compare);
+        ranked.truncate(limit as usize + 1);
+        Ok(ranked)
+    }
+}
+
+sql_repository!(SqliteRepository, sqlx::Sqlite, for_update: "");
+
+/// Writes are serialized by `begin_write`, which already holds the database write lock for the whole transaction.
+async fn lock_owner(
+    _tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    _owner: EntityUid,
+    _exclusive: bool,
+) -> DaResult<()> {
+    Ok(())
+}
+
+/// Draws `count` new directory sids from the `directory_sid_sequence` table.
+async fn allocate_sids(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    count: usize,
+) -> DaResult<Vec<DaDirectorySid>> {
+    let first_sid: i64 = sqlx::query_scalar(
+        "UPDATE directory_sid_sequence SET next_sid = next_sid + $1 RETURNING next_sid - $1",
+    )
+    .bind(count as i64)
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok((first_sid..first_sid + count as i64)
+        .map(DaDirectorySid)
+        .collect())
+}
+
+/// Tests for the SQLite backend
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::da_repository::backends::sql::VALUES_BATCH;
+    use crate::da_repository::suite;
+
+    suite::backend_suite!(#[sqlx::test(migrations = "./migrations/sqlite")] (pool: SqlitePool) => SqliteRepository::new(pool));
+
+    #[sqlx::test(migrations = "./migrations/sqlite")]
+    async fn test_migrate_folds_search_names(pool: SqlitePool) {
+        let repo = SqliteRepository::new(pool.clone());
+        let dir = repo.create_directory(1, "Été", None).await.unwrap();
+        // Enough directories to take more than one batch of the backfill
+        let operations: Vec<_> = (0..VALUES_BATCH)
+            .map(|idx| DaBatchOperation::Create {
+                temp_id: idx.to_string(),
+                name: format!("Dir {idx}"),
+                parent: Some(DaBatchRef::Sid(dir.sid)),
+            })
+            .collect();
+        repo.apply_batch(1, &operations).await.unwrap();
+        sqlx::query("UPDATE directories SET search_name = NULL")
+            .execute(&pool)
+            .await
+            .unwrap();
+        repo.migrate().await.unwrap();
+        let unfolded: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM directories WHERE search_name IS NULL")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(unfolded, 0);
+        let query = DaSearchQuery::new("été", DaSearchMode::Prefix);
+        let page = repo.search_directories(1, query, None, 10).await.unwrap();
+        assert_eq!(page.items[0].sid, dir.sid);
+        let query = DaSearchQuery::new("DIR 999", DaSearchMode::Prefix);
+        let page = repo.search_directories(1, query, None, 10).await.unwrap();
+        assert_eq!(page.items[0].path, vec!["Été", "Dir 999"]);
+    }
+}
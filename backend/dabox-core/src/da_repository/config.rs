@@ -0,0 +1,254 @@
+//! Configuration shared by every `DaRepository` backend.
+use crate::prelude::*;
+use unicode_normalization::UnicodeNormalization;
+
+/// Maximum length of a name (in characters) if not configured.
+pub const DEFAULT_MAX_NAME_LENGTH: usize = 255;
+
+/// Configuration of a repository, enforced the same way by every backend.
+#[derive(Debug, Clone, Default)]
+pub struct DaRepositoryConfig {
+    /// Maximum depth the directories can reach
+    pub depth_limit: DepthLimit,
+    /// Whether sibling directories may share the same name
+    pub sibling_names: SiblingNamePolicy,
+    /// How the names of the directories and files are validated and normalised
+    pub names: NameRules,
+    /// How long deleted directories are kept in the trash before being purged (forever if none)
+    pub trash_retention: Option<std::time::Duration>,
+}
+
+/// Validation and normalisation of the names given to directories and files.
+/// Names are stored normalised, control characters and empty names are always rejected.
+#[derive(Debug, Clone)]
+pub struct NameRules {
+    /// Maximum number of characters of a name (after normalisation)
+    pub max_length: usize,
+    /// Characters that can't appear in a name
+    pub forbidden_chars: Vec<char>,
+    /// Whether leading and trailing whitespaces are removed
+    pub trim: bool,
+    /// Whether names are converted to the Unicode normalisation form C (NFC), so that `é` is the same name whether it was typed as one or two code points
+    pub nfc: bool,
+}
+
+/// How the names of sibling directories (children of the same parent, or root directories of the same owner) are constrained.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SiblingNamePolicy {
+    /// Siblings may share the same name
+    #[default]
+    AllowDuplicates,
+    /// Siblings must have different names
+    CaseSensitive,
+    /// Siblings must have different names, ignoring case (`Reports` and `reports` conflict)
+    CaseInsensitive,
+}
+
+/// Maximum depth a directory can reach in the hierarchy (a root directory has a depth of 0).
+#[derive(Debug, Clone, Default)]
+pub struct DepthLimit {
+    /// Limit of the entities without a specific limit (none means unlimited)
+    pub default: Option<u32>,
+    /// Limits of specific entities, overriding `default`
+    pub per_entity: HashMap<EntityUid, u32>,
+}
+
+impl DaRepositoryConfig {
+    pub fn with_depth_limit(mut self, depth_limit: DepthLimit) -> Self {
+        self.depth_limit = depth_limit;
+        self
+    }
+
+    pub fn with_sibling_names(mut self, sibling_names: SiblingNamePolicy) -> Self {
+        self.sibling_names = sibling_names;
+        self
+    }
+
+    pub fn with_names(mut self, names: NameRules) -> Self {
+        self.names = names;
+        self
+    }
+
+    pub fn with_trash_retention(mut self, trash_retention: std::time::Duration) -> Self {
+        self.trash_retention = Some(trash_retention);
+        self
+    }
+
+    /// Deletion time before which the trash entries are expired (none if they never expire).
+    pub fn trash_expiry(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let retention = chrono::Duration::from_std(self.trash_retention?).ok()?;
+        now.checked_sub_signed(retention)
+    }
+}
+
+impl Default for NameRules {
+    /// Names of at most 255 characters without `/` (the path separator) nor `\`, trimmed and NFC normalised.
+    fn default() -> Self {
+        Self {
+            max_length: DEFAULT_MAX_NAME_LENGTH,
+            forbidden_chars: vec!['/', '\\'],
+            trim: true,
+            nfc: true,
+        }
+    }
+}
+
+impl NameRules {
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    pub fn with_forbidden_chars(mut self, forbidden_chars: impl IntoIterator<Item = char>) -> Self {
+        self.forbidden_chars = forbidden_chars.into_iter().collect();
+        self
+    }
+
+    pub fn with_trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    pub fn with_nfc(mut self, nfc: bool) -> Self {
+        self.nfc = nfc;
+        self
+    }
+
+    /// Returns the name as it must be stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::InvalidName` if the normalised name is empty, too long or contains a control or forbidden character.
+    pub fn normalize(&self, name: &str) -> DaResult<String> {
+        let name = if self.trim { name.trim() } else { name };
+        let name: String = if self.nfc {
+            name.nfc().collect()
+        } else {
+            name.to_string()
+        };
+        if name.is_empty() {
+            return Err(DaError::InvalidName("the name is empty".to_string()));
+        }
+        if name.chars().count() > self.max_length {
+            return Err(DaError::InvalidName(format!(
+                "the name is longer than {} characters",
+                self.max_length
+            )));
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| c.is_control() || self.forbidden_chars.contains(c))
+        {
+            return Err(DaError::InvalidName(format!(
+                "the name contains the forbidden character {c:?}"
+            )));
+        }
+        Ok(name)
+    }
+
+    /// Normalises the names of a path so that it matches the stored names.
+    /// Invalid names are kept as is, they can't match any directory anyway.
+    pub fn normalize_path(&self, path: &[&str]) -> Vec<String> {
+        path.iter()
+            .map(|name| self.normalize(name).unwrap_or_else(|_| name.to_string()))
+            .collect()
+    }
+}
+
+impl SiblingNamePolicy {
+    /// Whether sibling names must be unique, backends only look the siblings up in that case.
+    pub fn is_unique(self) -> bool {
+        self != Self::AllowDuplicates
+    }
+
+    /// Whether two sibling names are considered the same.
+    pub fn conflicts(self, a: &str, b: &str) -> bool {
+        match self {
+            Self::AllowDuplicates => false,
+            Self::CaseSensitive => a == b,
+            Self::CaseInsensitive => a == b || a.to_lowercase() == b.to_lowercase(),
+        }
+    }
+
+    /// Ensures that a directory can be named `name` next to `siblings`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::NameConflict` if one of the siblings has the same name.
+    pub fn check<'a>(
+        self,
+        siblings: impl IntoIterator<Item = &'a str>,
+        name: &str,
+    ) -> DaResult<()> {
+        match siblings
+            .into_iter()
+            .find(|sibling| self.conflicts(sibling, name))
+        {
+            Some(sibling) => Err(DaError::NameConflict(sibling.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl DepthLimit {
+    pub fn new(default: Option<u32>) -> Self {
+        Self {
+            default,
+            per_entity: HashMap::new(),
+        }
+    }
+
+    /// Overrides the limit of a specific entity.
+    pub fn with_entity_limit(mut self, uid: EntityUid, limit: u32) -> Self {
+        self.per_entity.insert(uid, limit);
+        self
+    }
+
+    /// Returns the limit that applies to the directories of `uid` (if any).
+    pub fn for_entity(&self, uid: EntityUid) -> Option<u32> {
+        self.per_entity.get(&uid).copied().or(self.default)
+    }
+
+    /// Ensures that a directory of `uid` is allowed at `depth`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::DirectoryDepthLimitExceeded` if `depth` is above the limit of `uid`.
+    pub fn check(&self, uid: EntityUid, depth: u32) -> DaResult<()> {
+        match self.for_entity(uid) {
+            Some(limit) if depth > limit => Err(DaError::DirectoryDepthLimitExceeded(limit)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_rules() {
+        let rules = NameRules::default();
+        assert_eq!(rules.normalize("  reports \t").unwrap(), "reports");
+        // `e` followed by a combining acute accent becomes a single `é`
+        assert_eq!(rules.normalize("cafe\u{301}").unwrap(), "caf\u{e9}");
+        for invalid in ["", "   ", "a/b", "a\\b", "line\nbreak", "nul\0"] {
+            assert!(matches!(
+                rules.normalize(invalid),
+                Err(DaError::InvalidName(_))
+            ));
+        }
+        assert!(rules.normalize(&"a".repeat(255)).is_ok());
+        assert!(rules.normalize(&"a".repeat(256)).is_err());
+
+        let rules = NameRules::default()
+            .with_max_length(3)
+            .with_forbidden_chars([':'])
+            .with_trim(false)
+            .with_nfc(false);
+        assert_eq!(rules.normalize(" a/").unwrap(), " a/");
+        assert!(rules.normalize("a:b").is_err());
+        assert!(rules.normalize("abcd").is_err());
+        assert_eq!(rules.normalize("e\u{301}").unwrap(), "e\u{301}");
+    }
+}
@@ -1,12 +1,59 @@
 /// This module contains extensions trait that allow persistence of `DaDirectory` objects across agnostic backends (e.g. Postgres, SQLite, etc.).
 use crate::prelude::*;
 pub(crate) mod backends;
+pub mod config;
+#[cfg(test)]
+pub(crate) mod suite;
 
 pub use backends::mem::MemRepository;
+pub use config::{DaRepositoryConfig, DepthLimit, NameRules, SiblingNamePolicy};
 
 #[cfg(feature = "database")]
 pub use backends::pg::PgRepository;
 
+#[cfg(feature = "sqlite")]
+pub use backends::sqlite::SqliteRepository;
+
+/// Formats the first `len` names of a path the way the errors report it (ex: `/projects/2026`).
+pub(crate) fn path_prefix(path: &[&str], len: usize) -> String {
+    format!("/{}", path[..len].join("/"))
+}
+
+/// Ensures that the directory `sid` at version `actual` is at the `expected` version (if any).
+pub(crate) fn check_version(
+    sid: DaDirectorySid,
+    expected: Option<u64>,
+    actual: u64,
+) -> DaResult<()> {
+    match expected {
+        Some(expected) if expected != actual => Err(DaError::VersionMismatch {
+            sid,
+            expected,
+            actual,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Keeps the ancestors (listed from the root down, with whether `requested_by` was granted access to each of them)
+/// from the topmost one `requested_by` can read, the owner reads all of them.
+pub(crate) fn readable_ancestors(
+    ancestors: Vec<(DaAncestor, bool)>,
+    is_owner: bool,
+) -> Vec<DaAncestor> {
+    ancestors
+        .into_iter()
+        .skip_while(|(_, granted)| !is_owner && !granted)
+        .map(|(ancestor, _)| ancestor)
+        .collect()
+}
+
+/// Every method is subject to access control: the owner of a directory has full access to it,
+/// other entities need a `DaGrant` on the directory or one of its ancestors (`DaError::AccessDenied` otherwise).
+/// Directories created by a grantee belong to the owner of the parent directory.
+/// Names of directories and files are validated and normalised by the `NameRules` of the repository (`DaError::InvalidName` otherwise).
+/// Names of sibling directories are constrained by the `SiblingNamePolicy` of the repository (`DaError::NameConflict` otherwise).
+/// Files are subject to the access control of the directory containing them.
 pub trait DaRepository: Clone + Send + Sync + Sized {
     /// Creates a new directory.
     ///
@@ -15,6 +62,10 @@ pub trait DaRepository: Clone + Send + Sync + Sized {
     /// * `requested_by` - The Uid of the entity that is requesting the creation of the directory.
     /// * `name` - The name of the directory.
     /// * `parent` - The parent directory of the new directory (if any).
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::DirectoryDepthLimitExceeded` if the new directory would exceed the depth limit of `requested_by`.
     fn create_directory(
         &self,
         requested_by: EntityUid,
@@ -32,9 +83,81 @@ pub trait DaRepository: Clone + Send + Sync + Sized {
         &self,
         requested_by: EntityUid,
         id: DaDirectorySid,
+    ) -> impl Future<Output = DaResult<DaDirectory>> + Send {
+        self.get_directory_with_depth(requested_by, id, None)
+    }
+
+    /// Retrieves a directory by its ID with its descendants up to a given depth.
+    /// Directories at the depth boundary have `has_unloaded_children` set if they have children.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the retrieval of the directory.
+    /// * `id` - The ID of the directory to retrieve.
+    /// * `depth` - The number of descendant levels to load, relative to the directory (none to load all of them, `0` to load none).
+    fn get_directory_with_depth(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        depth: Option<u32>,
+    ) -> impl Future<Output = DaResult<DaDirectory>> + Send;
+
+    /// Resolves a path of directory names, starting from the root directories owned by `requested_by`
+    /// (ex: `["projects", "2026", "reports"]` for `/projects/2026/reports`).
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity whose tree is walked.
+    /// * `path` - The names of the directories, from the root down to the resolved directory.
+    /// * `depth` - The number of descendant levels to load, same as `get_directory_with_depth`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::PathNotFound` if a name matches no directory (or if `path` is empty)
+    /// and `DaError::AmbiguousPath` if it matches several sibling directories.
+    fn resolve_path(
+        &self,
+        requested_by: EntityUid,
+        path: &[&str],
+        depth: Option<u32>,
     ) -> impl Future<Output = DaResult<DaDirectory>> + Send;
 
-    /// Deletes a directory by its ID.
+    /// Lists the ancestors of a directory from the root down, without the directory itself (empty for a root directory).
+    /// Entities other than the owner only get the ancestors they can read, from the directory they were granted access to.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the ancestors.
+    /// * `id` - The ID of the directory whose ancestors are listed.
+    fn list_ancestors(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> impl Future<Output = DaResult<Vec<DaAncestor>>> + Send;
+
+    /// Searches the directories owned by `requested_by` by name, one page at a time, ordered by sid
+    /// (by decreasing score then by sid for fuzzy searches). Directories in the trash are not searched.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity whose tree is searched.
+    /// * `query` - The searched text, how it is matched and the subtree the search is limited to.
+    /// * `cursor` - The `next_cursor` of the previous page (none for the first page), it must come from a search with the same `query`.
+    /// * `limit` - The maximum number of results in the page.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::AccessDenied` if the scope of the search is not owned by `requested_by`.
+    fn search_directories(
+        &self,
+        requested_by: EntityUid,
+        query: DaSearchQuery,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> impl Future<Output = DaResult<DaPage<DaSearchResult>>> + Send;
+
+    /// Moves a directory, along with its descendants and their files, to the trash of its owner.
+    /// The directory is detached from its parent and is not reachable anymore until it is restored.
     ///
     /// # Arguments
     ///
@@ -44,6 +167,26 @@ pub trait DaRepository: Clone + Send + Sync + Sized {
         &self,
         requested_by: EntityUid,
         id: DaDirectorySid,
+    ) -> impl Future<Output = DaResult<()>> + Send {
+        self.delete_directory_if_version(requested_by, id, None)
+    }
+
+    /// Moves a directory to the trash of its owner (same as `delete_directory`) if it is still at the expected version.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the deletion of the directory.
+    /// * `id` - The ID of the directory to delete.
+    /// * `expected_version` - The `version` of the directory the request is based on (none to delete whatever the version).
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::VersionMismatch` if the directory was updated since `expected_version`.
+    fn delete_directory_if_version(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        expected_version: Option<u64>,
     ) -> impl Future<Output = DaResult<()>> + Send;
 
     /// Renames a directory by its ID.
@@ -58,5 +201,372 @@ pub trait DaRepository: Clone + Send + Sync + Sized {
         requested_by: EntityUid,
         id: DaDirectorySid,
         name: &str,
+    ) -> impl Future<Output = DaResult<()>> + Send {
+        self.rename_directory_if_version(requested_by, id, name, None)
+    }
+
+    /// Renames a directory (same as `rename_directory`) if it is still at the expected version.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the renaming of the directory.
+    /// * `id` - The ID of the directory to rename.
+    /// * `name` - The new name of the directory.
+    /// * `expected_version` - The `version` of the directory the request is based on (none to rename whatever the version).
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::VersionMismatch` if the directory was updated since `expected_version`.
+    fn rename_directory_if_version(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        name: &str,
+        expected_version: Option<u64>,
+    ) -> impl Future<Output = DaResult<()>> + Send;
+
+    /// Moves a directory (and all its descendants) under a new parent.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the move of the directory.
+    /// * `id` - The ID of the directory to move.
+    /// * `parent` - The new parent directory (none to make the directory a root).
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::DirectoryCycle` if `parent` is the directory itself or one of its descendants
+    /// and `DaError::DirectoryDepthLimitExceeded` if one of the moved directories would exceed the depth limit of `requested_by`.
+    fn move_directory(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        parent: Option<DaDirectorySid>,
     ) -> impl Future<Output = DaResult<()>> + Send;
+
+    /// Copies a directory and all its descendants under a new parent.
+    /// The copies get fresh sids, their depth is recomputed from the new parent and they are created by `requested_by`.
+    /// The copies carry the tags of their originals, files and grants are not copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the copy (needs to read the directory and to write in `parent`).
+    /// * `id` - The ID of the copied directory.
+    /// * `parent` - The parent of the copy (none to make the copy a root of `requested_by`).
+    /// * `name` - The name of the copy (the name of the copied directory if none).
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::DirectoryDepthLimitExceeded` if one of the copies would exceed the depth limit of the owner of `parent`.
+    fn copy_directory(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        parent: Option<DaDirectorySid>,
+        name: Option<&str>,
+    ) -> impl Future<Output = DaResult<DaDirectory>> + Send;
+
+    /// Applies a batch of operations in order, all or none: if an operation fails, the previous ones are undone.
+    /// Each operation is subject to the same rules as the method of the same name (audit log and change events included),
+    /// the changes are only visible to the other requests once the whole batch is applied.
+    /// The operations of a batch must apply to the tree of a single owner (root directories are created in the tree of `requested_by`).
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the operations.
+    /// * `operations` - The operations, the directories they create can be referenced by the next ones through their temporary id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::BatchOperationFailed` with the index and the error of the first operation that failed,
+    /// the error is `DaError::InvalidBatch` if it references an unknown temporary id, reuses one or targets the tree of another owner.
+    fn apply_batch(
+        &self,
+        requested_by: EntityUid,
+        operations: &[DaBatchOperation],
+    ) -> impl Future<Output = DaResult<DaBatchResult>> + Send;
+
+    /// Lists the direct children of a directory ordered by sid, one page at a time.
+    /// The children are returned without their descendants (`has_unloaded_children` tells if they have any).
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the listing.
+    /// * `id` - The ID of the directory whose children are listed.
+    /// * `cursor` - The `next_cursor` of the previous page (none for the first page).
+    /// * `limit` - The maximum number of children in the page.
+    fn list_children(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> impl Future<Output = DaResult<DaPage<DaDirectory>>> + Send;
+
+    /// Lists the root directories (without parent) owned by an entity, one page at a time.
+    /// The directories are returned without their descendants (`has_unloaded_children` tells if they have any).
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity whose root directories are listed.
+    /// * `sort` - The order of the listing.
+    /// * `cursor` - The `next_cursor` of the previous page (none for the first page), it must come from a listing with the same `sort`.
+    /// * `limit` - The maximum number of directories in the page.
+    fn list_root_directories(
+        &self,
+        requested_by: EntityUid,
+        sort: DaSort,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> impl Future<Output = DaResult<DaPage<DaDirectory>>> + Send;
+
+    /// Grants (or updates) a permission on a directory and all its descendants to another entity.
+    /// Only the owner of the directory can manage its grants.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the grant (must be the owner).
+    /// * `id` - The ID of the shared directory.
+    /// * `grantee` - The Uid of the entity that is granted the permission.
+    /// * `permission` - The granted permission.
+    fn grant_access(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        grantee: EntityUid,
+        permission: DaPermission,
+    ) -> impl Future<Output = DaResult<DaGrant>> + Send;
+
+    /// Revokes the grant of an entity on a directory (grants on ancestors are left untouched).
+    /// Only the owner of the directory can manage its grants.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the revocation (must be the owner).
+    /// * `id` - The ID of the shared directory.
+    /// * `grantee` - The Uid of the entity whose grant is revoked.
+    fn revoke_access(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        grantee: EntityUid,
+    ) -> impl Future<Output = DaResult<()>> + Send;
+
+    /// Lists the grants set on a directory (grants on ancestors are not included).
+    /// Only the owner of the directory can manage its grants.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the listing (must be the owner).
+    /// * `id` - The ID of the shared directory.
+    fn list_grants(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> impl Future<Output = DaResult<Vec<DaGrant>>> + Send;
+
+    /// Attaches a tag to a directory (does nothing if the directory already carries it).
+    /// Returns the tags of the directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the tag (it must be able to write to the directory).
+    /// * `id` - The ID of the tagged directory.
+    /// * `tag` - The tag, normalised by `normalize_tag` (`DaError::InvalidTag` otherwise).
+    fn add_tag(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        tag: &str,
+    ) -> impl Future<Output = DaResult<Vec<String>>> + Send;
+
+    /// Removes a tag from a directory (does nothing if the directory doesn't carry it).
+    /// Returns the tags of the directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the removal (it must be able to write to the directory).
+    /// * `id` - The ID of the tagged directory.
+    /// * `tag` - The removed tag.
+    fn remove_tag(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+        tag: &str,
+    ) -> impl Future<Output = DaResult<Vec<String>>> + Send;
+
+    /// Lists the tags of the directories owned by `requested_by` with the number of directories carrying them, in alphabetical order.
+    /// Directories in the trash are not counted.
+    fn list_tags(
+        &self,
+        requested_by: EntityUid,
+    ) -> impl Future<Output = DaResult<Vec<DaTagCount>>> + Send;
+
+    /// Lists the directories owned by `requested_by` that match a set of tags, one page at a time, ordered by sid.
+    /// The directories are returned without their descendants (`has_unloaded_children` tells if they have any),
+    /// directories in the trash are not listed.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity whose directories are listed.
+    /// * `query` - The tags and whether the directories must carry all of them or any of them.
+    /// * `cursor` - The `next_cursor` of the previous page (none for the first page), it must come from a listing with the same `query`.
+    /// * `limit` - The maximum number of directories in the page.
+    fn list_tagged_directories(
+        &self,
+        requested_by: EntityUid,
+        query: DaTagQuery,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> impl Future<Output = DaResult<DaPage<DaDirectory>>> + Send;
+
+    /// Uploads a file into a directory.
+    /// The content is streamed to the blob store of the repository, its size and hash are computed on the fly.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is uploading the file.
+    /// * `directory` - The directory receiving the file.
+    /// * `name` - The name of the file.
+    /// * `content_type` - The media type of the content.
+    /// * `content` - The content of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::BlobStore` if the content can't be read or stored, nothing is created in that case.
+    fn create_file(
+        &self,
+        requested_by: EntityUid,
+        directory: DaDirectorySid,
+        name: &str,
+        content_type: &str,
+        content: DaByteStream,
+    ) -> impl Future<Output = DaResult<DaFile>> + Send;
+
+    /// Retrieves the metadata of a file by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the file.
+    /// * `id` - The ID of the file.
+    fn get_file(
+        &self,
+        requested_by: EntityUid,
+        id: DaFileSid,
+    ) -> impl Future<Output = DaResult<DaFile>> + Send;
+
+    /// Retrieves the metadata of a file along with a stream of its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is downloading the file.
+    /// * `id` - The ID of the file.
+    fn read_file(
+        &self,
+        requested_by: EntityUid,
+        id: DaFileSid,
+    ) -> impl Future<Output = DaResult<(DaFile, DaByteStream)>> + Send;
+
+    /// Deletes a file and its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the deletion of the file.
+    /// * `id` - The ID of the file.
+    fn delete_file(
+        &self,
+        requested_by: EntityUid,
+        id: DaFileSid,
+    ) -> impl Future<Output = DaResult<()>> + Send;
+
+    /// Lists the files of a directory ordered by sid, one page at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the listing.
+    /// * `directory` - The directory whose files are listed.
+    /// * `cursor` - The `next_cursor` of the previous page (none for the first page).
+    /// * `limit` - The maximum number of files in the page.
+    fn list_files(
+        &self,
+        requested_by: EntityUid,
+        directory: DaDirectorySid,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> impl Future<Output = DaResult<DaPage<DaFile>>> + Send;
+
+    /// Lists the trash of an entity ordered by sid, one page at a time.
+    /// The trash holds the directories owned by the entity that were deleted, by the entity itself or by a grantee.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity whose trash is listed.
+    /// * `cursor` - The `next_cursor` of the previous page (none for the first page).
+    /// * `limit` - The maximum number of entries in the page.
+    fn list_trash(
+        &self,
+        requested_by: EntityUid,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> impl Future<Output = DaResult<DaPage<DaTrashEntry>>> + Send;
+
+    /// Restores a deleted directory and its descendants, under its original parent if it still exists (as a root directory otherwise).
+    /// The depth of the restored directories is recomputed from their new parent.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the restore (must be the owner).
+    /// * `id` - The ID of the deleted directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::TrashEntryNotFound` if the directory is not in the trash of `requested_by`,
+    /// `DaError::NameConflict` or `DaError::DirectoryDepthLimitExceeded` if it can't be restored at its new place.
+    fn restore_directory(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> impl Future<Output = DaResult<DaDirectory>> + Send;
+
+    /// Permanently deletes a directory of the trash with its descendants, their files, grants and tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity that is requesting the purge (must be the owner).
+    /// * `id` - The ID of the deleted directory.
+    fn purge_directory(
+        &self,
+        requested_by: EntityUid,
+        id: DaDirectorySid,
+    ) -> impl Future<Output = DaResult<()>> + Send;
+
+    /// Lists the audit log of an entity ordered by id (the order the operations were made in), one page at a time.
+    /// The log of an entity holds the operations made on the directories it owns, by the entity itself or by grantees.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_by` - The Uid of the entity whose audit log is listed.
+    /// * `filter` - The criteria the listed events must match.
+    /// * `cursor` - The `next_cursor` of the previous page (none for the first page), it must come from a listing with the same `filter`.
+    /// * `limit` - The maximum number of events in the page.
+    fn list_audit_events(
+        &self,
+        requested_by: EntityUid,
+        filter: DaAuditFilter,
+        cursor: Option<DaCursor>,
+        limit: u32,
+    ) -> impl Future<Output = DaResult<DaPage<DaAuditEvent>>> + Send;
+
+    /// The configuration the repository enforces (ex: to compare sibling names the way it does).
+    fn config(&self) -> &DaRepositoryConfig;
+
+    /// Subscribes to the changes of the directory trees (creations, renames, moves and deletions of directories).
+    /// Every change committed from now on is received whatever the owner of its directory,
+    /// subscribers serving an entity filter them with `DaChangeEvent::is_visible_to`.
+    fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DaChangeEvent>;
+
+    /// Purges the directories of every trash that were deleted for longer than the configured retention.
+    /// Does nothing if the repository has no trash retention.
+    /// Returns the number of purged directories (not counting their descendants).
+    fn purge_expired_trash(&self) -> impl Future<Output = DaResult<usize>> + Send;
 }
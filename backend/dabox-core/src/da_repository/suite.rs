@@ -0,0 +1,1843 @@
+//! The scenarios shared by the tests of every backend.
+//! Each backend runs all of them with `backend_suite!`, every scenario gets a fresh repository.
+use crate::entity::StaticEntity;
+use crate::prelude::*;
+
+/// The setters of the backends used by the scenarios, they are inherent methods of every backend.
+pub(crate) trait TestRepository: DaRepository + 'static {
+    fn with_config(self, config: DaRepositoryConfig) -> Self;
+    fn with_blob_store(self, blob_store: Arc<dyn DaBlobStore>) -> Self;
+}
+
+macro_rules! impl_test_repository {
+    ($($repository:ty),*) => {
+        $(impl TestRepository for $repository {
+            fn with_config(self, config: DaRepositoryConfig) -> Self {
+                <$repository>::with_config(self, config)
+            }
+
+            fn with_blob_store(self, blob_store: Arc<dyn DaBlobStore>) -> Self {
+                <$repository>::with_blob_store(self, blob_store)
+            }
+        })*
+    };
+}
+
+impl_test_repository!(MemRepository);
+#[cfg(feature = "database")]
+impl_test_repository!(PgRepository);
+#[cfg(feature = "sqlite")]
+impl_test_repository!(SqliteRepository);
+
+/// Declares a `shared` test module running every scenario against the repository built by `$repository`.
+///
+/// `$params` are the parameters of the generated tests, `$repository` may use them.
+macro_rules! backend_suite {
+    (#[$test:meta] $params:tt => $repository:expr) => {
+        mod shared {
+            use super::*;
+            $crate::da_repository::suite::backend_suite!(@tests #[$test] $params => $repository;
+                test_directory_creation_basic,
+                test_directory_hierarchy,
+                test_directory_rename_and_isolation,
+                test_directory_move,
+                test_directory_depth_limit,
+                test_directory_depth_limited_read,
+                test_directory_list_children,
+                test_directory_sharing,
+                test_directory_list_roots,
+                test_directory_timestamps,
+                test_file_storage,
+                test_resolve_path,
+                test_sibling_names,
+                test_name_validation,
+                test_copy_directory,
+                test_trash,
+                test_audit_log,
+                test_change_events,
+                test_directory_versions,
+                test_batch,
+                test_search_directories,
+                test_list_ancestors,
+                test_tags,
+            );
+        }
+    };
+    (@tests #[$test:meta] $params:tt => $repository:expr; $($scenario:ident,)*) => {
+        $(
+            #[$test]
+            async fn $scenario $params {
+                $crate::da_repository::suite::$scenario($repository).await;
+            }
+        )*
+    };
+}
+pub(crate) use backend_suite;
+
+pub(crate) async fn test_directory_creation_basic<R: DaRepository>(repo: R) {
+    let mut known_sids = HashMap::new();
+    for idx in 0..128 {
+        let name = format!("test-{}", idx);
+        let dir = repo
+            .create_directory(StaticEntity::root().uid(), &name, None)
+            .await
+            .unwrap();
+        assert!(!known_sids.contains_key(&dir.sid), "sid sequence broken");
+        known_sids.insert(dir.sid, name);
+    }
+    for (id, name) in known_sids.clone() {
+        let dir = repo
+            .get_directory(StaticEntity::root().uid(), id)
+            .await
+            .expect("unable to get created directory");
+        assert_eq!(dir.name, name);
+    }
+    for (id, _) in known_sids {
+        repo.delete_directory(StaticEntity::root().uid(), id)
+            .await
+            .unwrap();
+    }
+}
+
+pub(crate) async fn test_directory_hierarchy<R: DaRepository>(repo: R) {
+    let uid = StaticEntity::root().uid();
+    let root = repo.create_directory(uid, "root", None).await.unwrap();
+    let mut dir = root.clone();
+    let mut children = vec![];
+    for depth in 0..16 {
+        let child = repo
+            .create_directory(uid, &format!("child-{}", depth), Some(dir.sid))
+            .await
+            .unwrap();
+        children.push(child.clone());
+        dir = child;
+    }
+    assert_eq!(children.last().unwrap().depth, 16);
+
+    let mut tree = repo.get_directory(uid, root.sid).await.unwrap();
+    for child in children.iter() {
+        assert_eq!(tree.children.len(), 1);
+        tree = tree.children.remove(0);
+        assert_eq!(tree.sid, child.sid);
+        assert_eq!(tree.depth, child.depth);
+    }
+
+    repo.delete_directory(uid, children[10].sid).await.unwrap();
+    for child in children.iter().skip(10) {
+        assert!(repo.get_directory(uid, child.sid).await.is_err());
+    }
+    assert!(repo.get_directory(uid, children[9].sid).await.is_ok());
+    repo.delete_directory(uid, children[9].sid).await.unwrap();
+    assert!(repo.get_directory(uid, children[9].sid).await.is_err());
+    repo.delete_directory(uid, root.sid).await.unwrap();
+    assert!(repo.get_directory(uid, root.sid).await.is_err());
+    assert!(repo.get_directory(uid, children[0].sid).await.is_err());
+}
+
+pub(crate) async fn test_directory_rename_and_isolation<R: DaRepository>(repo: R) {
+    let dir = repo.create_directory(1, "before", None).await.unwrap();
+    repo.rename_directory(1, dir.sid, "after").await.unwrap();
+    assert_eq!(repo.get_directory(1, dir.sid).await.unwrap().name, "after");
+
+    // Sids are global, the directories of another owner are not reachable
+    let other = repo.create_directory(2, "other", None).await.unwrap();
+    assert_ne!(other.sid, dir.sid);
+    assert!(matches!(
+        repo.get_directory(2, dir.sid).await,
+        Err(DaError::AccessDenied { .. })
+    ));
+    assert!(matches!(
+        repo.rename_directory(2, dir.sid, "stolen").await,
+        Err(DaError::AccessDenied { .. })
+    ));
+    assert!(matches!(
+        repo.create_directory(2, "child", Some(dir.sid)).await,
+        Err(DaError::AccessDenied { .. })
+    ));
+
+    assert!(matches!(
+        repo.rename_directory(2, DaDirectorySid(42), "missing")
+            .await,
+        Err(DaError::DirectoryNotFound(_))
+    ));
+    assert!(matches!(
+        repo.create_directory(2, "child", Some(DaDirectorySid(42)))
+            .await,
+        Err(DaError::ParentNotFound(DaDirectorySid(42)))
+    ));
+}
+
+pub(crate) async fn test_directory_move<R: DaRepository>(repo: R) {
+    let uid = StaticEntity::root().uid();
+    let a = repo.create_directory(uid, "a", None).await.unwrap();
+    let b = repo.create_directory(uid, "b", None).await.unwrap();
+    let a1 = repo.create_directory(uid, "a1", Some(a.sid)).await.unwrap();
+    let a2 = repo
+        .create_directory(uid, "a2", Some(a1.sid))
+        .await
+        .unwrap();
+
+    repo.move_directory(uid, a1.sid, Some(b.sid)).await.unwrap();
+    assert!(repo
+        .get_directory(uid, a.sid)
+        .await
+        .unwrap()
+        .children
+        .is_empty());
+    let b = repo.get_directory(uid, b.sid).await.unwrap();
+    assert_eq!(b.children.len(), 1);
+    assert_eq!(b.children[0].sid, a1.sid);
+    assert_eq!(b.children[0].parent_sid, Some(b.sid));
+    assert_eq!(b.children[0].children[0].sid, a2.sid);
+    assert_eq!(b.children[0].children[0].depth, 2);
+
+    repo.move_directory(uid, a1.sid, None).await.unwrap();
+    let a1 = repo.get_directory(uid, a1.sid).await.unwrap();
+    assert_eq!((a1.parent_sid, a1.depth), (None, 0));
+    assert_eq!(a1.children[0].depth, 1);
+
+    assert!(matches!(
+        repo.move_directory(uid, a1.sid, Some(a1.sid)).await,
+        Err(DaError::DirectoryCycle(..))
+    ));
+    assert!(matches!(
+        repo.move_directory(uid, a1.sid, Some(a2.sid)).await,
+        Err(DaError::DirectoryCycle(..))
+    ));
+    // A missing parent is told apart from a missing directory
+    assert!(matches!(
+        repo.move_directory(uid, a1.sid, Some(DaDirectorySid(4242)))
+            .await,
+        Err(DaError::ParentNotFound(DaDirectorySid(4242)))
+    ));
+    assert!(matches!(
+        repo.move_directory(uid, DaDirectorySid(4242), Some(b.sid))
+            .await,
+        Err(DaError::DirectoryNotFound(DaDirectorySid(4242)))
+    ));
+    assert!(matches!(
+        repo.copy_directory(uid, a1.sid, Some(DaDirectorySid(4242)), None)
+            .await,
+        Err(DaError::ParentNotFound(DaDirectorySid(4242)))
+    ));
+}
+
+pub(crate) async fn test_directory_depth_limit<R: TestRepository>(repo: R) {
+    let repo = repo.with_config(
+        DaRepositoryConfig::default()
+            .with_depth_limit(DepthLimit::new(Some(2)).with_entity_limit(42, 3)),
+    );
+    for (uid, limit) in [(0, 2), (42, 3)] {
+        let mut dir = repo.create_directory(uid, "root", None).await.unwrap();
+        for _ in 0..limit {
+            dir = repo
+                .create_directory(uid, "child", Some(dir.sid))
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            repo.create_directory(uid, "child", Some(dir.sid)).await,
+            Err(DaError::DirectoryDepthLimitExceeded(l)) if l == limit
+        ));
+    }
+
+    // a -> a1 -> a2 can't be moved under b -> b1 (a2 would be at depth 4)
+    let uid = 0;
+    let a = repo.create_directory(uid, "a", None).await.unwrap();
+    let a1 = repo.create_directory(uid, "a1", Some(a.sid)).await.unwrap();
+    repo.create_directory(uid, "a2", Some(a1.sid))
+        .await
+        .unwrap();
+    let b = repo.create_directory(uid, "b", None).await.unwrap();
+    let b1 = repo.create_directory(uid, "b1", Some(b.sid)).await.unwrap();
+    assert!(matches!(
+        repo.move_directory(uid, a.sid, Some(b1.sid)).await,
+        Err(DaError::DirectoryDepthLimitExceeded(2))
+    ));
+    assert_eq!(
+        repo.get_directory(uid, a.sid).await.unwrap().parent_sid,
+        None
+    );
+    repo.move_directory(uid, a1.sid, Some(b.sid)).await.unwrap();
+}
+
+pub(crate) async fn test_directory_depth_limited_read<R: DaRepository>(repo: R) {
+    let uid = StaticEntity::root().uid();
+    let root = repo.create_directory(uid, "root", None).await.unwrap();
+    let a = repo
+        .create_directory(uid, "a", Some(root.sid))
+        .await
+        .unwrap();
+    repo.create_directory(uid, "b", Some(root.sid))
+        .await
+        .unwrap();
+    repo.create_directory(uid, "a1", Some(a.sid)).await.unwrap();
+
+    let shallow = repo
+        .get_directory_with_depth(uid, root.sid, Some(0))
+        .await
+        .unwrap();
+    assert!(shallow.children.is_empty());
+    assert!(shallow.has_unloaded_children);
+
+    let one_level = repo
+        .get_directory_with_depth(uid, root.sid, Some(1))
+        .await
+        .unwrap();
+    assert!(!one_level.has_unloaded_children);
+    assert_eq!(one_level.children.len(), 2);
+    for child in one_level.children {
+        assert!(child.children.is_empty());
+        assert_eq!(child.has_unloaded_children, child.sid == a.sid);
+    }
+
+    let full = repo.get_directory(uid, root.sid).await.unwrap();
+    let a = full.children.iter().find(|c| c.sid == a.sid).unwrap();
+    assert_eq!(a.children.len(), 1);
+    assert!(!a.children[0].has_unloaded_children);
+}
+
+pub(crate) async fn test_directory_list_children<R: DaRepository>(repo: R) {
+    let uid = StaticEntity::root().uid();
+    let root = repo.create_directory(uid, "root", None).await.unwrap();
+    let mut expected = vec![];
+    for idx in 0..25 {
+        let child = repo
+            .create_directory(uid, &format!("child-{}", idx), Some(root.sid))
+            .await
+            .unwrap();
+        expected.push(child.sid);
+    }
+    repo.create_directory(uid, "grand-child", Some(expected[3]))
+        .await
+        .unwrap();
+
+    let mut listed = vec![];
+    let mut cursor = None;
+    loop {
+        let page = repo.list_children(uid, root.sid, cursor, 10).await.unwrap();
+        assert!(page.items.len() <= 10);
+        listed.extend(page.items);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(
+        listed.iter().map(|dir| dir.sid).collect::<Vec<_>>(),
+        expected
+    );
+    assert!(listed[3].has_unloaded_children);
+    assert!(listed.iter().all(|dir| dir.children.is_empty()));
+
+    let empty = repo
+        .list_children(uid, expected[0], None, 10)
+        .await
+        .unwrap();
+    assert!(empty.items.is_empty() && empty.next_cursor.is_none());
+    assert!(matches!(
+        repo.list_children(uid, DaDirectorySid(4242), None, 10)
+            .await,
+        Err(DaError::DirectoryNotFound(_))
+    ));
+}
+
+pub(crate) async fn test_directory_sharing<R: DaRepository>(repo: R) {
+    let (owner, other) = (1, 2);
+    let root = repo.create_directory(owner, "root", None).await.unwrap();
+    let shared = repo
+        .create_directory(owner, "shared", Some(root.sid))
+        .await
+        .unwrap();
+    let child = repo
+        .create_directory(owner, "child", Some(shared.sid))
+        .await
+        .unwrap();
+    let denied = |res: DaResult<_>| {
+        matches!(
+            res,
+            Err(DaError::AccessDenied {
+                requested_by: 2,
+                resource_owner: 1
+            })
+        )
+    };
+
+    // Directories are isolated by default
+    assert!(denied(
+        repo.get_directory(other, shared.sid).await.map(|_| ())
+    ));
+    assert!(denied(repo.rename_directory(other, shared.sid, "x").await));
+    assert!(denied(repo.delete_directory(other, shared.sid).await));
+    assert!(denied(
+        repo.grant_access(other, shared.sid, other, DaPermission::Write)
+            .await
+            .map(|_| ())
+    ));
+
+    // A read grant is inherited by the descendants but doesn't allow writes
+    repo.grant_access(owner, shared.sid, other, DaPermission::Read)
+        .await
+        .unwrap();
+    let tree = repo.get_directory(other, shared.sid).await.unwrap();
+    assert_eq!(tree.children[0].sid, child.sid);
+    assert!(repo.get_directory(other, child.sid).await.is_ok());
+    assert!(denied(
+        repo.get_directory(other, root.sid).await.map(|_| ())
+    ));
+    assert!(denied(
+        repo.create_directory(other, "new", Some(child.sid))
+            .await
+            .map(|_| ())
+    ));
+
+    // A write grant allows to create directories that belong to the owner
+    repo.grant_access(owner, shared.sid, other, DaPermission::Write)
+        .await
+        .unwrap();
+    let created = repo
+        .create_directory(other, "new", Some(child.sid))
+        .await
+        .unwrap();
+    repo.rename_directory(other, created.sid, "renamed")
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.get_directory(owner, created.sid).await.unwrap().name,
+        "renamed"
+    );
+    assert!(denied(
+        repo.move_directory(other, created.sid, Some(root.sid))
+            .await
+    ));
+    let own = repo.create_directory(other, "own", None).await.unwrap();
+    assert!(matches!(
+        repo.move_directory(other, created.sid, Some(own.sid)).await,
+        Err(DaError::AccessDenied {
+            resource_owner: 2,
+            ..
+        })
+    ));
+    assert_eq!(
+        repo.list_grants(owner, shared.sid).await.unwrap(),
+        vec![DaGrant {
+            sid: shared.sid,
+            grantee: other,
+            permission: DaPermission::Write
+        }]
+    );
+
+    repo.revoke_access(owner, shared.sid, other).await.unwrap();
+    assert!(denied(
+        repo.get_directory(other, created.sid).await.map(|_| ())
+    ));
+    assert!(repo
+        .list_grants(owner, shared.sid)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Grants are removed with their directory
+    repo.grant_access(owner, child.sid, other, DaPermission::Read)
+        .await
+        .unwrap();
+    repo.delete_directory(owner, shared.sid).await.unwrap();
+    assert!(matches!(
+        repo.get_directory(other, child.sid).await,
+        Err(DaError::DirectoryNotFound(_))
+    ));
+}
+
+pub(crate) async fn test_directory_list_roots<R: DaRepository>(repo: R) {
+    let (uid, other) = (1, 2);
+    let mut roots = vec![];
+    for idx in 0..11 {
+        // Duplicated names are ordered by sid
+        let root = repo
+            .create_directory(uid, &format!("root-{}", idx % 4), None)
+            .await
+            .unwrap();
+        roots.push((root.name, root.sid));
+    }
+    repo.create_directory(uid, "child", Some(roots[2].1))
+        .await
+        .unwrap();
+    repo.create_directory(other, "other", None).await.unwrap();
+
+    for sort in [
+        DaSort::default(),
+        DaSort::new(DaSortKey::Name, DaSortOrder::Asc),
+        DaSort::new(DaSortKey::Name, DaSortOrder::Desc),
+    ] {
+        let mut listed = vec![];
+        let mut cursor = None;
+        loop {
+            let page = repo
+                .list_root_directories(uid, sort, cursor, 4)
+                .await
+                .unwrap();
+            assert!(page.items.len() <= 4);
+            listed.extend(page.items);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        let mut expected = roots.clone();
+        if sort.key == DaSortKey::Sid {
+            expected.sort_by_key(|(_, sid)| *sid);
+        } else {
+            expected.sort();
+        }
+        if sort.order == DaSortOrder::Desc {
+            expected.reverse();
+        }
+        assert_eq!(
+            listed
+                .iter()
+                .map(|dir| (dir.name.clone(), dir.sid))
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert!(listed.iter().all(|dir| dir.children.is_empty()));
+        assert_eq!(
+            listed
+                .iter()
+                .filter(|dir| dir.has_unloaded_children)
+                .count(),
+            1
+        );
+    }
+
+    // A cursor of a listing sorted by sid can't be used in a listing sorted by name
+    let page = repo
+        .list_root_directories(uid, DaSort::default(), None, 4)
+        .await
+        .unwrap();
+    assert!(matches!(
+        repo.list_root_directories(
+            uid,
+            DaSort::new(DaSortKey::Name, DaSortOrder::Asc),
+            page.next_cursor,
+            4
+        )
+        .await,
+        Err(DaError::InvalidCursor(_))
+    ));
+}
+
+pub(crate) async fn test_directory_timestamps<R: DaRepository>(repo: R) {
+    let (owner, other) = (1, 2);
+    let tick = || tokio::time::sleep(std::time::Duration::from_millis(5));
+    let root = repo.create_directory(owner, "root", None).await.unwrap();
+    assert_eq!(root.created_by, owner);
+    assert_eq!(root.created_at, root.updated_at);
+
+    // Creating a child updates the parent, the child is created by the requester
+    tick().await;
+    repo.grant_access(owner, root.sid, other, DaPermission::Write)
+        .await
+        .unwrap();
+    let child = repo
+        .create_directory(other, "child", Some(root.sid))
+        .await
+        .unwrap();
+    assert_eq!(child.created_by, other);
+    let after_create = repo.get_directory(owner, root.sid).await.unwrap();
+    assert_eq!(after_create.created_at, root.created_at);
+    assert!(after_create.updated_at > root.updated_at);
+    assert_eq!(after_create.children[0].created_by, other);
+
+    // Renaming updates the directory itself
+    tick().await;
+    repo.rename_directory(owner, child.sid, "renamed")
+        .await
+        .unwrap();
+    let renamed = repo.get_directory(owner, child.sid).await.unwrap();
+    assert_eq!(renamed.created_at, after_create.children[0].created_at);
+    assert!(renamed.updated_at > after_create.children[0].updated_at);
+
+    // Moving and deleting update the parents
+    tick().await;
+    let other_root = repo.create_directory(owner, "other", None).await.unwrap();
+    repo.move_directory(owner, child.sid, Some(other_root.sid))
+        .await
+        .unwrap();
+    let after_move = repo.get_directory(owner, root.sid).await.unwrap();
+    assert!(after_move.updated_at > after_create.updated_at);
+    assert!(
+        repo.get_directory(owner, other_root.sid)
+            .await
+            .unwrap()
+            .updated_at
+            > other_root.updated_at
+    );
+    tick().await;
+    repo.delete_directory(owner, child.sid).await.unwrap();
+    let after_delete = repo.get_directory(owner, other_root.sid).await.unwrap();
+    assert!(after_delete.updated_at > after_move.updated_at);
+}
+
+pub(crate) async fn test_file_storage<R: TestRepository>(repo: R) {
+    let blobs = MemBlobStore::new();
+    let repo = repo.with_blob_store(Arc::new(blobs.clone()));
+    let (owner, other) = (1, 2);
+    let content = |chunks: &[&'static str]| -> DaByteStream {
+        Box::pin(futures::stream::iter(
+            chunks
+                .iter()
+                .map(|chunk| Ok(bytes::Bytes::from_static(chunk.as_bytes())))
+                .collect::<Vec<_>>(),
+        ))
+    };
+    let root = repo.create_directory(owner, "root", None).await.unwrap();
+    let child = repo
+        .create_directory(owner, "child", Some(root.sid))
+        .await
+        .unwrap();
+
+    let file = repo
+        .create_file(
+            owner,
+            root.sid,
+            "hello.txt",
+            "text/plain",
+            content(&["hello ", "world"]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(file.directory_sid, root.sid);
+    assert_eq!(file.size, 11);
+    assert_eq!(
+        file.content_hash,
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+    );
+    assert_eq!(repo.get_file(owner, file.sid).await.unwrap(), file);
+    let (read, stream) = repo.read_file(owner, file.sid).await.unwrap();
+    assert_eq!(read, file);
+    let bytes: Vec<u8> = stream.map(|chunk| chunk.unwrap().to_vec()).concat().await;
+    assert_eq!(bytes, b"hello world");
+
+    // A failing upload stores nothing
+    let failing: DaByteStream = Box::pin(futures::stream::iter([
+        Ok(bytes::Bytes::from_static(b"partial")),
+        Err(std::io::Error::other("connection reset")),
+    ]));
+    assert!(matches!(
+        repo.create_file(owner, root.sid, "broken", "text/plain", failing)
+            .await,
+        Err(DaError::BlobStore(_))
+    ));
+
+    // A directory moved to the trash while the content is uploaded doesn't get the file
+    let doomed = repo.create_directory(owner, "doomed", None).await.unwrap();
+    let deleting = repo.clone();
+    let racing: DaByteStream = Box::pin(futures::stream::once(async move {
+        deleting.delete_directory(owner, doomed.sid).await.unwrap();
+        Ok(bytes::Bytes::from_static(b"late"))
+    }));
+    assert!(matches!(
+        repo.create_file(owner, doomed.sid, "late", "text/plain", racing)
+            .await,
+        Err(DaError::DirectoryNotFound(sid)) if sid == doomed.sid
+    ));
+
+    // Files are paginated by sid
+    let mut sids = vec![file.sid];
+    for idx in 0..4 {
+        let file = repo
+            .create_file(
+                owner,
+                root.sid,
+                &format!("file-{idx}"),
+                "text/plain",
+                content(&["x"]),
+            )
+            .await
+            .unwrap();
+        sids.push(file.sid);
+    }
+    let first = repo.list_files(owner, root.sid, None, 3).await.unwrap();
+    let second = repo
+        .list_files(owner, root.sid, first.next_cursor, 3)
+        .await
+        .unwrap();
+    assert!(second.next_cursor.is_none());
+    let listed: Vec<_> = first
+        .items
+        .iter()
+        .chain(second.items.iter())
+        .map(|file| file.sid)
+        .collect();
+    assert_eq!(listed, sids);
+
+    // Files are only accessible through the access to their directory
+    assert!(matches!(
+        repo.get_file(other, file.sid).await,
+        Err(DaError::AccessDenied { .. })
+    ));
+    repo.grant_access(owner, root.sid, other, DaPermission::Read)
+        .await
+        .unwrap();
+    repo.get_file(other, file.sid).await.unwrap();
+    assert!(matches!(
+        repo.delete_file(other, file.sid).await,
+        Err(DaError::AccessDenied { .. })
+    ));
+
+    repo.delete_file(owner, file.sid).await.unwrap();
+    assert!(matches!(
+        repo.get_file(owner, file.sid).await,
+        Err(DaError::FileNotFound(_))
+    ));
+    assert_eq!(blobs.len().await, 4);
+
+    // Deleting a directory deletes the files of its whole subtree
+    let nested = repo
+        .create_file(owner, child.sid, "nested", "text/plain", content(&["y"]))
+        .await
+        .unwrap();
+    repo.delete_directory(owner, root.sid).await.unwrap();
+    assert!(matches!(
+        repo.get_file(owner, nested.sid).await,
+        Err(DaError::FileNotFound(_))
+    ));
+    // The contents are kept until the directory is purged from the trash
+    assert_eq!(blobs.len().await, 5);
+    repo.purge_directory(owner, root.sid).await.unwrap();
+    assert!(blobs.is_empty().await);
+}
+
+pub(crate) async fn test_resolve_path<R: DaRepository>(repo: R) {
+    let (owner, other) = (1, 2);
+    let projects = repo
+        .create_directory(owner, "projects", None)
+        .await
+        .unwrap();
+    let year = repo
+        .create_directory(owner, "2026", Some(projects.sid))
+        .await
+        .unwrap();
+    let reports = repo
+        .create_directory(owner, "reports", Some(year.sid))
+        .await
+        .unwrap();
+    repo.create_directory(owner, "q1", Some(reports.sid))
+        .await
+        .unwrap();
+
+    let resolved = repo
+        .resolve_path(owner, &["projects", "2026", "reports"], Some(0))
+        .await
+        .unwrap();
+    assert_eq!(resolved.sid, reports.sid);
+    assert!(resolved.has_unloaded_children);
+    let resolved = repo.resolve_path(owner, &["projects"], None).await.unwrap();
+    assert_eq!(resolved.children[0].children[0].sid, reports.sid);
+
+    // Missing segments are reported with the path leading to them
+    assert!(matches!(
+        repo.resolve_path(owner, &["projects", "2025", "reports"], None).await,
+        Err(DaError::PathNotFound(path)) if path == "/projects/2025"
+    ));
+    assert!(matches!(
+        repo.resolve_path(owner, &[], None).await,
+        Err(DaError::PathNotFound(_))
+    ));
+    // Only the tree of the requester is walked
+    assert!(matches!(
+        repo.resolve_path(other, &["projects"], None).await,
+        Err(DaError::PathNotFound(_))
+    ));
+
+    // Siblings with the same name make the path ambiguous
+    repo.create_directory(owner, "2026", Some(projects.sid))
+        .await
+        .unwrap();
+    assert!(matches!(
+        repo.resolve_path(owner, &["projects", "2026", "reports"], None).await,
+        Err(DaError::AmbiguousPath(path)) if path == "/projects/2026"
+    ));
+}
+
+pub(crate) async fn test_sibling_names<R: TestRepository>(repo: R) {
+    let config = DaRepositoryConfig::default().with_sibling_names(SiblingNamePolicy::CaseSensitive);
+    let repo = repo.with_config(config);
+    let uid = 1;
+    let a = repo.create_directory(uid, "a", None).await.unwrap();
+    assert!(matches!(
+        repo.create_directory(uid, "a", None).await,
+        Err(DaError::NameConflict(name)) if name == "a"
+    ));
+    // Root directories of different owners are not siblings
+    repo.create_directory(2, "a", None).await.unwrap();
+    let upper = repo.create_directory(uid, "A", None).await.unwrap();
+
+    let x = repo.create_directory(uid, "x", Some(a.sid)).await.unwrap();
+    assert!(matches!(
+        repo.create_directory(uid, "x", Some(a.sid)).await,
+        Err(DaError::NameConflict(_))
+    ));
+    assert!(matches!(
+        repo.rename_directory(uid, upper.sid, "a").await,
+        Err(DaError::NameConflict(_))
+    ));
+    // A directory doesn't conflict with itself
+    repo.rename_directory(uid, x.sid, "x").await.unwrap();
+
+    let other_x = repo.create_directory(uid, "x", None).await.unwrap();
+    assert!(matches!(
+        repo.move_directory(uid, other_x.sid, Some(a.sid)).await,
+        Err(DaError::NameConflict(_))
+    ));
+    assert!(repo
+        .get_directory(uid, other_x.sid)
+        .await
+        .unwrap()
+        .parent_sid
+        .is_none());
+
+    // The case-insensitive policy also rejects names that only differ by case
+    let config =
+        DaRepositoryConfig::default().with_sibling_names(SiblingNamePolicy::CaseInsensitive);
+    let repo = repo.with_config(config);
+    repo.create_directory(uid, "Reports", Some(x.sid))
+        .await
+        .unwrap();
+    assert!(matches!(
+        repo.create_directory(uid, "REPORTS", Some(x.sid)).await,
+        Err(DaError::NameConflict(name)) if name == "Reports"
+    ));
+}
+
+pub(crate) async fn test_name_validation<R: DaRepository>(repo: R) {
+    let uid = 1;
+    let dir = repo
+        .create_directory(uid, "  cafe\u{301} ", None)
+        .await
+        .unwrap();
+    assert_eq!(dir.name, "caf\u{e9}");
+    assert_eq!(
+        repo.resolve_path(uid, &["cafe\u{301}"], Some(0))
+            .await
+            .unwrap()
+            .sid,
+        dir.sid
+    );
+
+    for invalid in ["", "a/b", "tab\there", &"a".repeat(256)] {
+        assert!(matches!(
+            repo.create_directory(uid, invalid, Some(dir.sid)).await,
+            Err(DaError::InvalidName(_))
+        ));
+    }
+    assert!(matches!(
+        repo.rename_directory(uid, dir.sid, " ").await,
+        Err(DaError::InvalidName(_))
+    ));
+    assert_eq!(
+        repo.get_directory(uid, dir.sid).await.unwrap().name,
+        "caf\u{e9}"
+    );
+}
+
+pub(crate) async fn test_copy_directory<R: TestRepository>(repo: R) {
+    let uid = 1;
+    let a = repo.create_directory(uid, "a", None).await.unwrap();
+    let a1 = repo.create_directory(uid, "a1", Some(a.sid)).await.unwrap();
+    let a2 = repo
+        .create_directory(uid, "a2", Some(a1.sid))
+        .await
+        .unwrap();
+    let a3 = repo.create_directory(uid, "a3", Some(a.sid)).await.unwrap();
+    let b = repo.create_directory(uid, "b", None).await.unwrap();
+
+    let copy = repo
+        .copy_directory(uid, a.sid, Some(b.sid), None)
+        .await
+        .unwrap();
+    assert_eq!(copy.name, "a");
+    assert_eq!(copy.parent_sid, Some(b.sid));
+    assert_eq!(copy.depth, 1);
+    assert_ne!(copy.sid, a.sid);
+    assert_eq!(copy.children.len(), 2);
+    let copy_a1 = copy.children.iter().find(|c| c.name == "a1").unwrap();
+    assert_eq!(copy_a1.depth, 2);
+    assert_eq!(copy_a1.parent_sid, Some(copy.sid));
+    assert_ne!(copy_a1.sid, a1.sid);
+    assert_eq!(copy_a1.children[0].name, "a2");
+    assert_eq!(copy_a1.children[0].depth, 3);
+    assert_ne!(copy_a1.children[0].sid, a2.sid);
+    // The source is left untouched
+    let source = repo.get_directory(uid, a.sid).await.unwrap();
+    assert_eq!(source.parent_sid, None);
+    assert_eq!(source.children.len(), 2);
+
+    // A directory can be copied into one of its descendants, as a root and under a new name
+    let nested = repo
+        .copy_directory(uid, a.sid, Some(a2.sid), None)
+        .await
+        .unwrap();
+    assert_eq!(nested.depth, 3);
+    assert_eq!(nested.children.len(), 2);
+    let root = repo
+        .copy_directory(uid, a1.sid, None, Some(" root copy "))
+        .await
+        .unwrap();
+    assert_eq!(root.name, "root copy");
+    assert_eq!(root.depth, 0);
+    assert_eq!(root.children[0].depth, 1);
+
+    // The copy is subject to the depth limit of the target
+    let repo = repo.with_config(
+        DaRepositoryConfig::default()
+            .with_depth_limit(DepthLimit::new(Some(3)))
+            .with_sibling_names(SiblingNamePolicy::CaseSensitive),
+    );
+    assert!(matches!(
+        repo.copy_directory(uid, a1.sid, Some(a2.sid), None).await,
+        Err(DaError::DirectoryDepthLimitExceeded(3))
+    ));
+    assert!(matches!(
+        repo.copy_directory(uid, a3.sid, Some(a.sid), None).await,
+        Err(DaError::NameConflict(name)) if name == "a3"
+    ));
+
+    // A shared directory can be copied by a grantee into its own tree
+    let other = 2;
+    assert!(matches!(
+        repo.copy_directory(other, a.sid, None, None).await,
+        Err(DaError::AccessDenied { .. })
+    ));
+    repo.grant_access(uid, a.sid, other, DaPermission::Read)
+        .await
+        .unwrap();
+    let shared = repo
+        .copy_directory(other, a3.sid, None, None)
+        .await
+        .unwrap();
+    assert_eq!(shared.created_by, other);
+    assert!(repo
+        .list_root_directories(other, DaSort::default(), None, 10)
+        .await
+        .unwrap()
+        .items
+        .iter()
+        .any(|d| d.sid == shared.sid));
+    assert!(matches!(
+        repo.copy_directory(other, a3.sid, Some(a.sid), Some("other"))
+            .await,
+        Err(DaError::AccessDenied { .. })
+    ));
+}
+
+pub(crate) async fn test_trash<R: TestRepository>(repo: R) {
+    let (owner, other) = (1, 2);
+    let root = repo.create_directory(owner, "root", None).await.unwrap();
+    let a = repo
+        .create_directory(owner, "a", Some(root.sid))
+        .await
+        .unwrap();
+    let a1 = repo
+        .create_directory(owner, "a1", Some(a.sid))
+        .await
+        .unwrap();
+    let b = repo
+        .create_directory(owner, "b", Some(root.sid))
+        .await
+        .unwrap();
+
+    // A directory deleted by a grantee goes to the trash of its owner, with its descendants
+    repo.grant_access(owner, root.sid, other, DaPermission::Write)
+        .await
+        .unwrap();
+    repo.delete_directory(other, a.sid).await.unwrap();
+    assert!(matches!(
+        repo.get_directory(owner, a1.sid).await,
+        Err(DaError::DirectoryNotFound(_))
+    ));
+    assert_eq!(
+        repo.get_directory(owner, root.sid)
+            .await
+            .unwrap()
+            .children
+            .len(),
+        1
+    );
+    let trash = repo.list_trash(owner, None, 10).await.unwrap();
+    assert_eq!(trash.items.len(), 1);
+    assert_eq!(trash.items[0].sid, a.sid);
+    assert_eq!(trash.items[0].name, "a");
+    assert_eq!(trash.items[0].parent_sid, Some(root.sid));
+    assert_eq!(trash.items[0].deleted_by, other);
+    assert!(repo
+        .list_trash(other, None, 10)
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+    assert!(matches!(
+        repo.restore_directory(other, a.sid).await,
+        Err(DaError::TrashEntryNotFound(_))
+    ));
+
+    // Restored under its original parent
+    let restored = repo.restore_directory(owner, a.sid).await.unwrap();
+    assert_eq!(restored.parent_sid, Some(root.sid));
+    assert_eq!(restored.children[0].sid, a1.sid);
+    assert_eq!(restored.children[0].depth, 2);
+    assert!(repo
+        .list_trash(owner, None, 10)
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+
+    // Restored as a root directory once its original parent is gone
+    repo.delete_directory(owner, a.sid).await.unwrap();
+    repo.delete_directory(owner, root.sid).await.unwrap();
+    let page = repo.list_trash(owner, None, 1).await.unwrap();
+    assert_eq!(page.items[0].sid, root.sid);
+    let page = repo.list_trash(owner, page.next_cursor, 1).await.unwrap();
+    assert_eq!(page.items[0].sid, a.sid);
+    assert!(page.next_cursor.is_none());
+    let restored = repo.restore_directory(owner, a.sid).await.unwrap();
+    assert_eq!(restored.parent_sid, None);
+    assert_eq!(restored.depth, 0);
+    assert_eq!(restored.children[0].depth, 1);
+
+    repo.purge_directory(owner, root.sid).await.unwrap();
+    assert!(matches!(
+        repo.restore_directory(owner, root.sid).await,
+        Err(DaError::TrashEntryNotFound(_))
+    ));
+    assert!(matches!(
+        repo.get_directory(owner, b.sid).await,
+        Err(DaError::DirectoryNotFound(_))
+    ));
+
+    // A restored directory must not conflict with its new siblings
+    let repo = repo.with_config(
+        DaRepositoryConfig::default().with_sibling_names(SiblingNamePolicy::CaseSensitive),
+    );
+    repo.delete_directory(owner, a.sid).await.unwrap();
+    repo.create_directory(owner, "a", None).await.unwrap();
+    assert!(matches!(
+        repo.restore_directory(owner, a.sid).await,
+        Err(DaError::NameConflict(_))
+    ));
+
+    // Entries are purged once they are older than the retention
+    assert_eq!(repo.purge_expired_trash().await.unwrap(), 0);
+    let repo = repo.with_config(
+        DaRepositoryConfig::default().with_trash_retention(std::time::Duration::from_secs(3600)),
+    );
+    assert_eq!(repo.purge_expired_trash().await.unwrap(), 0);
+    let repo = repo
+        .with_config(DaRepositoryConfig::default().with_trash_retention(std::time::Duration::ZERO));
+    assert_eq!(repo.purge_expired_trash().await.unwrap(), 1);
+    assert!(repo
+        .list_trash(owner, None, 10)
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+}
+
+pub(crate) async fn test_audit_log<R: DaRepository>(repo: R) {
+    let (owner, other) = (1, 2);
+    let root = repo.create_directory(owner, "root", None).await.unwrap();
+    let a = repo
+        .create_directory(owner, "a", Some(root.sid))
+        .await
+        .unwrap();
+    repo.rename_directory(owner, a.sid, "renamed")
+        .await
+        .unwrap();
+    repo.grant_access(owner, root.sid, other, DaPermission::Write)
+        .await
+        .unwrap();
+    // The operations of a grantee are recorded in the log of the owner
+    let b = repo
+        .create_directory(other, "b", Some(root.sid))
+        .await
+        .unwrap();
+    repo.move_directory(other, b.sid, Some(a.sid))
+        .await
+        .unwrap();
+    let file = repo
+        .create_file(
+            other,
+            b.sid,
+            "hello.txt",
+            "text/plain",
+            Box::pin(futures::stream::iter(vec![Ok(bytes::Bytes::from_static(
+                b"hello",
+            ))])),
+        )
+        .await
+        .unwrap();
+    repo.delete_directory(owner, a.sid).await.unwrap();
+    repo.purge_directory(owner, a.sid).await.unwrap();
+
+    let events = repo
+        .list_audit_events(owner, DaAuditFilter::default(), None, 100)
+        .await
+        .unwrap()
+        .items;
+    assert_eq!(
+        events
+            .iter()
+            .map(|event| (event.entity, event.operation, event.sid))
+            .collect::<Vec<_>>(),
+        vec![
+            (Some(owner), DaAuditOperation::CreateDirectory, root.sid),
+            (Some(owner), DaAuditOperation::CreateDirectory, a.sid),
+            (Some(owner), DaAuditOperation::RenameDirectory, a.sid),
+            (Some(owner), DaAuditOperation::GrantAccess, root.sid),
+            (Some(other), DaAuditOperation::CreateDirectory, b.sid),
+            (Some(other), DaAuditOperation::MoveDirectory, b.sid),
+            (Some(other), DaAuditOperation::CreateFile, b.sid),
+            (Some(owner), DaAuditOperation::DeleteDirectory, a.sid),
+            (Some(owner), DaAuditOperation::PurgeDirectory, a.sid),
+        ]
+    );
+    assert!(events.windows(2).all(|pair| pair[0].id < pair[1].id));
+    assert_eq!(
+        events[2].old_value,
+        Some(serde_json::json!({ "name": "a" }))
+    );
+    assert_eq!(
+        events[2].new_value,
+        Some(serde_json::json!({ "name": "renamed" }))
+    );
+    assert_eq!(
+        events[3].old_value,
+        Some(serde_json::json!({ "grantee": other, "permission": null }))
+    );
+    assert_eq!(
+        events[3].new_value,
+        Some(serde_json::json!({ "grantee": other, "permission": "write" }))
+    );
+    assert_eq!(
+        events[5].old_value,
+        Some(serde_json::json!({ "parent_sid": root.sid }))
+    );
+    assert_eq!(
+        events[5].new_value,
+        Some(serde_json::json!({ "parent_sid": a.sid }))
+    );
+    assert_eq!(events[6].file_sid, Some(file.sid));
+    // The grantee owns no directory
+    assert!(repo
+        .list_audit_events(other, DaAuditFilter::default(), None, 100)
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+
+    // Filtered by sid, one page at a time
+    let filter = DaAuditFilter {
+        sid: Some(a.sid),
+        ..Default::default()
+    };
+    let page = repo
+        .list_audit_events(owner, filter, None, 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        page.items
+            .iter()
+            .map(|event| event.operation)
+            .collect::<Vec<_>>(),
+        vec![
+            DaAuditOperation::CreateDirectory,
+            DaAuditOperation::RenameDirectory
+        ]
+    );
+    let page = repo
+        .list_audit_events(owner, filter, page.next_cursor, 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        page.items
+            .iter()
+            .map(|event| event.operation)
+            .collect::<Vec<_>>(),
+        vec![
+            DaAuditOperation::DeleteDirectory,
+            DaAuditOperation::PurgeDirectory
+        ]
+    );
+    assert!(page.next_cursor.is_none());
+
+    // Filtered by time range, `since` is inclusive and `until` exclusive
+    let first = events[0].at;
+    let filter = DaAuditFilter {
+        since: Some(first),
+        ..Default::default()
+    };
+    assert_eq!(
+        repo.list_audit_events(owner, filter, None, 100)
+            .await
+            .unwrap()
+            .items
+            .len(),
+        events.len()
+    );
+    let filter = DaAuditFilter {
+        until: Some(first),
+        ..Default::default()
+    };
+    assert!(repo
+        .list_audit_events(owner, filter, None, 100)
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+}
+
+pub(crate) async fn test_change_events<R: DaRepository>(repo: R) {
+    let mut events = repo.subscribe();
+    let owner = 1;
+    let root = repo.create_directory(owner, "root", None).await.unwrap();
+    let a = repo
+        .create_directory(owner, "a", Some(root.sid))
+        .await
+        .unwrap();
+    repo.rename_directory(owner, a.sid, "renamed")
+        .await
+        .unwrap();
+    repo.move_directory(owner, a.sid, None).await.unwrap();
+    repo.delete_directory(owner, a.sid).await.unwrap();
+    // Failed operations publish nothing
+    assert!(repo.rename_directory(owner, a.sid, "x").await.is_err());
+
+    let mut changes = vec![];
+    while let Ok(event) = events.try_recv() {
+        assert_eq!(event.owner, owner);
+        assert_eq!(event.changed_by, owner);
+        changes.push((event.sid, event.change));
+    }
+    assert_eq!(
+        changes,
+        vec![
+            (
+                root.sid,
+                DaChange::Created {
+                    name: "root".to_string(),
+                    parent_sid: None
+                }
+            ),
+            (
+                a.sid,
+                DaChange::Created {
+                    name: "a".to_string(),
+                    parent_sid: Some(root.sid)
+                }
+            ),
+            (
+                a.sid,
+                DaChange::Renamed {
+                    name: "renamed".to_string()
+                }
+            ),
+            (
+                a.sid,
+                DaChange::Moved {
+                    old_parent_sid: Some(root.sid),
+                    parent_sid: None
+                }
+            ),
+            (a.sid, DaChange::Deleted { parent_sid: None }),
+        ]
+    );
+
+    // The grantees of a subtree see its changes but not those of the directories above it, whose names are hidden from them.
+    // They only see the sid of a deleted ancestor
+    let (grantee, other) = (2, 3);
+    let shared = repo
+        .create_directory(owner, "shared", Some(root.sid))
+        .await
+        .unwrap();
+    let nested = repo
+        .create_directory(owner, "nested", Some(shared.sid))
+        .await
+        .unwrap();
+    repo.grant_access(owner, nested.sid, grantee, DaPermission::Read)
+        .await
+        .unwrap();
+    let mut events = repo.subscribe();
+    repo.rename_directory(owner, root.sid, "renamed")
+        .await
+        .unwrap();
+    repo.move_directory(owner, shared.sid, None).await.unwrap();
+    repo.rename_directory(owner, nested.sid, "renamed")
+        .await
+        .unwrap();
+    repo.delete_directory(owner, shared.sid).await.unwrap();
+    repo.create_directory(owner, "unrelated", Some(root.sid))
+        .await
+        .unwrap();
+    let mut visible = vec![];
+    let mut deleted = vec![];
+    while let Ok(event) = events.try_recv() {
+        assert!(!event.is_visible_to(other));
+        assert_eq!(event.deleted_sid_for(other), None);
+        visible.push(event.is_visible_to(grantee));
+        deleted.extend(event.deleted_sid_for(grantee).map(|deleted| deleted.sid));
+    }
+    assert_eq!(visible, vec![false, false, true, false, false]);
+    assert_eq!(deleted, vec![shared.sid]);
+}
+
+pub(crate) async fn test_directory_versions<R: DaRepository>(repo: R) {
+    let owner = 1;
+    let root = repo.create_directory(owner, "root", None).await.unwrap();
+    assert_eq!(root.version, 1);
+    repo.rename_directory(owner, root.sid, "renamed")
+        .await
+        .unwrap();
+    // Adding a child updates the parent
+    let child = repo
+        .create_directory(owner, "child", Some(root.sid))
+        .await
+        .unwrap();
+    let root = repo.get_directory(owner, root.sid).await.unwrap();
+    assert_eq!(root.version, 3);
+
+    // A stale version is rejected without applying the change
+    assert!(matches!(
+        repo.rename_directory_if_version(owner, root.sid, "stale", Some(2))
+            .await,
+        Err(DaError::VersionMismatch {
+            expected: 2,
+            actual: 3,
+            ..
+        })
+    ));
+    repo.rename_directory_if_version(owner, root.sid, "fresh", Some(3))
+        .await
+        .unwrap();
+    let root = repo.get_directory(owner, root.sid).await.unwrap();
+    assert_eq!(root.name, "fresh");
+    assert_eq!(root.version, 4);
+
+    assert!(matches!(
+        repo.delete_directory_if_version(owner, child.sid, Some(2))
+            .await,
+        Err(DaError::VersionMismatch { .. })
+    ));
+    repo.delete_directory_if_version(owner, child.sid, Some(child.version))
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.get_directory(owner, root.sid).await.unwrap().version,
+        5
+    );
+}
+
+pub(crate) async fn test_batch<R: DaRepository>(repo: R) {
+    let (owner, grantee) = (1, 2);
+    let root = repo.create_directory(owner, "root", None).await.unwrap();
+    let old = repo
+        .create_directory(owner, "old", Some(root.sid))
+        .await
+        .unwrap();
+    let mut events = repo.subscribe();
+
+    // The operations reference the directories created earlier in the batch by their temporary id
+    let batch: Vec<DaBatchOperation> = serde_json::from_value(serde_json::json!([
+        { "op": "create", "temp_id": "a", "name": "a", "parent": root.sid },
+        { "op": "create", "temp_id": "b", "name": "b", "parent": "a" },
+        { "op": "rename", "sid": "b", "name": "renamed" },
+        { "op": "move", "sid": old.sid, "parent": "b" },
+        { "op": "create", "temp_id": "tmp", "name": "tmp", "parent": null },
+        { "op": "delete", "sid": "tmp" },
+    ]))
+    .unwrap();
+    let result = repo.apply_batch(owner, &batch).await.unwrap();
+    assert_eq!(result.created.len(), 3);
+    let tree = repo.get_directory(owner, root.sid).await.unwrap();
+    assert_eq!(tree.children.len(), 1);
+    let a = &tree.children[0];
+    assert_eq!(a.sid, result.created["a"]);
+    assert_eq!(a.children[0].sid, result.created["b"]);
+    assert_eq!(a.children[0].name, "renamed");
+    assert_eq!(a.children[0].children[0].sid, old.sid);
+    assert_eq!(a.children[0].children[0].depth, 3);
+    assert!(repo
+        .get_directory(owner, result.created["tmp"])
+        .await
+        .is_err());
+    let mut changes = 0;
+    while events.try_recv().is_ok() {
+        changes += 1;
+    }
+    assert_eq!(changes, batch.len());
+
+    // A failing operation undoes the previous ones
+    let tree = |repo: R| async move {
+        serde_json::to_value(repo.get_directory(owner, root.sid).await.unwrap()).unwrap()
+    };
+    let before = tree(repo.clone()).await;
+    let batch: Vec<DaBatchOperation> = serde_json::from_value(serde_json::json!([
+        { "op": "create", "temp_id": "c", "name": "c", "parent": root.sid },
+        { "op": "rename", "sid": old.sid, "name": "old-renamed" },
+        { "op": "move", "sid": old.sid, "parent": "c" },
+        { "op": "delete", "sid": result.created["a"] },
+        { "op": "move", "sid": root.sid, "parent": "c" },
+    ]))
+    .unwrap();
+    assert!(matches!(
+        repo.apply_batch(owner, &batch).await,
+        Err(DaError::BatchOperationFailed { index: 4, source })
+            if matches!(*source, DaError::DirectoryCycle(..))
+    ));
+    assert_eq!(tree(repo.clone()).await, before);
+    assert_eq!(
+        repo.get_directory(owner, old.sid).await.unwrap().name,
+        "old"
+    );
+    assert!(events.try_recv().is_err());
+
+    // Unknown and duplicate temporary ids
+    let batch: Vec<DaBatchOperation> = serde_json::from_value(serde_json::json!([
+        { "op": "create", "temp_id": "d", "name": "d", "parent": null },
+        { "op": "create", "temp_id": "d", "name": "e", "parent": null },
+    ]))
+    .unwrap();
+    assert!(matches!(
+        repo.apply_batch(owner, &batch).await,
+        Err(DaError::BatchOperationFailed { index: 1, source })
+            if matches!(*source, DaError::InvalidBatch(_))
+    ));
+    let batch: Vec<DaBatchOperation> =
+        serde_json::from_value(serde_json::json!([{ "op": "delete", "sid": "x" }])).unwrap();
+    assert!(matches!(
+        repo.apply_batch(owner, &batch).await,
+        Err(DaError::BatchOperationFailed { index: 0, source })
+            if matches!(*source, DaError::InvalidBatch(_))
+    ));
+
+    // Every operation is subject to the access control, a batch can't span the trees of several owners
+    let batch: Vec<DaBatchOperation> = serde_json::from_value(serde_json::json!([
+        { "op": "create", "temp_id": "g", "name": "g", "parent": root.sid },
+        { "op": "create", "temp_id": "h", "name": "h", "parent": null },
+    ]))
+    .unwrap();
+    assert!(matches!(
+        repo.apply_batch(grantee, &batch).await,
+        Err(DaError::BatchOperationFailed { index: 0, source })
+            if matches!(*source, DaError::AccessDenied { .. })
+    ));
+    repo.grant_access(owner, root.sid, grantee, DaPermission::Write)
+        .await
+        .unwrap();
+    assert!(matches!(
+        repo.apply_batch(grantee, &batch).await,
+        Err(DaError::BatchOperationFailed { index: 1, source })
+            if matches!(*source, DaError::InvalidBatch(_))
+    ));
+    let result = repo.apply_batch(grantee, &batch[..1]).await.unwrap();
+    let g = repo
+        .get_directory(owner, result.created["g"])
+        .await
+        .unwrap();
+    assert_eq!(g.created_by, grantee);
+}
+
+pub(crate) async fn test_search_directories<R: DaRepository>(repo: R) {
+    let (owner, other) = (1, 2);
+    let projects = repo
+        .create_directory(owner, "Projects", None)
+        .await
+        .unwrap();
+    let reports = repo
+        .create_directory(owner, "reports", Some(projects.sid))
+        .await
+        .unwrap();
+    let sales = repo
+        .create_directory(owner, "sales_report", Some(reports.sid))
+        .await
+        .unwrap();
+    let archive = repo.create_directory(owner, "archive", None).await.unwrap();
+    let old_reports = repo
+        .create_directory(owner, "Report 2020", Some(archive.sid))
+        .await
+        .unwrap();
+    repo.create_directory(other, "reports", None).await.unwrap();
+
+    let search = |text: &str, mode| DaSearchQuery::new(text, mode);
+    let sids = |page: &DaPage<DaSearchResult>| {
+        page.items
+            .iter()
+            .map(|result| result.sid)
+            .collect::<Vec<_>>()
+    };
+    let page = repo
+        .search_directories(owner, search("REPORT", DaSearchMode::Prefix), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(sids(&page), vec![reports.sid, old_reports.sid]);
+    assert_eq!(page.items[0].path, vec!["Projects", "reports"]);
+    assert_eq!(page.items[0].depth, 1);
+    assert_eq!(page.items[0].parent_sid, Some(projects.sid));
+    let page = repo
+        .search_directories(owner, search("report", DaSearchMode::Substring), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(sids(&page), vec![reports.sid, sales.sid, old_reports.sid]);
+    assert_eq!(
+        page.items[1].path,
+        vec!["Projects", "reports", "sales_report"]
+    );
+    // Fuzzy searches tolerate typos and rank the results by similarity
+    let page = repo
+        .search_directories(owner, search("reprots", DaSearchMode::Fuzzy), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(sids(&page), vec![reports.sid]);
+    assert_eq!(page.items[0].path, vec!["Projects", "reports"]);
+    assert_eq!(page.items[0].score, Some(4.0 / 12.0));
+    let page = repo
+        .search_directories(owner, search("Projetcs", DaSearchMode::Fuzzy), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(sids(&page), vec![projects.sid]);
+    let first = repo
+        .search_directories(owner, search("report", DaSearchMode::Fuzzy), None, 2)
+        .await
+        .unwrap();
+    assert_eq!(sids(&first), vec![reports.sid, old_reports.sid]);
+    let second = repo
+        .search_directories(
+            owner,
+            search("report", DaSearchMode::Fuzzy),
+            first.next_cursor.clone(),
+            2,
+        )
+        .await
+        .unwrap();
+    assert_eq!(sids(&second), vec![sales.sid]);
+    assert!(second.next_cursor.is_none());
+    // The cursor of a fuzzy search carries the score of the last result
+    assert!(matches!(
+        repo.search_directories(
+            owner,
+            search("report", DaSearchMode::Fuzzy),
+            Some(DaCursor::after(reports.sid)),
+            2,
+        )
+        .await,
+        Err(DaError::InvalidCursor(_))
+    ));
+    // `_` is matched literally
+    let page = repo
+        .search_directories(owner, search("s_r", DaSearchMode::Substring), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(sids(&page), vec![sales.sid]);
+    // Non-ASCII names are matched ignoring case too
+    let summer = repo
+        .create_directory(owner, "Été 2024", Some(archive.sid))
+        .await
+        .unwrap();
+    for text in ["été", "ÉTÉ 2"] {
+        let page = repo
+            .search_directories(owner, search(text, DaSearchMode::Prefix), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(sids(&page), vec![summer.sid]);
+    }
+    repo.rename_directory(owner, summer.sid, "Œuvres")
+        .await
+        .unwrap();
+    let page = repo
+        .search_directories(owner, search("œuv", DaSearchMode::Prefix), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(sids(&page), vec![summer.sid]);
+    let copy = repo
+        .copy_directory(owner, archive.sid, None, Some("Copie"))
+        .await
+        .unwrap();
+    let page = repo
+        .search_directories(owner, search("ŒUVRES", DaSearchMode::Substring), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(page.items.len(), 2);
+    assert_eq!(page.items[1].path, vec!["Copie", "Œuvres"]);
+    repo.delete_directory(owner, copy.sid).await.unwrap();
+    repo.delete_directory(owner, summer.sid).await.unwrap();
+
+    // Scoped searches only match the descendants of the scope
+    let page = repo
+        .search_directories(
+            owner,
+            search("report", DaSearchMode::Substring).with_scope(reports.sid),
+            None,
+            10,
+        )
+        .await
+        .unwrap();
+    assert_eq!(sids(&page), vec![sales.sid]);
+    assert!(matches!(
+        repo.search_directories(
+            other,
+            search("report", DaSearchMode::Substring).with_scope(reports.sid),
+            None,
+            10,
+        )
+        .await,
+        Err(DaError::AccessDenied { .. })
+    ));
+
+    // Pages follow each other
+    let first = repo
+        .search_directories(owner, search("r", DaSearchMode::Substring), None, 2)
+        .await
+        .unwrap();
+    assert_eq!(sids(&first), vec![projects.sid, reports.sid]);
+    let second = repo
+        .search_directories(
+            owner,
+            search("r", DaSearchMode::Substring),
+            first.next_cursor,
+            2,
+        )
+        .await
+        .unwrap();
+    assert_eq!(sids(&second), vec![sales.sid, archive.sid]);
+
+    // Directories in the trash are not searched
+    repo.delete_directory(owner, archive.sid).await.unwrap();
+    let page = repo
+        .search_directories(owner, search("report", DaSearchMode::Prefix), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(sids(&page), vec![reports.sid]);
+    assert!(page.next_cursor.is_none());
+}
+
+pub(crate) async fn test_list_ancestors<R: DaRepository>(repo: R) {
+    let (owner, grantee, other) = (1, 2, 3);
+    let projects = repo
+        .create_directory(owner, "projects", None)
+        .await
+        .unwrap();
+    let year = repo
+        .create_directory(owner, "2026", Some(projects.sid))
+        .await
+        .unwrap();
+    let reports = repo
+        .create_directory(owner, "reports", Some(year.sid))
+        .await
+        .unwrap();
+    let q1 = repo
+        .create_directory(owner, "q1", Some(reports.sid))
+        .await
+        .unwrap();
+    let ancestor = |dir: &DaDirectory| DaAncestor {
+        sid: dir.sid,
+        name: dir.name.clone(),
+    };
+
+    assert_eq!(
+        repo.list_ancestors(owner, q1.sid).await.unwrap(),
+        vec![ancestor(&projects), ancestor(&year), ancestor(&reports)]
+    );
+    assert!(repo
+        .list_ancestors(owner, projects.sid)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // A grantee only gets the ancestors it can read
+    repo.grant_access(owner, year.sid, grantee, DaPermission::Read)
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.list_ancestors(grantee, q1.sid).await.unwrap(),
+        vec![ancestor(&year), ancestor(&reports)]
+    );
+    assert!(repo
+        .list_ancestors(grantee, year.sid)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        repo.list_ancestors(other, q1.sid).await,
+        Err(DaError::AccessDenied { .. })
+    ));
+
+    // The chain follows moves
+    repo.move_directory(owner, q1.sid, Some(projects.sid))
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.list_ancestors(owner, q1.sid).await.unwrap(),
+        vec![ancestor(&projects)]
+    );
+    repo.delete_directory(owner, q1.sid).await.unwrap();
+    assert!(matches!(
+        repo.list_ancestors(owner, q1.sid).await,
+        Err(DaError::DirectoryNotFound(_))
+    ));
+}
+
+pub(crate) async fn test_tags<R: DaRepository>(repo: R) {
+    let (owner, grantee, other) = (1, 2, 3);
+    let projects = repo
+        .create_directory(owner, "projects", None)
+        .await
+        .unwrap();
+    let reports = repo
+        .create_directory(owner, "reports", Some(projects.sid))
+        .await
+        .unwrap();
+    let drafts = repo
+        .create_directory(owner, "drafts", Some(projects.sid))
+        .await
+        .unwrap();
+    let sids = |page: DaPage<DaDirectory>| page.items.iter().map(|dir| dir.sid).collect::<Vec<_>>();
+    let counts = |counts: Vec<DaTagCount>| {
+        counts
+            .into_iter()
+            .map(|count| (count.tag, count.count))
+            .collect::<Vec<_>>()
+    };
+
+    // Tags are normalised, kept in alphabetical order and added once
+    assert_eq!(
+        repo.add_tag(owner, reports.sid, " urgent ").await.unwrap(),
+        vec!["urgent"]
+    );
+    assert_eq!(
+        repo.add_tag(owner, reports.sid, "finance").await.unwrap(),
+        vec!["finance", "urgent"]
+    );
+    assert_eq!(
+        repo.add_tag(owner, reports.sid, "urgent").await.unwrap(),
+        vec!["finance", "urgent"]
+    );
+    repo.add_tag(owner, drafts.sid, "urgent").await.unwrap();
+    assert!(matches!(
+        repo.add_tag(owner, drafts.sid, "a,b").await,
+        Err(DaError::InvalidTag(_))
+    ));
+    assert_eq!(
+        repo.get_directory(owner, projects.sid)
+            .await
+            .unwrap()
+            .children[0]
+            .tags,
+        vec!["finance", "urgent"]
+    );
+    assert_eq!(
+        counts(repo.list_tags(owner).await.unwrap()),
+        vec![("finance".to_string(), 1), ("urgent".to_string(), 2)]
+    );
+
+    // All the tags or any of them, one page at a time
+    let query = |tags: &[&str], mode| DaTagQuery::new(tags.iter().copied(), mode);
+    assert_eq!(
+        sids(
+            repo.list_tagged_directories(
+                owner,
+                query(&["urgent", "finance"], DaTagMatch::All),
+                None,
+                10
+            )
+            .await
+            .unwrap()
+        ),
+        vec![reports.sid]
+    );
+    let page = repo
+        .list_tagged_directories(
+            owner,
+            query(&["finance", "urgent"], DaTagMatch::Any),
+            None,
+            1,
+        )
+        .await
+        .unwrap();
+    assert_eq!(page.items[0].tags, vec!["finance", "urgent"]);
+    assert_eq!(sids(page.clone()), vec![reports.sid]);
+    assert_eq!(
+        sids(
+            repo.list_tagged_directories(
+                owner,
+                query(&["finance", "urgent"], DaTagMatch::Any),
+                page.next_cursor,
+                1
+            )
+            .await
+            .unwrap()
+        ),
+        vec![drafts.sid]
+    );
+    assert!(repo
+        .list_tagged_directories(owner, query(&[], DaTagMatch::Any), None, 10)
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+
+    // Only the entities allowed to write to a directory can tag it
+    assert!(matches!(
+        repo.add_tag(grantee, reports.sid, "shared").await,
+        Err(DaError::AccessDenied { .. })
+    ));
+    repo.grant_access(owner, projects.sid, grantee, DaPermission::Write)
+        .await
+        .unwrap();
+    repo.add_tag(grantee, reports.sid, "shared").await.unwrap();
+    assert!(repo.list_tags(grantee).await.unwrap().is_empty());
+    assert!(matches!(
+        repo.remove_tag(other, reports.sid, "shared").await,
+        Err(DaError::AccessDenied { .. })
+    ));
+    assert_eq!(
+        repo.remove_tag(owner, reports.sid, "shared").await.unwrap(),
+        vec!["finance", "urgent"]
+    );
+
+    // A copy carries the tags of its originals
+    let copy = repo
+        .copy_directory(owner, projects.sid, Some(projects.sid), Some("copy"))
+        .await
+        .unwrap();
+    let copy_reports = copy.children.iter().find(|c| c.name == "reports").unwrap();
+    let copy_drafts = copy.children.iter().find(|c| c.name == "drafts").unwrap();
+    assert_eq!(copy_reports.tags, vec!["finance", "urgent"]);
+    assert_eq!(copy_drafts.tags, vec!["urgent"]);
+    assert_eq!(
+        counts(repo.list_tags(owner).await.unwrap()),
+        vec![("finance".to_string(), 2), ("urgent".to_string(), 4)]
+    );
+
+    // Trashed directories keep their tags until they are purged
+    repo.delete_directory(owner, copy.sid).await.unwrap();
+    repo.delete_directory(owner, drafts.sid).await.unwrap();
+    assert_eq!(
+        counts(repo.list_tags(owner).await.unwrap()),
+        vec![("finance".to_string(), 1), ("urgent".to_string(), 1)]
+    );
+    assert_eq!(
+        sids(
+            repo.list_tagged_directories(owner, query(&["urgent"], DaTagMatch::All), None, 10)
+                .await
+                .unwrap()
+        ),
+        vec![reports.sid]
+    );
+    let restored = repo.restore_directory(owner, drafts.sid).await.unwrap();
+    assert_eq!(restored.tags, vec!["urgent"]);
+    repo.purge_directory(owner, copy.sid).await.unwrap();
+    assert_eq!(
+        counts(repo.list_tags(owner).await.unwrap()),
+        vec![("finance".to_string(), 1), ("urgent".to_string(), 2)]
+    );
+}
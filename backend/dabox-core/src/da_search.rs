@@ -0,0 +1,202 @@
+/// This module contains the search of directories by name, see `DaRepository::search_directories`.
+/// Names are matched ignoring case, the SQL backends translate the search into a `LIKE` pattern matched against the folded
+/// names they store (see `fold_name`).
+/// Fuzzy searches rank the names by trigram similarity, computed the way Postgres' `pg_trgm` extension does.
+use crate::prelude::*;
+use std::cmp::Ordering;
+
+/// The minimum similarity of a name to the text of a fuzzy search, the default threshold of `pg_trgm`'s `%` operator.
+pub const FUZZY_THRESHOLD: f32 = 0.3;
+
+/// How the searched text is matched against the names of the directories.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DaSearchMode {
+    /// The name starts with the text
+    Prefix,
+    /// The name contains the text
+    #[default]
+    Substring,
+    /// The name is similar to the text, typos are tolerated (`reprots` matches `reports`).
+    /// The results are ordered by decreasing similarity instead of sid.
+    Fuzzy,
+}
+
+/// The criteria of a search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaSearchQuery {
+    /// The searched text
+    pub text: String,
+    pub mode: DaSearchMode,
+    /// Only the descendants of this directory (the whole tree if none)
+    pub scope: Option<DaDirectorySid>,
+}
+
+/// A directory matching a search.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaSearchResult {
+    pub sid: DaDirectorySid,
+    pub name: String,
+    pub parent_sid: Option<DaDirectorySid>,
+    /// The depth of the directory in the hierarchy
+    pub depth: u32,
+    /// The names of the directories from the root down to the directory itself, as resolved by `DaRepository::resolve_path`
+    pub path: Vec<String>,
+    /// How similar the name is to the searched text, between 0 and 1, only set for fuzzy searches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+}
+
+impl DaSearchQuery {
+    pub fn new(text: impl Into<String>, mode: DaSearchMode) -> Self {
+        Self {
+            text: text.into(),
+            mode,
+            scope: None,
+        }
+    }
+
+    pub fn with_scope(mut self, scope: DaDirectorySid) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Whether a name matches the search.
+    pub fn matches(&self, name: &str) -> bool {
+        let name = fold_name(name);
+        let text = fold_name(&self.text);
+        match self.mode {
+            DaSearchMode::Prefix => name.starts_with(&text),
+            DaSearchMode::Substring => name.contains(&text),
+            DaSearchMode::Fuzzy => similarity(&name, &text) >= FUZZY_THRESHOLD,
+        }
+    }
+
+    /// The score of a matching name, the similarity of the name to the text for fuzzy searches (none otherwise).
+    pub fn score(&self, name: &str) -> Option<f32> {
+        (self.mode == DaSearchMode::Fuzzy)
+            .then(|| similarity(&fold_name(name), &fold_name(&self.text)))
+    }
+
+    /// The `LIKE` pattern matching the folded names the way `matches` does, `\` being the escape character.
+    #[cfg(feature = "sqlx")]
+    pub(crate) fn like_pattern(&self) -> String {
+        let escape = |c: char| match c {
+            '%' | '_' | '\\' => format!("\\{c}"),
+            c => c.to_string(),
+        };
+        let text = fold_name(&self.text);
+        match self.mode {
+            DaSearchMode::Prefix => format!("{}%", text.chars().map(escape).collect::<String>()),
+            DaSearchMode::Substring => {
+                format!("%{}%", text.chars().map(escape).collect::<String>())
+            }
+            // Any name may be similar, they are scored afterward
+            DaSearchMode::Fuzzy => "%".to_string(),
+        }
+    }
+}
+
+impl DaSearchResult {
+    /// Compares two results in the order of the search: by decreasing score, then by sid.
+    pub fn compare(&self, other: &Self) -> Ordering {
+        let score = |result: &Self| result.score.unwrap_or_default();
+        score(other)
+            .total_cmp(&score(self))
+            .then(self.sid.cmp(&other.sid))
+    }
+
+    /// Whether the result comes after the position of `cursor` in the search.
+    pub fn is_after(&self, cursor: &DaCursor) -> Result<bool, InvalidCursor> {
+        let ordering = match self.score {
+            Some(score) => cursor
+                .last_score()?
+                .total_cmp(&score)
+                .then(self.sid.cmp(&cursor.last_sid())),
+            None => self.sid.cmp(&cursor.last_sid()),
+        };
+        Ok(ordering.is_gt())
+    }
+}
+
+/// The form of a name the searches compare, lowercased the same way for every character.
+/// The SQL backends store it with the name, as SQL `lower()` doesn't lowercase the non-ASCII characters in SQLite.
+pub(crate) fn fold_name(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// The trigrams of a folded name as `pg_trgm` extracts them: the words (runs of alphanumeric characters)
+/// are padded with two spaces in front and one behind, then cut in every sequence of three characters.
+fn trigrams(name: &str) -> BTreeSet<[char; 3]> {
+    let mut trigrams = BTreeSet::new();
+    for word in name.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+        let padded: Vec<char> = format!("  {word} ").chars().collect();
+        trigrams.extend(
+            padded
+                .windows(3)
+                .map(|chars| [chars[0], chars[1], chars[2]]),
+        );
+    }
+    trigrams
+}
+
+/// The share of trigrams two folded names have in common, between 0 and 1, like `pg_trgm`'s `similarity()`.
+pub(crate) fn similarity(a: &str, b: &str) -> f32 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let common = a.intersection(&b).count();
+    common as f32 / (a.len() + b.len() - common) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let query = |text: &str, mode| DaSearchQuery::new(text, mode);
+        assert!(query("Rep", DaSearchMode::Prefix).matches("reports"));
+        assert!(!query("port", DaSearchMode::Prefix).matches("reports"));
+        assert!(query("PORT", DaSearchMode::Substring).matches("reports"));
+        assert!(!query("rpt", DaSearchMode::Substring).matches("reports"));
+        assert!(query("reprots", DaSearchMode::Fuzzy).matches("reports"));
+        assert!(query("Sales Reprot", DaSearchMode::Fuzzy).matches("sales_report"));
+        assert!(!query("rpt", DaSearchMode::Fuzzy).matches("reports"));
+        assert!(!query("", DaSearchMode::Fuzzy).matches("reports"));
+        assert!(query("été", DaSearchMode::Prefix).matches("Été 2024"));
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("reports", "reports"), 1.0);
+        // 4 trigrams in common out of 12: `  r`, ` re`, `rep` and `ts `
+        assert_eq!(similarity("reprots", "reports"), 4.0 / 12.0);
+        assert_eq!(similarity("report", "report 2020"), 7.0 / 12.0);
+        assert_eq!(similarity("report", "archive"), 0.0);
+        assert_eq!(similarity("", "reports"), 0.0);
+        assert_eq!(similarity("a-b", "b a"), 1.0);
+        let query = DaSearchQuery::new("Reprots", DaSearchMode::Fuzzy);
+        assert_eq!(query.score("REPORTS"), Some(4.0 / 12.0));
+        assert_eq!(
+            DaSearchQuery::new("rep", DaSearchMode::Prefix).score("reports"),
+            None
+        );
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[test]
+    fn test_like_pattern() {
+        let query = |text: &str, mode| DaSearchQuery::new(text, mode);
+        assert_eq!(query("Re", DaSearchMode::Prefix).like_pattern(), "re%");
+        assert_eq!(
+            query("50%_", DaSearchMode::Substring).like_pattern(),
+            "%50\\%\\_%"
+        );
+        assert_eq!(query("ab", DaSearchMode::Fuzzy).like_pattern(), "%");
+    }
+}
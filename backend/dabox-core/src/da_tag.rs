@@ -0,0 +1,112 @@
+/// This module contains the tags of the directories.
+/// Tags are free-form labels attached to a directory by the entities that can write to it,
+/// they stay with a directory in the trash and are removed when it is purged.
+use crate::prelude::*;
+use unicode_normalization::UnicodeNormalization;
+
+/// Maximum length of a tag (in characters).
+pub const MAX_TAG_LENGTH: usize = 64;
+
+/// A tag used by the directories of an entity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaTagCount {
+    pub tag: String,
+    /// The number of directories carrying the tag
+    pub count: u64,
+}
+
+/// How the tags of a `DaTagQuery` are combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DaTagMatch {
+    /// The directories carrying every tag
+    #[default]
+    All,
+    /// The directories carrying at least one of the tags
+    Any,
+}
+
+/// The criteria of a listing of tagged directories.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaTagQuery {
+    pub tags: Vec<String>,
+    pub mode: DaTagMatch,
+}
+
+/// Returns the tag as it must be stored: trimmed and NFC normalised.
+///
+/// # Errors
+///
+/// Returns `DaError::InvalidTag` if the tag is empty, longer than `MAX_TAG_LENGTH` or contains a control character or a `,`
+/// (the separator of the tags in the API queries).
+pub fn normalize_tag(tag: &str) -> DaResult<String> {
+    let tag: String = tag.trim().nfc().collect();
+    if tag.is_empty() {
+        return Err(DaError::InvalidTag("the tag is empty".to_string()));
+    }
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(DaError::InvalidTag(format!(
+            "the tag is longer than {MAX_TAG_LENGTH} characters"
+        )));
+    }
+    if let Some(c) = tag.chars().find(|c| c.is_control() || *c == ',') {
+        return Err(DaError::InvalidTag(format!(
+            "the tag contains the forbidden character {c:?}"
+        )));
+    }
+    Ok(tag)
+}
+
+impl DaTagMatch {
+    /// Whether a directory carrying `carried` matches the (normalised) tags of a query, a query without tags matches nothing.
+    pub fn matches(self, tags: &BTreeSet<String>, carried: &BTreeSet<String>) -> bool {
+        match self {
+            Self::All => !tags.is_empty() && tags.is_subset(carried),
+            Self::Any => !tags.is_disjoint(carried),
+        }
+    }
+}
+
+impl DaTagQuery {
+    pub fn new(tags: impl IntoIterator<Item = impl Into<String>>, mode: DaTagMatch) -> Self {
+        Self {
+            tags: tags.into_iter().map(Into::into).collect(),
+            mode,
+        }
+    }
+
+    /// The distinct normalised tags of the query.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DaError::InvalidTag` if one of the tags is invalid (see `normalize_tag`).
+    pub fn normalized_tags(&self) -> DaResult<BTreeSet<String>> {
+        self.tags.iter().map(|tag| normalize_tag(tag)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag("  urgent ").unwrap(), "urgent");
+        assert_eq!(normalize_tag("cafe\u{301}").unwrap(), "caf\u{e9}");
+        let too_long = "a".repeat(MAX_TAG_LENGTH + 1);
+        for invalid in ["", "  ", "a,b", "line\nbreak", too_long.as_str()] {
+            assert!(matches!(
+                normalize_tag(invalid),
+                Err(DaError::InvalidTag(_))
+            ));
+        }
+
+        let carried: BTreeSet<String> = ["a", "b"].map(String::from).into();
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect();
+        assert!(DaTagMatch::All.matches(&tags(&["a", "b"]), &carried));
+        assert!(!DaTagMatch::All.matches(&tags(&["a", "c"]), &carried));
+        assert!(DaTagMatch::Any.matches(&tags(&["a", "c"]), &carried));
+        assert!(!DaTagMatch::All.matches(&tags(&[]), &carried));
+        assert!(!DaTagMatch::Any.matches(&tags(&[]), &carried));
+    }
+}
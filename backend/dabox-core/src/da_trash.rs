@@ -0,0 +1,18 @@
+/// This module contains the trash data structure.
+/// Deleted directories are kept with their descendants and files in the trash of their owner until they are restored or purged.
+use crate::prelude::*;
+
+/// A directory deleted with all its descendants, as listed in the trash of its owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaTrashEntry {
+    /// The unique identifier of the deleted directory (kept on restore)
+    pub sid: DaDirectorySid,
+    /// The name of the deleted directory
+    pub name: String,
+    /// The parent the directory was deleted from (none if it was a root directory)
+    pub parent_sid: Option<DaDirectorySid>,
+    /// When the directory was deleted
+    pub deleted_at: DateTime<Utc>,
+    /// The entity that deleted the directory (not necessarily its owner if it was deleted from a shared directory)
+    pub deleted_by: EntityUid,
+}
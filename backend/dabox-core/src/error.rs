@@ -1,6 +1,7 @@
 use crate::prelude::*;
 
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum DaError {
     #[error(
         "Access denied: {requested_by} try to access a resource that is owned by {resource_owner}"
@@ -11,14 +12,100 @@ pub enum DaError {
     },
     #[error("Directory depth limit exceeded (max: {0})")]
     DirectoryDepthLimitExceeded(u32),
-    #[error("No directory with id {0:?} found")]
+    #[error("No directory with id {0} found")]
     DirectoryNotFound(DaDirectorySid),
+    /// The parent a directory is created, moved or copied into doesn't exist
+    #[error("No parent directory with id {0} found")]
+    ParentNotFound(DaDirectorySid),
+    #[error("Moving directory {0} under {1} would create a cycle")]
+    DirectoryCycle(DaDirectorySid, DaDirectorySid),
+    #[error("The cursor doesn't match the listing")]
+    InvalidCursor(#[from] InvalidCursor),
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+    #[error("A sibling directory is already named {0:?}")]
+    NameConflict(String),
+    #[error("No directory found at {0}")]
+    PathNotFound(String),
+    #[error("Several directories match {0}")]
+    AmbiguousPath(String),
+    #[error("Directory {sid} is at version {actual}, not {expected}")]
+    VersionMismatch {
+        sid: DaDirectorySid,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Invalid batch: {0}")]
+    InvalidBatch(String),
+    /// Nothing of the batch was applied, `source` is the error of the operation at `index`
+    #[error("Operation {index} of the batch failed: {source}")]
+    BatchOperationFailed {
+        index: usize,
+        #[source]
+        source: Box<DaError>,
+    },
+    #[error("Invalid tag: {0}")]
+    InvalidTag(String),
+    #[error("Invalid archive: {0}")]
+    InvalidArchive(String),
+    #[error("No directory with id {0} found in the trash")]
+    TrashEntryNotFound(DaDirectorySid),
+    #[error("No file with id {0} found")]
+    FileNotFound(DaFileSid),
+    #[error("Blob store error: {0}")]
+    BlobStore(#[from] std::io::Error),
     #[error(transparent)]
-    #[cfg(feature = "database")]
+    #[cfg(feature = "sqlx")]
     SqlxError(#[from] sqlx::Error),
     #[error("Failed to apply migrations")]
-    #[cfg(feature = "database")]
+    #[cfg(feature = "sqlx")]
     SqlxMigration(#[from] sqlx::migrate::MigrateError),
 }
 
+impl DaError {
+    /// A stable machine-readable identifier of the error, meant to be exposed to the clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::AccessDenied { .. } => "access_denied",
+            Self::DirectoryDepthLimitExceeded(_) => "directory_depth_limit_exceeded",
+            Self::DirectoryNotFound(_) => "directory_not_found",
+            Self::ParentNotFound(_) => "parent_not_found",
+            Self::DirectoryCycle(..) => "directory_cycle",
+            Self::InvalidCursor(_) => "invalid_cursor",
+            Self::InvalidName(_) => "invalid_name",
+            Self::NameConflict(_) => "name_conflict",
+            Self::PathNotFound(_) => "path_not_found",
+            Self::AmbiguousPath(_) => "ambiguous_path",
+            Self::VersionMismatch { .. } => "version_mismatch",
+            Self::InvalidBatch(_) => "invalid_batch",
+            Self::BatchOperationFailed { source, .. } => source.code(),
+            Self::InvalidTag(_) => "invalid_tag",
+            Self::InvalidArchive(_) => "invalid_archive",
+            Self::TrashEntryNotFound(_) => "trash_entry_not_found",
+            Self::FileNotFound(_) => "file_not_found",
+            Self::BlobStore(_) => "blob_store_error",
+            #[cfg(feature = "sqlx")]
+            Self::SqlxError(_) => "database_error",
+            #[cfg(feature = "sqlx")]
+            Self::SqlxMigration(_) => "database_migration_error",
+        }
+    }
+
+    /// Reports a missing directory as a missing parent, for the lookups of the parent of a created, moved or copied directory.
+    pub(crate) fn for_parent(self) -> Self {
+        match self {
+            Self::DirectoryNotFound(sid) => Self::ParentNotFound(sid),
+            err => err,
+        }
+    }
+
+    /// Wraps the error of the operation at `index` of a batch.
+    pub(crate) fn in_batch(self, index: usize) -> Self {
+        Self::BatchOperationFailed {
+            index,
+            source: Box::new(self),
+        }
+    }
+}
+
 pub type DaResult<T> = Result<T, DaError>;
@@ -1,5 +1,16 @@
+pub mod da_access;
+pub mod da_archive;
+pub mod da_audit;
+pub mod da_batch;
+pub mod da_blob;
 pub mod da_directory;
+pub mod da_events;
+pub mod da_file;
 pub mod da_repository;
+pub mod da_search;
+pub mod da_tag;
+pub mod da_trash;
 pub mod entity;
 pub mod error;
+pub mod pagination;
 pub mod prelude;
@@ -0,0 +1,266 @@
+//! This module contains the types used to paginate listings.
+//! Pages are navigated with an opaque cursor that points right after the last item of the previous page,
+//! so that pages stay consistent while directories are created or deleted.
+use crate::prelude::*;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Number of items in a page if not specified by the client.
+pub const DEFAULT_PAGE_LIMIT: u32 = 100;
+/// Maximum number of items in a page.
+pub const MAX_PAGE_LIMIT: u32 = 1000;
+
+/// A page of a listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaPage<T> {
+    /// The items of the page
+    pub items: Vec<T>,
+    /// The cursor of the next page (none if this is the last page)
+    pub next_cursor: Option<DaCursor>,
+}
+
+/// An opaque position in a listing, serialized as an url-safe string.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct DaCursor {
+    key: CursorKey,
+}
+
+/// The actual content of a cursor: the sort key of the last item of the previous page.
+/// The sid (of a directory, a file or the id of an audit event depending on the listing) is always part of the key
+/// to break ties between items with the same sort value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CursorKey {
+    sid: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    score: Option<f32>,
+}
+
+/// The field a listing is sorted by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DaSortKey {
+    /// Creation order
+    #[default]
+    Sid,
+    Name,
+}
+
+/// The direction a listing is sorted in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DaSortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// The order of a listing, ties are broken by sid in the same direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DaSort {
+    pub key: DaSortKey,
+    pub order: DaSortOrder,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid cursor")]
+pub struct InvalidCursor;
+
+impl DaCursor {
+    /// Creates a cursor pointing right after `sid`.
+    pub fn after(sid: DaDirectorySid) -> Self {
+        Self {
+            key: CursorKey {
+                sid: sid.0,
+                name: None,
+                score: None,
+            },
+        }
+    }
+
+    /// Creates a cursor pointing right after the file `sid`.
+    pub fn after_file(sid: DaFileSid) -> Self {
+        Self {
+            key: CursorKey {
+                sid: sid.0,
+                name: None,
+                score: None,
+            },
+        }
+    }
+
+    /// Creates a cursor pointing right after the audit event `id`.
+    pub fn after_event(id: i64) -> Self {
+        Self {
+            key: CursorKey {
+                sid: id,
+                name: None,
+                score: None,
+            },
+        }
+    }
+
+    /// Creates a cursor pointing right after `dir` in a listing sorted by `sort`.
+    pub fn after_directory(dir: &DaDirectory, sort: DaSort) -> Self {
+        Self {
+            key: CursorKey {
+                sid: dir.sid.0,
+                name: (sort.key == DaSortKey::Name).then(|| dir.name.clone()),
+                score: None,
+            },
+        }
+    }
+
+    /// Creates a cursor pointing right after `result` in the results of a search.
+    pub fn after_search_result(result: &DaSearchResult) -> Self {
+        Self {
+            key: CursorKey {
+                sid: result.sid.0,
+                name: None,
+                score: result.score,
+            },
+        }
+    }
+
+    /// The sid of the last item of the previous page.
+    pub fn last_sid(&self) -> DaDirectorySid {
+        DaDirectorySid(self.key.sid)
+    }
+
+    /// The sid of the last file of the previous page, for file listings.
+    pub fn last_file_sid(&self) -> DaFileSid {
+        DaFileSid(self.key.sid)
+    }
+
+    /// The id of the last event of the previous page, for audit log listings.
+    pub fn last_event_id(&self) -> i64 {
+        self.key.sid
+    }
+
+    /// The name of the last item of the previous page, only set for listings sorted by name.
+    pub fn last_name(&self) -> Result<&str, InvalidCursor> {
+        self.key.name.as_deref().ok_or(InvalidCursor)
+    }
+
+    /// The score of the last result of the previous page, only set for fuzzy searches.
+    pub fn last_score(&self) -> Result<f32, InvalidCursor> {
+        self.key.score.ok_or(InvalidCursor)
+    }
+}
+
+impl DaSort {
+    pub fn new(key: DaSortKey, order: DaSortOrder) -> Self {
+        Self { key, order }
+    }
+
+    /// Compares two directories in the order of the listing.
+    pub fn compare(&self, a: &DaDirectory, b: &DaDirectory) -> std::cmp::Ordering {
+        let ordering = match self.key {
+            DaSortKey::Sid => a.sid.cmp(&b.sid),
+            DaSortKey::Name => a.name.cmp(&b.name).then(a.sid.cmp(&b.sid)),
+        };
+        match self.order {
+            DaSortOrder::Asc => ordering,
+            DaSortOrder::Desc => ordering.reverse(),
+        }
+    }
+
+    /// Whether `dir` comes after the position of `cursor` in the listing.
+    pub fn is_after(&self, dir: &DaDirectory, cursor: &DaCursor) -> Result<bool, InvalidCursor> {
+        let ordering = match self.key {
+            DaSortKey::Sid => dir.sid.cmp(&cursor.last_sid()),
+            DaSortKey::Name => dir
+                .name
+                .as_str()
+                .cmp(cursor.last_name()?)
+                .then(dir.sid.cmp(&cursor.last_sid())),
+        };
+        Ok(match self.order {
+            DaSortOrder::Asc => ordering.is_gt(),
+            DaSortOrder::Desc => ordering.is_lt(),
+        })
+    }
+}
+
+impl<T> DaPage<T> {
+    /// Builds a page from up to `limit + 1` items fetched in listing order,
+    /// the extra item only tells that there is a next page.
+    pub fn from_overfetched(
+        mut items: Vec<T>,
+        limit: u32,
+        cursor_of: impl Fn(&T) -> DaCursor,
+    ) -> Self {
+        let next_cursor = if items.len() > limit as usize {
+            items.truncate(limit as usize);
+            items.last().map(cursor_of)
+        } else {
+            None
+        };
+        Self { items, next_cursor }
+    }
+}
+
+impl Display for DaCursor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let json = serde_json::to_vec(&self.key).map_err(|_| std::fmt::Error)?;
+        write!(f, "{}", URL_SAFE_NO_PAD.encode(json))
+    }
+}
+
+impl FromStr for DaCursor {
+    type Err = InvalidCursor;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = URL_SAFE_NO_PAD.decode(s).map_err(|_| InvalidCursor)?;
+        let key = serde_json::from_slice(&json).map_err(|_| InvalidCursor)?;
+        Ok(Self { key })
+    }
+}
+
+impl From<DaCursor> for String {
+    fn from(cursor: DaCursor) -> Self {
+        cursor.to_string()
+    }
+}
+
+impl TryFrom<String> for DaCursor {
+    type Error = InvalidCursor;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = DaCursor::after(DaDirectorySid(42));
+        let encoded = cursor.to_string();
+        assert_eq!(encoded.parse::<DaCursor>().unwrap(), cursor);
+        assert!("not a cursor".parse::<DaCursor>().is_err());
+        assert!(URL_SAFE_NO_PAD.encode("{}").parse::<DaCursor>().is_err());
+        assert!(cursor.last_name().is_err());
+        assert!(cursor.last_score().is_err());
+
+        // Scores are compared exactly, they must survive the encoding
+        let result = DaSearchResult {
+            sid: DaDirectorySid(7),
+            name: "report 2020".to_string(),
+            parent_sid: None,
+            depth: 0,
+            path: vec!["report 2020".to_string()],
+            score: Some(7.0 / 12.0),
+        };
+        let cursor = DaCursor::after_search_result(&result);
+        let decoded = cursor.to_string().parse::<DaCursor>().unwrap();
+        assert_eq!(decoded.last_score().unwrap(), 7.0 / 12.0);
+        assert_eq!(decoded.last_sid(), DaDirectorySid(7));
+    }
+}
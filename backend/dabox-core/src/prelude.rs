@@ -1,14 +1,31 @@
-pub use crate::da_directory::{DaDirectory, DaDirectorySid};
-pub use crate::da_repository::{DaRepository, MemRepository};
+pub use crate::da_access::{DaGrant, DaPermission};
+pub use crate::da_archive::{DaArchive, DaArchiveEntry, DaImportResult, DaImportStrategy};
+pub use crate::da_audit::{DaAuditEvent, DaAuditFilter, DaAuditOperation};
+pub use crate::da_batch::{DaBatchOperation, DaBatchRef, DaBatchResult};
+pub use crate::da_blob::{DaBlobStore, DaByteStream, LocalBlobStore, MemBlobStore};
+pub use crate::da_directory::{DaAncestor, DaDirectory, DaDirectorySid};
+pub use crate::da_events::{DaChange, DaChangeEvent, DaDeletedSid, DaEventBus};
+pub use crate::da_file::{DaFile, DaFileSid};
+pub use crate::da_repository::{
+    DaRepository, DaRepositoryConfig, DepthLimit, MemRepository, NameRules, SiblingNamePolicy,
+};
+pub use crate::da_search::{DaSearchMode, DaSearchQuery, DaSearchResult};
+pub use crate::da_tag::{DaTagCount, DaTagMatch, DaTagQuery};
+pub use crate::da_trash::DaTrashEntry;
 pub use crate::entity::{Entity, EntityUid};
 pub use crate::error::*;
+pub use crate::pagination::{DaCursor, DaPage, DaSort, DaSortKey, DaSortOrder, InvalidCursor};
 
+pub(crate) use chrono::{DateTime, Utc};
 pub(crate) use futures::Future;
 pub(crate) use futures::StreamExt;
 pub(crate) use serde::{Deserialize, Serialize};
-pub(crate) use std::collections::{BTreeMap, HashMap, HashSet};
+pub(crate) use std::collections::{BTreeMap, BTreeSet, HashMap};
 pub(crate) use std::sync::{atomic::AtomicI64, Arc};
 pub(crate) use tokio::sync::RwLock;
 
 #[cfg(feature = "database")]
 pub use crate::da_repository::PgRepository;
+
+#[cfg(feature = "sqlite")]
+pub use crate::da_repository::SqliteRepository;
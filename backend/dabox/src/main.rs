@@ -1,11 +1,26 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use dabox_api::app::{create_app, AppExt};
+use dabox_api::middlewares::auth::{Authenticator, JwtAuthenticator};
+use dabox_api::routes::file::MaxUploadSize;
+use dabox_core::da_blob::{DaBlobStore, LocalBlobStore};
+#[cfg(not(any(feature = "database", feature = "sqlite")))]
 use dabox_core::da_repository::MemRepository;
-use tracing::info;
+#[cfg(feature = "database")]
+use dabox_core::da_repository::PgRepository;
+#[cfg(all(feature = "sqlite", not(feature = "database")))]
+use dabox_core::da_repository::SqliteRepository;
+use dabox_core::da_repository::{
+    DaRepository, DaRepositoryConfig, DepthLimit, NameRules, SiblingNamePolicy,
+};
+use tracing::{info, warn};
 use tracing_subscriber::{prelude::*, EnvFilter};
 
 const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:3000";
+const DEFAULT_BLOB_STORE_PATH: &str = "blobs";
+/// How often the expired trash entries are looked for.
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 #[tokio::main]
 async fn main() {
@@ -18,22 +33,183 @@ async fn main() {
     let listen_addres =
         std::env::var("LISTEN_ADDRESS").unwrap_or(DEFAULT_LISTEN_ADDRESS.to_string());
 
-    let repository = Arc::new(MemRepository::new());
+    #[cfg(feature = "database")]
+    let repository = Arc::new(
+        PgRepository::connect(
+            &std::env::var("DATABASE_URL")
+                .expect("DATABASE_URL must be set with the database feature"),
+        )
+        .await
+        .unwrap()
+        .with_config(repository_config())
+        .with_blob_store(blob_store().await),
+    );
+
+    // The Postgres backend takes precedence if both database features are enabled
+    #[cfg(all(feature = "sqlite", not(feature = "database")))]
+    let repository = Arc::new(
+        SqliteRepository::connect(
+            &std::env::var("DATABASE_URL")
+                .expect("DATABASE_URL must be set with the sqlite feature"),
+        )
+        .await
+        .unwrap()
+        .with_config(repository_config())
+        .with_blob_store(blob_store().await),
+    );
+
+    #[cfg(not(any(feature = "database", feature = "sqlite")))]
+    let repository = Arc::new(
+        MemRepository::new()
+            .with_config(repository_config())
+            .with_blob_store(blob_store().await),
+    );
 
     #[cfg(feature = "default-dataset")]
-    populate_repository(&repository).await;
+    populate_repository(repository.as_ref()).await;
+
+    tokio::spawn(purge_expired_trash(repository.clone()));
 
     info!(listen_address = listen_addres, "Starting Dabox API");
-    create_app(repository)
+    create_app(repository, authenticator(), max_upload_size())
         .enable_cors()
         .serve(listen_addres)
         .await
         .unwrap();
 }
 
+/// Build the repository configuration from the environment:
+/// - `MAX_DIRECTORY_DEPTH`: default depth limit of the entities (unlimited if unset)
+/// - `MAX_DIRECTORY_DEPTH_PER_ENTITY`: comma separated `uid=depth` list overriding the default limit (ex: `42=8,7=16`)
+/// - `MAX_NAME_LENGTH`: maximum number of characters of the directory and file names (255 if unset)
+/// - `FORBIDDEN_NAME_CHARS`: characters that can't appear in a name, in addition to `/` and `\` (ex: `:*?`)
+/// - `UNIQUE_SIBLING_NAMES`: `case-sensitive` or `case-insensitive` to forbid sibling directories with the same name (allowed if unset)
+/// - `TRASH_RETENTION_DAYS`: number of days the deleted directories are kept in the trash before being purged (kept forever if unset)
+fn repository_config() -> DaRepositoryConfig {
+    let mut depth_limit = DepthLimit::new(std::env::var("MAX_DIRECTORY_DEPTH").ok().map(|value| {
+        value
+            .parse()
+            .expect("MAX_DIRECTORY_DEPTH must be a positive integer")
+    }));
+    if let Ok(value) = std::env::var("MAX_DIRECTORY_DEPTH_PER_ENTITY") {
+        for entry in value.split(',').filter(|entry| !entry.is_empty()) {
+            let (uid, limit) = entry
+                .split_once('=')
+                .and_then(|(uid, limit)| {
+                    Some((uid.trim().parse().ok()?, limit.trim().parse().ok()?))
+                })
+                .expect("MAX_DIRECTORY_DEPTH_PER_ENTITY must be a list of `uid=depth`");
+            depth_limit = depth_limit.with_entity_limit(uid, limit);
+        }
+    }
+    let sibling_names = match std::env::var("UNIQUE_SIBLING_NAMES").as_deref() {
+        Err(_) => SiblingNamePolicy::AllowDuplicates,
+        Ok("case-sensitive") => SiblingNamePolicy::CaseSensitive,
+        Ok("case-insensitive") => SiblingNamePolicy::CaseInsensitive,
+        Ok(_) => panic!("UNIQUE_SIBLING_NAMES must be `case-sensitive` or `case-insensitive`"),
+    };
+    let mut names = NameRules::default();
+    if let Ok(value) = std::env::var("MAX_NAME_LENGTH") {
+        names = names.with_max_length(
+            value
+                .parse()
+                .expect("MAX_NAME_LENGTH must be a positive integer"),
+        );
+    }
+    if let Ok(value) = std::env::var("FORBIDDEN_NAME_CHARS") {
+        names.forbidden_chars.extend(value.chars());
+    }
+    let mut config = DaRepositoryConfig::default()
+        .with_depth_limit(depth_limit)
+        .with_sibling_names(sibling_names)
+        .with_names(names);
+    if let Ok(value) = std::env::var("TRASH_RETENTION_DAYS") {
+        let days: u64 = value
+            .parse()
+            .expect("TRASH_RETENTION_DAYS must be a positive integer");
+        config = config.with_trash_retention(Duration::from_secs(days * 24 * 60 * 60));
+    }
+    config
+}
+
+/// Periodically purge the trash entries older than the retention (does nothing if `TRASH_RETENTION_DAYS` is unset).
+async fn purge_expired_trash<R: DaRepository + 'static>(repository: Arc<R>) {
+    let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repository.purge_expired_trash().await {
+            Ok(0) => {}
+            Ok(purged) => info!(purged, "Purged expired trash entries"),
+            Err(err) => warn!(%err, "Failed to purge the expired trash entries"),
+        }
+    }
+}
+
+/// Open the store of the file contents, a local directory set by `BLOB_STORE_PATH` (`./blobs` by default).
+async fn blob_store() -> Arc<dyn DaBlobStore> {
+    let path = std::env::var("BLOB_STORE_PATH").unwrap_or(DEFAULT_BLOB_STORE_PATH.to_string());
+    Arc::new(
+        LocalBlobStore::open(path)
+            .await
+            .expect("BLOB_STORE_PATH must be a writable directory"),
+    )
+}
+
+/// The maximum size of an uploaded file in bytes, set by `MAX_UPLOAD_SIZE` (1 GiB by default).
+fn max_upload_size() -> MaxUploadSize {
+    std::env::var("MAX_UPLOAD_SIZE")
+        .map(|value| {
+            MaxUploadSize(
+                value
+                    .parse()
+                    .expect("MAX_UPLOAD_SIZE must be a positive integer"),
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Build the authenticator of the API requests from the environment:
+/// - `AUTH_JWT_SECRET`: shared secret of the `HS256` tokens
+/// - `AUTH_JWKS_FILE`: path of a JWKS file holding the public keys of the `RS256` / `ES256` tokens
+/// - `AUTH_UID_CLAIM`: claim holding the entity uid (`sub` by default)
+/// - `AUTH_ISSUER` / `AUTH_AUDIENCE`: expected `iss` / `aud` claims of the tokens (not checked if unset)
+/// - `AUTH_INSECURE_HEADER`: set to `true` to trust the raw `X-Entity-Uid` header instead, for development only
+fn authenticator() -> Authenticator {
+    if std::env::var("AUTH_INSECURE_HEADER").is_ok_and(|value| value == "true") {
+        warn!("AUTH_INSECURE_HEADER is enabled, any client can impersonate any entity");
+        return Authenticator::InsecureHeader;
+    }
+
+    let secret = std::env::var("AUTH_JWT_SECRET").ok();
+    let jwks_file = std::env::var("AUTH_JWKS_FILE").ok();
+    if secret.is_none() && jwks_file.is_none() {
+        panic!("AUTH_JWT_SECRET or AUTH_JWKS_FILE must be set (or AUTH_INSECURE_HEADER=true for development)");
+    }
+
+    let mut authenticator = JwtAuthenticator::new();
+    if let Some(secret) = secret {
+        authenticator = authenticator.with_secret(secret.as_bytes());
+    }
+    if let Some(path) = jwks_file {
+        authenticator = authenticator
+            .with_jwks_file(path)
+            .expect("AUTH_JWKS_FILE must be a valid JWKS file");
+    }
+    if let Ok(claim) = std::env::var("AUTH_UID_CLAIM") {
+        authenticator = authenticator.with_uid_claim(claim);
+    }
+    if let Ok(issuer) = std::env::var("AUTH_ISSUER") {
+        authenticator = authenticator.with_issuer(issuer);
+    }
+    if let Ok(audience) = std::env::var("AUTH_AUDIENCE") {
+        authenticator = authenticator.with_audience(audience);
+    }
+    Authenticator::jwt(authenticator)
+}
+
 /// Populate the repository with a default dataset
 #[cfg(feature = "default-dataset")]
-async fn populate_repository(repository: &MemRepository) {
+async fn populate_repository<R: dabox_core::prelude::DaRepository>(repository: &R) {
     use dabox_core::{
         entity::EntityUid,
         prelude::{DaDirectorySid, DaRepository},
@@ -45,21 +221,26 @@ async fn populate_repository(repository: &MemRepository) {
     }
 
     #[async_recursion::async_recursion]
-    async fn populate_bucket(
+    async fn populate_bucket<R>(
         uid: EntityUid,
-        repository: &MemRepository,
+        repository: &R,
+        name: &str,
         place_holders: PlaceHolder,
         parent_sid: Option<DaDirectorySid>,
-    ) {
+    ) where
+        R: DaRepository,
+    {
         let dir = repository
-            .create_directory(uid, "Empty", parent_sid)
+            .create_directory(uid, name, parent_sid)
             .await
             .unwrap();
         match place_holders {
             PlaceHolder::Empty => {}
             PlaceHolder::WithChildren(children) => {
-                for child in children {
-                    populate_bucket(uid, repository, child, Some(dir.sid)).await;
+                // Siblings are named uniquely so the dataset also loads with UNIQUE_SIBLING_NAMES
+                for (idx, child) in children.into_iter().enumerate() {
+                    let name = format!("Empty {idx}");
+                    populate_bucket(uid, repository, &name, child, Some(dir.sid)).await;
                 }
             }
         }
@@ -68,6 +249,7 @@ async fn populate_repository(repository: &MemRepository) {
     populate_bucket(
         0,
         repository,
+        "Empty",
         PlaceHolder::WithChildren(vec![
             PlaceHolder::Empty,
             PlaceHolder::WithChildren((0..5).map(|_| PlaceHolder::Empty).collect()),
@@ -82,6 +264,7 @@ async fn populate_repository(repository: &MemRepository) {
     populate_bucket(
         42,
         repository,
+        "Empty",
         PlaceHolder::WithChildren(vec![
             PlaceHolder::Empty,
             PlaceHolder::WithChildren((0..100000).map(|_| PlaceHolder::Empty).collect()),
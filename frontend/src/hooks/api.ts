@@ -8,6 +8,121 @@ export type DaDirectory = {
     name: string;
     parent?: number;
     children: DaDirectory[];
+    depth: number;
+    /// Set when `children` was not loaded because of the requested depth
+    has_unloaded_children: boolean;
+    /// RFC 3339 timestamps
+    created_at: string;
+    updated_at: string;
+    /// Incremented with `updated_at`, sent back as `ifMatch` to detect concurrent updates
+    version: number;
+    created_by: number;
+    /// In alphabetical order
+    tags: string[];
+};
+
+export type DaAncestor = {
+    sid: number;
+    name: string;
+};
+
+export type DaFile = {
+    sid: number;
+    directory_sid: number;
+    name: string;
+    /// Size of the content in bytes
+    size: number;
+    content_type: string;
+    /// Hex encoded SHA-256 of the content
+    content_hash: string;
+    created_at: string;
+    updated_at: string;
+    created_by: number;
+};
+
+export type DaTrashEntry = {
+    sid: number;
+    name: string;
+    /// The parent the directory was deleted from
+    parent_sid?: number;
+    deleted_at: string;
+    deleted_by: number;
+};
+
+export type DaAuditOperation =
+    | "create_directory"
+    | "rename_directory"
+    | "move_directory"
+    | "copy_directory"
+    | "delete_directory"
+    | "restore_directory"
+    | "purge_directory"
+    | "grant_access"
+    | "revoke_access"
+    | "tag_directory"
+    | "untag_directory"
+    | "create_file"
+    | "delete_file";
+
+export type DaAuditEvent = {
+    id: number;
+    at: string;
+    /// Null for the changes made by the server (ex: expired trash)
+    entity: number | null;
+    operation: DaAuditOperation;
+    sid: number;
+    file_sid: number | null;
+    /// The changed values before and after the change
+    old_value: Record<string, unknown> | null;
+    new_value: Record<string, unknown> | null;
+};
+
+export type DaAuditFilter = {
+    sid?: number;
+    /// RFC 3339 timestamps
+    since?: string;
+    until?: string;
+};
+
+export type DaChangeEvent = {
+    sid: number;
+    owner: number;
+    changed_by: number;
+    at: string;
+} & (
+    | { type: "created"; name: string; parent_sid: number | null }
+    | { type: "renamed"; name: string }
+    | {
+        type: "moved";
+        old_parent_sid: number | null;
+        parent_sid: number | null;
+    }
+    | { type: "deleted"; parent_sid: number | null }
+);
+
+export type DaPage<T> = {
+    items: T[];
+    /// Opaque cursor of the next page (null on the last page)
+    next_cursor: string | null;
+};
+
+export type DaSearchMode = "prefix" | "substring" | "fuzzy";
+
+export type DaSearchResult = {
+    sid: number;
+    name: string;
+    parent_sid?: number;
+    depth: number;
+    /// Names from the root down to the directory, as accepted by `getDirectoryByPath`
+    path: string[];
+};
+
+export type DaTagMatch = "all" | "any";
+
+export type DaTagCount = {
+    tag: string;
+    /// Number of directories carrying the tag
+    count: number;
 };
 
 export type PostDirectoryRequest = {
@@ -19,6 +134,48 @@ export type PutDirectoryRequest = {
     name: string;
 };
 
+export type CopyDirectoryRequest = {
+    parent?: number;
+    name?: string;
+};
+
+/// The serial id of an existing directory, or the `temp_id` of a directory created earlier in the batch
+export type DaBatchRef = number | string;
+
+export type DaBatchOperation =
+    | { op: "create"; temp_id: string; name: string; parent?: DaBatchRef }
+    | { op: "rename"; sid: DaBatchRef; name: string }
+    | { op: "move"; sid: DaBatchRef; parent?: DaBatchRef }
+    | { op: "delete"; sid: DaBatchRef };
+
+export type DaBatchResult = {
+    /// Serial ids of the created directories, by `temp_id`
+    created: Record<string, number>;
+};
+
+export type DaArchiveEntry = {
+    /// Only meaningful within the archive
+    sid: number;
+    parent_sid: number | null;
+    name: string;
+};
+
+export type DaArchive = {
+    version: number;
+    exported_at: string;
+    /// Every directory comes after its parent
+    directories: DaArchiveEntry[];
+};
+
+export type DaImportStrategy = "merge" | "skip" | "overwrite";
+
+export type DaImportResult = {
+    /// New serial ids by serial id in the archive
+    sids: Record<string, number>;
+    /// Serial ids in the archive of the skipped directories
+    skipped: number[];
+};
+
 export enum ApiErrorType {
     Forbidden,
     NotFound,
@@ -29,6 +186,18 @@ export type ApiError = {
     kind: ApiErrorType;
     status: number;
     message: string;
+    /// Machine-readable error code of the problem details (ex: `directory_not_found`)
+    code?: string;
+    /// Index of the failed operation of a batch
+    operation?: number;
+};
+
+type ProblemDetails = {
+    title: string;
+    status: number;
+    detail: string;
+    code: string;
+    operation?: number;
 };
 
 class ApiClient {
@@ -38,15 +207,21 @@ class ApiClient {
         this.userId = Math.abs(userId);
     }
 
+    private headers(ifMatch?: number): Record<string, string> {
+        const headers: Record<string, string> = {
+            "Content-Type": "application/json",
+            "X-Entity-Uid": this.userId.toString(),
+        };
+        if (ifMatch != null) headers["If-Match"] = `"${ifMatch}"`;
+        return headers;
+    }
+
     public async fetchApi<T>(
         path: string,
         custom_options: RequestInit = {},
     ) {
         const response = await fetch(`${API_URL}${path}`, {
-            headers: {
-                "Content-Type": "application/json",
-                "X-Entity-Uid": this.userId.toString(),
-            },
+            headers: this.headers(),
             ...custom_options,
         });
 
@@ -64,10 +239,15 @@ class ApiClient {
                     break;
             }
 
+            const problem = await response.json().catch(() => null) as
+                | ProblemDetails
+                | null;
             throw {
                 kind,
                 status: response.status,
-                message: response.statusText,
+                message: problem?.detail ?? response.statusText,
+                code: problem?.code,
+                operation: problem?.operation,
             } as ApiError;
         }
         if (response.headers.get("Content-Length") == "0") {
@@ -76,8 +256,105 @@ class ApiClient {
         return response.json() as T;
     }
 
-    public getDirectory(id: number) {
-        return this.fetchApi<DaDirectory>(`/directory/${id}`, {
+    public getDirectory(id: number, depth?: number) {
+        const query = depth != null ? `?depth=${depth}` : "";
+        return this.fetchApi<DaDirectory>(`/directory/${id}${query}`, {
+            method: "GET",
+        });
+    }
+
+    public getDirectoryByPath(path: string[], depth?: number) {
+        const query = depth != null ? `?depth=${depth}` : "";
+        const encoded = path.map(encodeURIComponent).join("/");
+        return this.fetchApi<DaDirectory>(`/path/${encoded}${query}`, {
+            method: "GET",
+        });
+    }
+
+    public listChildren(id: number, cursor?: string, limit?: number) {
+        const query = new URLSearchParams();
+        if (cursor != null) query.set("cursor", cursor);
+        if (limit != null) query.set("limit", limit.toString());
+        return this.fetchApi<DaPage<DaDirectory>>(
+            `/directory/${id}/children?${query}`,
+            { method: "GET" },
+        );
+    }
+
+    /// The ancestors of a directory from the root down, for breadcrumbs
+    public listAncestors(id: number) {
+        return this.fetchApi<DaAncestor[]>(`/directory/${id}/ancestors`, {
+            method: "GET",
+        });
+    }
+
+    /// Searches the directories of the user by name, `scope` limits the search to the descendants of a directory
+    public searchDirectories(
+        q: string,
+        mode?: DaSearchMode,
+        scope?: number,
+        cursor?: string,
+        limit?: number,
+    ) {
+        const query = new URLSearchParams({ q });
+        if (mode != null) query.set("mode", mode);
+        if (scope != null) query.set("scope", scope.toString());
+        if (cursor != null) query.set("cursor", cursor);
+        if (limit != null) query.set("limit", limit.toString());
+        return this.fetchApi<DaPage<DaSearchResult>>(`/search?${query}`, {
+            method: "GET",
+        });
+    }
+
+    /// Returns the tags of the directory
+    public addTag(id: number, tag: string) {
+        return this.fetchApi<string[]>(
+            `/directory/${id}/tags/${encodeURIComponent(tag)}`,
+            { method: "PUT" },
+        );
+    }
+
+    /// Returns the remaining tags of the directory
+    public removeTag(id: number, tag: string) {
+        return this.fetchApi<string[]>(
+            `/directory/${id}/tags/${encodeURIComponent(tag)}`,
+            { method: "DELETE" },
+        );
+    }
+
+    public listTags() {
+        return this.fetchApi<DaTagCount[]>("/tags", { method: "GET" });
+    }
+
+    /// The directories carrying all (or any of) the tags, without their children
+    public listTaggedDirectories(
+        tags: string[],
+        match?: DaTagMatch,
+        cursor?: string,
+        limit?: number,
+    ) {
+        const query = new URLSearchParams({ tags: tags.join(",") });
+        if (match != null) query.set("match", match);
+        if (cursor != null) query.set("cursor", cursor);
+        if (limit != null) query.set("limit", limit.toString());
+        return this.fetchApi<DaPage<DaDirectory>>(
+            `/tags/directories?${query}`,
+            { method: "GET" },
+        );
+    }
+
+    public listRootDirectories(
+        sort?: "sid" | "name",
+        order?: "asc" | "desc",
+        cursor?: string,
+        limit?: number,
+    ) {
+        const query = new URLSearchParams();
+        if (sort != null) query.set("sort", sort);
+        if (order != null) query.set("order", order);
+        if (cursor != null) query.set("cursor", cursor);
+        if (limit != null) query.set("limit", limit.toString());
+        return this.fetchApi<DaPage<DaDirectory>>(`/directory?${query}`, {
             method: "GET",
         });
     }
@@ -89,15 +366,161 @@ class ApiClient {
         });
     }
 
-    public putDirectory(sid: number, request: PutDirectoryRequest) {
+    /// With `ifMatch` (the `version` of the directory last read), the request fails with a 412 `version_mismatch`
+    /// if the directory was updated in the meantime
+    public putDirectory(
+        sid: number,
+        request: PutDirectoryRequest,
+        ifMatch?: number,
+    ) {
         return this.fetchApi<DaDirectory>(`/directory/${sid}`, {
             method: "PUT",
+            headers: this.headers(ifMatch),
             body: JSON.stringify(request),
         });
     }
 
-    public deleteDirectory(sid: number) {
-        return this.fetchApi(`/directory/${sid}`, { method: "DELETE" });
+    public deleteDirectory(sid: number, ifMatch?: number) {
+        return this.fetchApi(`/directory/${sid}`, {
+            method: "DELETE",
+            headers: this.headers(ifMatch),
+        });
+    }
+
+    public copyDirectory(sid: number, request: CopyDirectoryRequest) {
+        return this.fetchApi<DaDirectory>(`/directory/${sid}/copy`, {
+            method: "POST",
+            body: JSON.stringify(request),
+        });
+    }
+
+    /// Applies the operations in order, all or none
+    public applyBatch(operations: DaBatchOperation[]) {
+        return this.fetchApi<DaBatchResult>(`/batch`, {
+            method: "POST",
+            body: JSON.stringify({ operations }),
+        });
+    }
+
+    /// Exports a directory and its descendants, or every directory of the user without `sid`
+    public exportArchive(sid?: number) {
+        const query = new URLSearchParams();
+        if (sid != null) query.set("sid", sid.toString());
+        return this.fetchApi<DaArchive>(`/export?${query}`, {
+            method: "GET",
+        });
+    }
+
+    /// Re-creates the directories of an archive under `parent`, all or none
+    public importArchive(
+        archive: DaArchive,
+        parent?: number,
+        strategy?: DaImportStrategy,
+    ) {
+        const query = new URLSearchParams();
+        if (parent != null) query.set("parent", parent.toString());
+        if (strategy != null) query.set("strategy", strategy);
+        return this.fetchApi<DaImportResult>(`/import?${query}`, {
+            method: "POST",
+            body: JSON.stringify(archive),
+        });
+    }
+
+    public listFiles(id: number, cursor?: string, limit?: number) {
+        const query = new URLSearchParams();
+        if (cursor != null) query.set("cursor", cursor);
+        if (limit != null) query.set("limit", limit.toString());
+        return this.fetchApi<DaPage<DaFile>>(
+            `/directory/${id}/files?${query}`,
+            { method: "GET" },
+        );
+    }
+
+    public deleteFile(sid: number) {
+        return this.fetchApi(`/file/${sid}`, { method: "DELETE" });
+    }
+
+    public listTrash(cursor?: string, limit?: number) {
+        const query = new URLSearchParams();
+        if (cursor != null) query.set("cursor", cursor);
+        if (limit != null) query.set("limit", limit.toString());
+        return this.fetchApi<DaPage<DaTrashEntry>>(`/trash?${query}`, {
+            method: "GET",
+        });
+    }
+
+    public restoreDirectory(sid: number) {
+        return this.fetchApi<DaDirectory>(`/trash/${sid}/restore`, {
+            method: "POST",
+        });
+    }
+
+    public purgeDirectory(sid: number) {
+        return this.fetchApi(`/trash/${sid}`, { method: "DELETE" });
+    }
+
+    /// Streams the changes the user can see until `signal` is aborted.
+    /// `onLagged` is called when changes were missed, the displayed directories should then be re-fetched.
+    public async subscribeEvents(
+        onChange: (event: DaChangeEvent) => void,
+        onLagged: () => void,
+        signal: AbortSignal,
+    ) {
+        // `EventSource` can't send the entity header, the stream is read by hand
+        const response = await fetch(`${API_URL}/events`, {
+            headers: { "X-Entity-Uid": this.userId.toString() },
+            signal,
+        });
+        if (!response.ok || response.body == null) {
+            throw {
+                kind: ApiErrorType.InternalServerError,
+                status: response.status,
+                message: response.statusText,
+            } as ApiError;
+        }
+        const reader = response.body
+            .pipeThrough(new TextDecoderStream())
+            .getReader();
+        let buffer = "";
+        while (true) {
+            const { value, done } = await reader.read();
+            if (done) break;
+            buffer += value;
+            let end;
+            while ((end = buffer.indexOf("\n\n")) >= 0) {
+                const lines = buffer.slice(0, end).split("\n");
+                buffer = buffer.slice(end + 2);
+                const name = lines
+                    .find((line) => line.startsWith("event:"))
+                    ?.slice(6)
+                    .trim();
+                const data = lines
+                    .filter((line) => line.startsWith("data:"))
+                    .map((line) => line.slice(5).trim())
+                    .join("\n");
+                if (name === "lagged") {
+                    onLagged();
+                } else if (data) {
+                    onChange(JSON.parse(data) as DaChangeEvent);
+                }
+            }
+        }
+    }
+
+    public listAuditEvents(
+        filter: DaAuditFilter = {},
+        cursor?: string,
+        limit?: number,
+    ) {
+        const query = new URLSearchParams();
+        if (filter.sid != null) query.set("sid", filter.sid.toString());
+        if (filter.since != null) query.set("since", filter.since);
+        if (filter.until != null) query.set("until", filter.until);
+        if (cursor != null) query.set("cursor", cursor);
+        if (limit != null) query.set("limit", limit.toString());
+        return this.fetchApi<DaPage<DaAuditEvent>>(`/audit?${query}`, {
+            method: "GET",
+        });
     }
 }
 
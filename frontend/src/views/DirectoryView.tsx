@@ -46,7 +46,15 @@ function DirectoryView() {
         if (api == null) {
             return;
         }
-        api.getDirectory(0)
+        // The first root directory of the user is displayed
+        api.listRootDirectories("sid", "asc", undefined, 1)
+            .then((page) => {
+                const root = page?.items[0];
+                if (root == null) {
+                    throw { kind: ApiErrorType.NotFound } as ApiError;
+                }
+                return api.getDirectory(root.sid);
+            })
             .then((directory: DaDirectory | null) => {
                 if (directory) {
                     setRootDirectory(directory);